@@ -9,6 +9,27 @@
 // The length of a secret
 #define V_SECRET_KEY_LENGTH 32
 
+// The length of a symmetric encryption key
+#define V_ENCRYPTION_KEY_LENGTH 32
+
+// Leaf flag, compress the leaf using LZ4 if it makes it smaller
+#define V_LZ4_COMPRESSED 1073741824
+
+// Leaf flag, compress the leaf using Snappy if it makes it smaller
+#define V_SNAPPY_COMPRESSED 536870912
+
+// Leaf flag, compress the leaf using Brotli if it makes it smaller
+#define V_BROTLI_COMPRESSED 268435456
+
+// Leaf flag, compress the leaf using Zstd if it makes it smaller
+#define V_ZSTD_COMPRESSED 67108864
+
+// Leaf flag, sign the leaf
+#define V_SIGNED_FLAG 134217728
+
+// Leaf flag, encrypt the leaf
+#define V_ENCRYPTED_FLAG 33554432
+
 // One parameter passed to a function was NULL
 #define E_PARAMETER_IS_NULL -1
 
@@ -39,7 +60,7 @@
 // Generic cryptographic error, signature verification failed or otherwise
 #define E_LEAF_ID_TOO_LONG -10
 
-// Verifying and Decryption Key
+// Verifying Key
 typedef void v_verifying_key;
 
 // An Archive instance, bound to either a file or a buffer
@@ -63,7 +84,7 @@ typedef struct v_resource {
 typedef void v_builder_ctx;
 
 // callback for each newly created leaf node, use `userdata` to pass extra data
-typedef void (*v_builder_callback)(void *userdata, const char *id, uintptr_t id_len, const char *bytes, uintptr_t len, uint64_t location);
+typedef void (*v_builder_callback)(void *userdata, const char *id, uintptr_t id_len, uint64_t len, uint64_t location);
 
 // The version of the library
 uint16_t version(void);
@@ -75,10 +96,10 @@ v_verifying_key *new_verifying_key(const uint8_t (*vk_bytes)[V_VERIFYING_KEY_LEN
 void free_verifying_key(v_verifying_key *config);
 
 // Create a new archive from a file
-v_archive *new_archive_from_file(const char *path, const v_verifying_key *config, int32_t *error_p);
+v_archive *new_archive_from_file(const char *path, const v_verifying_key *config, const uint8_t (*dk_bytes)[V_ENCRYPTION_KEY_LENGTH], int32_t *error_p);
 
 // Create a new archive from a buffer
-v_archive *new_archive_from_buffer(const v_verifying_key *config, const uint8_t *data, uintptr_t len, int32_t *error_p);
+v_archive *new_archive_from_buffer(const v_verifying_key *config, const uint8_t (*dk_bytes)[V_ENCRYPTION_KEY_LENGTH], const uint8_t *data, uintptr_t len, int32_t *error_p);
 
 void free_archive(v_archive *archive);
 
@@ -96,7 +117,7 @@ struct v_resource *archive_fetch_resource_locked(const v_archive *archive, const
 void free_resource(struct v_resource *resource);
 
 // Create new Builder Context
-v_builder_ctx *new_builder_ctx(const uint8_t (*sk_bytes)[V_SECRET_KEY_LENGTH], uint32_t flags);
+v_builder_ctx *new_builder_ctx(const uint8_t (*sk_bytes)[V_SECRET_KEY_LENGTH], const uint8_t (*ek_bytes)[V_ENCRYPTION_KEY_LENGTH], uint32_t flags);
 
 // free memory bound by `new_builder_ctx`
 void free_builder_ctx(v_builder_ctx *ctx);
@@ -1,53 +1,68 @@
-#![deny(clippy::from_raw_with_void_ptr)]
-#![allow(non_camel_case_types)]
-#![allow(clippy::not_unsafe_ptr_arg_deref)]
-
-use std::*;
-
-mod errors;
-pub mod reader;
-pub mod writer;
-
-// TODO: Document usage
-
-/// A wrapper combining `fs::File` and `io::Cursor`, for static access over the C boundary.
-/// Allowing both inner buffers and files to be used for data.
-pub(crate) enum DataSource {
-	File(fs::File),
-	Buffer(io::Cursor<&'static [u8]>),
-}
-
-impl io::Read for DataSource {
-	fn read(
-		&mut self,
-		buf: &mut [u8],
-	) -> io::Result<usize> {
-		match self {
-			DataSource::File(f) => f.read(buf),
-			DataSource::Buffer(b) => b.read(buf),
-		}
-	}
-}
-
-impl io::Seek for DataSource {
-	fn seek(
-		&mut self,
-		pos: io::SeekFrom,
-	) -> io::Result<u64> {
-		match self {
-			DataSource::File(f) => f.seek(pos),
-			DataSource::Buffer(b) => b.seek(pos),
-		}
-	}
-}
-
-/// The version of the library
-#[no_mangle]
-pub extern "C" fn version() -> u16 {
-	vach::VERSION
-}
-
-/// The length of a public key
-pub const V_VERIFYING_KEY_LENGTH: usize = 32;
-/// The length of a secret
-pub const V_SECRET_KEY_LENGTH: usize = 32;
+#![deny(clippy::from_raw_with_void_ptr)]
+#![allow(non_camel_case_types)]
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+use std::*;
+
+mod errors;
+pub mod reader;
+pub mod writer;
+
+// TODO: Document usage
+
+/// A wrapper combining `fs::File` and `io::Cursor`, for static access over the C boundary.
+/// Allowing both inner buffers and files to be used for data.
+pub(crate) enum DataSource {
+	File(fs::File),
+	Buffer(io::Cursor<&'static [u8]>),
+}
+
+impl io::Read for DataSource {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		match self {
+			DataSource::File(f) => f.read(buf),
+			DataSource::Buffer(b) => b.read(buf),
+		}
+	}
+}
+
+impl io::Seek for DataSource {
+	fn seek(
+		&mut self,
+		pos: io::SeekFrom,
+	) -> io::Result<u64> {
+		match self {
+			DataSource::File(f) => f.seek(pos),
+			DataSource::Buffer(b) => b.seek(pos),
+		}
+	}
+}
+
+/// The version of the library
+#[no_mangle]
+pub extern "C" fn version() -> u16 {
+	vach::VERSION
+}
+
+/// The length of a public key
+pub const V_VERIFYING_KEY_LENGTH: usize = 32;
+/// The length of a secret
+pub const V_SECRET_KEY_LENGTH: usize = 32;
+/// The length of a symmetric encryption key
+pub const V_ENCRYPTION_KEY_LENGTH: usize = 32;
+
+/// Leaf flag, compress the leaf using LZ4 if it makes it smaller
+pub const V_LZ4_COMPRESSED: u32 = 0x4000_0000;
+/// Leaf flag, compress the leaf using Snappy if it makes it smaller
+pub const V_SNAPPY_COMPRESSED: u32 = 0x2000_0000;
+/// Leaf flag, compress the leaf using Brotli if it makes it smaller
+pub const V_BROTLI_COMPRESSED: u32 = 0x1000_0000;
+/// Leaf flag, compress the leaf using Zstd if it makes it smaller
+pub const V_ZSTD_COMPRESSED: u32 = 0x0400_0000;
+/// Leaf flag, sign the leaf
+pub const V_SIGNED_FLAG: u32 = 0x0800_0000;
+/// Leaf flag, encrypt the leaf
+pub const V_ENCRYPTED_FLAG: u32 = 0x0200_0000;
@@ -14,7 +14,7 @@ int main(int argc, char **argv) {
   int32_t error_p = 0;
 
   // use default archive_config
-  v_archive *archive = new_archive_from_file(path, NULL, &error_p);
+  v_archive *archive = new_archive_from_file(path, NULL, NULL, &error_p);
   if (error_p != 0) {
     printf("Error: %d\n", error_p);
   }
@@ -4,9 +4,10 @@
 #include <stdio.h>
 #include <stdlib.h>
 
-void callback(void *userdata, const char *id, uintptr_t id_len,
-              const char *data, uintptr_t len, uint64_t location) {
-  printf("Processed Leaf: ID=%s, Len=%lu, Location=%llu\n", id, len, location);
+void callback(void *userdata, const char *id, uintptr_t id_len, uint64_t len,
+              uint64_t location) {
+  printf("Processed Leaf: ID=%.*s, Len=%llu, Location=%llu\n", (int)id_len, id,
+         len, location);
 }
 
 int main(int argc, char **argv) {
@@ -21,7 +22,7 @@ int main(int argc, char **argv) {
 
   // allocate space for leaves, one leaf per file
   int paths_count = argc - 2;
-  v_builder_ctx *ctx = new_builder_ctx(NULL, 0);
+  v_builder_ctx *ctx = new_builder_ctx(NULL, NULL, 0);
 
   // initialize leaves
   char *id_buf = calloc(256, sizeof(char));
@@ -1,152 +1,236 @@
-use clap::{Parser, Subcommand, ValueEnum};
-use std::{path::PathBuf, thread};
-
-use crate::commands::{self, CommandTrait};
-
-#[derive(Debug, Clone, ValueEnum)]
-pub enum SortSetting {
-	Alphabetical,
-	AlphabeticalReversed,
-	SizeAscending,
-	SizeDescending,
-}
-
-#[derive(Debug, Clone, ValueEnum)]
-pub enum CompressModeSetting {
-	Always,
-	Never,
-	Auto,
-}
-
-#[derive(Debug, Clone, ValueEnum)]
-pub enum CompressionAlgorithmSetting {
-	LZ4,
-	Snappy,
-	Brotli,
-}
-
-#[derive(Parser)]
-#[command(name = "vach-cli")]
-#[command(version, author, about, long_about = None)]
-#[command(next_line_help = true)]
-pub struct CommandLine {
-	#[command(subcommand)]
-	/// What class of operation do you fancy?
-	pub command: Command,
-}
-
-#[derive(Subcommand)]
-pub enum Command {
-	/// Unpack an archive to the filesystem
-	#[command(version = commands::unpack::Subcommand::version())]
-	Unpack {
-		/// Path to file to unpack
-		#[arg(short, long, value_name = "FILE")]
-		input: PathBuf,
-		/// Directory to unpack to
-		#[arg(short, long, value_name = "DIR")]
-		output: Option<PathBuf>,
-		/// Path to keypair to use for cryptographic operations
-		#[arg(short, long, value_name = "FILE")]
-		keypair: Option<PathBuf>,
-		/// Path to public key to use for cryptographic operations
-		#[arg(short, long, value_name = "FILE")]
-		public_key: Option<PathBuf>,
-		/// Number of threads to spawn during unpacking, prefer using one thread for small archives
-		#[arg(short, long, default_value_t = thread::available_parallelism().unwrap().get(), value_name = "THREADS")]
-		jobs: usize,
-		/// How may entries to process per thread, [default: archive.entries.count / jobs]
-		#[arg(short, long, value_name = "BATCH")]
-		chunks_size: Option<usize>,
-	},
-	/// Unpacks a resource and writes to stdout
-	#[command(version = commands::pipe::Subcommand::version())]
-	Pipe {
-		/// Path to file to unpack
-		#[arg(short, long, value_name = "FILE")]
-		input: PathBuf,
-		/// The `id` of the resource to extract
-		#[arg(short, long, value_name = "ID")]
-		resource: String,
-		/// Path to keypair to use for cryptographic operations
-		#[arg(short, long, value_name = "FILE")]
-		keypair: Option<PathBuf>,
-		/// Path to public key to use for cryptographic operations
-		#[arg(short, long, value_name = "FILE")]
-		public_key: Option<PathBuf>,
-	},
-	/// List metadata and entries in an archive,
-	#[command(version = commands::list::Subcommand::version())]
-	List {
-		/// Path to file to list
-		#[arg(short, long, value_name = "FILE")]
-		input: PathBuf,
-		/// How to sort the listed entries
-		#[arg(short, long, value_name = "SORT", value_enum)]
-		sort: Option<SortSetting>,
-	},
-	/// Check an input file is a valid .vach archive
-	#[command(version = commands::verify::Subcommand::version())]
-	Verify {
-		/// Path to file to verify
-		#[arg(value_name = "FILE")]
-		input: PathBuf,
-	},
-	/// Generate a keypair (verifying & signing key)
-	#[command(name = "keypair")]
-	#[command(version = commands::keypair::Subcommand::version())]
-	GenKeypair {
-		/// Path to output keypair files
-		#[arg(short, long, default_value_t = String::from("keypair.kp"), value_name = "PATH")]
-		output: String,
-		/// Whether to output keypair as separate signing and verifying keys
-		#[arg(short, long, default_value_t = true)]
-		split_key: bool,
-	},
-	/// Pack some files into a .vach archive
-	#[command(version = commands::pack::Subcommand::version())]
-	Pack {
-		/// Input files to include in the archive
-		#[arg(short, long, value_name = "FILES", num_args=1..)]
-		inputs: Option<Vec<PathBuf>>,
-		/// New archive will be output to this path
-		#[arg(short, long, value_name = "PATH")]
-		output: PathBuf,
-		/// Includes files from these directories, non-recursively
-		#[arg(short, long, num_args=1..)]
-		directories: Option<Vec<PathBuf>>,
-		/// Includes files from these directories, recursively
-		#[arg(short, long = "recursive", value_name = "DIRECTORIES", num_args=1..)]
-		recursive_directories: Option<Vec<PathBuf>>,
-		/// Input files to include in the archive
-		#[arg(short = 'x', long, value_name = "PATHS", num_args=1..)]
-		exclude: Option<Vec<PathBuf>>,
-		/// Compression Mode for entries, `Auto` picks the better on a per-entry basis
-		#[arg(short, long = "c-mode", value_name = "MODE", value_enum)]
-		compress_mode: Option<CompressModeSetting>,
-		/// Compression algorithm to use for entries
-		#[arg(short = 'a', long = "c-algo", value_name = "ALGO", value_enum)]
-		compression_algorithm: Option<CompressionAlgorithmSetting>,
-		/// Path to keypair to use for cryptographic operations
-		#[arg(short, long, value_name = "FILE")]
-		keypair: Option<PathBuf>,
-		/// Path to private key to use for cryptographic operations
-		#[arg(short = 'p', long, value_name = "FILE")]
-		private_key: Option<PathBuf>,
-		/// Encrypts the data using the provided keypair, using AesGcm256
-		#[arg(short, long)]
-		encrypt: bool,
-		/// Whether to sign entries and include signatures in the header
-		#[arg(short, long)]
-		sign: bool,
-		/// A simple tag set in the header, can be used as a version eg
-		#[arg(short, long)]
-		tag: Option<u8>,
-		/// Flags to include in header section of archive
-		#[arg(short, long)]
-		flags: Option<u32>,
-		/// Number of threads to spawn during packing
-		#[arg(short, long, default_value_t = thread::available_parallelism().unwrap().get(), value_name = "THREADS")]
-		jobs: usize,
-	},
-}
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, thread};
+
+use crate::commands::{self, CommandTrait};
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum SortSetting {
+	Alphabetical,
+	AlphabeticalReversed,
+	SizeAscending,
+	SizeDescending,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum CompressModeSetting {
+	Always,
+	Never,
+	Auto,
+	Best,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum CompressionAlgorithmSetting {
+	LZ4,
+	Snappy,
+	Brotli,
+	Zstd,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum AttributeSetting {
+	Mode,
+	Mtime,
+	Symlinks,
+}
+
+#[derive(Parser)]
+#[command(name = "vach-cli")]
+#[command(version, author, about, long_about = None)]
+#[command(next_line_help = true)]
+pub struct CommandLine {
+	#[command(subcommand)]
+	/// What class of operation do you fancy?
+	pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+	/// Unpack an archive to the filesystem
+	#[command(version = commands::unpack::Subcommand::version())]
+	Unpack {
+		/// Path to file to unpack
+		#[arg(short, long, value_name = "FILE")]
+		input: PathBuf,
+		/// Directory to unpack to
+		#[arg(short, long, value_name = "DIR")]
+		output: Option<PathBuf>,
+		/// Path to keypair to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		keypair: Option<PathBuf>,
+		/// Path to public key to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		public_key: Option<PathBuf>,
+		/// Passphrase to derive the decryption key from, prompted for if the flag is passed without a value
+		#[arg(long, value_name = "PASSPHRASE")]
+		passphrase: Option<Option<String>>,
+		/// Path to a raw 32 byte decryption key, used instead of a passphrase
+		#[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+		key_file: Option<PathBuf>,
+		/// Number of threads to spawn during unpacking, prefer using one thread for small archives
+		#[arg(short, long, default_value_t = thread::available_parallelism().unwrap().get(), value_name = "THREADS")]
+		jobs: usize,
+		/// How may entries to process per thread, [default: archive.entries.count / jobs]
+		#[arg(short, long, value_name = "BATCH")]
+		chunks_size: Option<usize>,
+		/// Restores these file attributes, where they were recorded by `pack --preserve`. Modes and symlinks are only restored on unix
+		#[arg(long, value_name = "ATTRS", num_args=1.., value_enum)]
+		restore: Option<Vec<AttributeSetting>>,
+		/// Also restores symlinks with absolute targets, or targets outside of the output directory. These are skipped otherwise
+		#[arg(long)]
+		unsafe_links: bool,
+	},
+	/// Unpacks a resource and writes to stdout
+	#[command(version = commands::pipe::Subcommand::version())]
+	Pipe {
+		/// Path to file to unpack
+		#[arg(short, long, value_name = "FILE")]
+		input: PathBuf,
+		/// The `id` of the resource to extract
+		#[arg(short, long, value_name = "ID")]
+		resource: String,
+		/// Path to keypair to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		keypair: Option<PathBuf>,
+		/// Path to public key to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		public_key: Option<PathBuf>,
+		/// Passphrase to derive the decryption key from, prompted for if the flag is passed without a value
+		#[arg(long, value_name = "PASSPHRASE")]
+		passphrase: Option<Option<String>>,
+		/// Path to a raw 32 byte decryption key, used instead of a passphrase
+		#[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+		key_file: Option<PathBuf>,
+	},
+	/// List metadata and entries in an archive,
+	#[command(version = commands::list::Subcommand::version())]
+	List {
+		/// Path to file to list
+		#[arg(short, long, value_name = "FILE")]
+		input: PathBuf,
+		/// How to sort the listed entries
+		#[arg(short, long, value_name = "SORT", value_enum)]
+		sort: Option<SortSetting>,
+		/// Shows the entries as a directory tree instead of a table, sorted by name
+		#[arg(short, long)]
+		tree: bool,
+		/// Splits entry IDs into directories at this character, for `--tree`
+		#[arg(long, value_name = "CHAR", default_value_t = '/')]
+		separator: char,
+	},
+	/// Check an input file is a valid .vach archive, auditing every entry's bounds, checksum, signature and data
+	#[command(version = commands::verify::Subcommand::version())]
+	Verify {
+		/// Path to file to verify
+		#[arg(value_name = "FILE")]
+		input: PathBuf,
+		/// Path to keypair to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		keypair: Option<PathBuf>,
+		/// Path to public key to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		public_key: Option<PathBuf>,
+		/// Passphrase to derive the decryption key from, prompted for if the flag is passed without a value
+		#[arg(long, value_name = "PASSPHRASE")]
+		passphrase: Option<Option<String>>,
+		/// Path to a raw 32 byte decryption key, used instead of a passphrase
+		#[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+		key_file: Option<PathBuf>,
+		/// Number of threads to spawn while auditing entries
+		#[arg(short, long, default_value_t = thread::available_parallelism().unwrap().get(), value_name = "THREADS")]
+		jobs: usize,
+	},
+	/// Generate a keypair (verifying & signing key)
+	#[command(name = "keypair")]
+	#[command(version = commands::keypair::Subcommand::version())]
+	GenKeypair {
+		/// Path to output keypair files
+		#[arg(short, long, default_value_t = String::from("keypair.kp"), value_name = "PATH")]
+		output: String,
+		/// Whether to output keypair as separate signing and verifying keys
+		#[arg(short, long, default_value_t = true)]
+		split_key: bool,
+	},
+	/// Pack some files into a .vach archive
+	#[command(version = commands::pack::Subcommand::version())]
+	Pack {
+		/// Input files to include in the archive
+		#[arg(short, long, value_name = "FILES", num_args=1..)]
+		inputs: Option<Vec<PathBuf>>,
+		/// New archive will be output to this path
+		#[arg(short, long, value_name = "PATH")]
+		output: PathBuf,
+		/// Includes files from these directories, non-recursively
+		#[arg(short, long, num_args=1..)]
+		directories: Option<Vec<PathBuf>>,
+		/// Includes files from these directories, recursively
+		#[arg(short, long = "recursive", value_name = "DIRECTORIES", num_args=1..)]
+		recursive_directories: Option<Vec<PathBuf>>,
+		/// Input files to include in the archive
+		#[arg(short = 'x', long, value_name = "PATHS", num_args=1..)]
+		exclude: Option<Vec<PathBuf>>,
+		/// Compression Mode for entries, `Auto` picks the better on a per-entry basis, `Best` tries every candidate algorithm and keeps the smallest
+		#[arg(short, long = "c-mode", value_name = "MODE", value_enum)]
+		compress_mode: Option<CompressModeSetting>,
+		/// Compression algorithm to use for entries
+		#[arg(short = 'a', long = "c-algo", value_name = "ALGO", value_enum)]
+		compression_algorithm: Option<CompressionAlgorithmSetting>,
+		/// Compression level for Brotli (1 to 11, defaults to 9) and Zstd (1 to 22, defaults to 3)
+		#[arg(short = 'l', long = "c-level", value_name = "LEVEL", allow_negative_numbers = true)]
+		compression_level: Option<i32>,
+		/// Algorithms tried by `--c-mode best`, defaults to all of them
+		#[arg(long = "c-candidates", value_name = "ALGOS", value_enum, num_args = 1..)]
+		compression_candidates: Option<Vec<CompressionAlgorithmSetting>>,
+		/// Fraction of an entry's size compression must save to be used by `auto` and `best` modes, eg 0.1 for 10%
+		#[arg(long, value_name = "RATIO")]
+		min_savings: Option<f32>,
+		/// Trains a Zstd dictionary of up to this many bytes from small files, compressing them against it. Only applies with `--c-algo zstd`
+		#[arg(long, value_name = "BYTES")]
+		dictionary: Option<usize>,
+		/// Groups small files into solid blocks of about this many bytes, compressed together. Signed or encrypted files, and files compressed with `--c-mode best`, are never grouped
+		#[arg(long, value_name = "BYTES")]
+		solid: Option<usize>,
+		/// Stores the data of identical files once, after compression. Encrypted files are never deduplicated
+		#[arg(long)]
+		dedup: bool,
+		/// Stores a checksum of each file's data, so corruption is detected when it's read
+		#[arg(long)]
+		checksums: bool,
+		/// Pads the archive so each file's data starts at a multiple of this power of two, eg 4096 for memory-mapped files
+		#[arg(long, value_name = "BYTES")]
+		align: Option<u64>,
+		/// Records these file attributes as entry metadata, so `unpack --restore` can restore them. Preserved symlinks only record their target's path, others are packed with their target's data
+		#[arg(long, value_name = "ATTRS", num_args=1.., value_enum)]
+		preserve: Option<Vec<AttributeSetting>>,
+		/// Appends the files to the existing archive at the output path, rather than replacing it. Signed archives need their keypair
+		#[arg(long)]
+		append: bool,
+		/// Path to keypair to use for cryptographic operations
+		#[arg(short, long, value_name = "FILE")]
+		keypair: Option<PathBuf>,
+		/// Path to private key to use for cryptographic operations
+		#[arg(short = 'p', long, value_name = "FILE")]
+		private_key: Option<PathBuf>,
+		/// Encrypts the data using AesGcm256, with a key derived from the passphrase or read from the key file
+		#[arg(short, long)]
+		encrypt: bool,
+		/// Passphrase to derive the encryption key from, prompted for if the flag is passed without a value or `--encrypt` is set without a `--key-file`
+		#[arg(long, value_name = "PASSPHRASE")]
+		passphrase: Option<Option<String>>,
+		/// Path to a raw 32 byte encryption key, used instead of a passphrase so encrypting needs no prompt
+		#[arg(long, value_name = "FILE", conflicts_with = "passphrase")]
+		key_file: Option<PathBuf>,
+		/// Whether to sign entries and include signatures in the header
+		#[arg(short, long)]
+		sign: bool,
+		/// A simple tag stored in the low byte of every entry's flags, can be used as a version eg
+		#[arg(short, long)]
+		tag: Option<u8>,
+		/// Flags to include in header section of archive
+		#[arg(short, long)]
+		flags: Option<u32>,
+		/// Number of threads to spawn during packing
+		#[arg(short, long, default_value_t = thread::available_parallelism().unwrap().get(), value_name = "THREADS")]
+		jobs: usize,
+	},
+}
@@ -1,80 +1,110 @@
-use std::fs::File;
-
-use indicatif::HumanBytes;
-use tabled::{
-	Table, Tabled,
-	settings::{object::Columns, *},
-};
-use vach::prelude::*;
-
-use super::CommandTrait;
-use crate::cli;
-
-#[derive(Tabled)]
-struct FileTableEntry<'a> {
-	id: &'a str,
-	size: String,
-	flags: Flags,
-	compression: &'static str,
-}
-
-/// This command lists the entries in an archive in tabulated form
-pub struct Subcommand;
-
-impl CommandTrait for Subcommand {
-	fn version() -> &'static str {
-		"0.3"
-	}
-
-	fn evaluate(
-		&self,
-		cli: cli::CommandLine,
-	) -> anyhow::Result<()> {
-		let cli::Command::List { input, sort } = cli.command else {
-			anyhow::bail!("Wrong implementation invoked for subcommand")
-		};
-
-		let file = File::open(input)?;
-		let archive = Archive::new(file)?;
-
-		// log basic metadata
-		println!("{}", archive);
-
-		let mut entries = archive.entries().values().collect::<Vec<_>>();
-		match sort {
-			None | Some(cli::SortSetting::Alphabetical) => entries.sort_by(|a, b| a.id.cmp(&b.id)),
-			Some(cli::SortSetting::AlphabeticalReversed) => entries.sort_by(|a, b| b.id.cmp(&a.id)),
-			Some(cli::SortSetting::SizeAscending) => entries.sort_by(|a, b| a.offset.cmp(&b.offset)),
-			Some(cli::SortSetting::SizeDescending) => entries.sort_by(|a, b| b.offset.cmp(&a.offset)),
-		};
-
-		let table_entries: Vec<FileTableEntry> = entries
-			.into_iter()
-			.map(|entry| {
-				let c_algo = if entry.flags.contains(Flags::LZ4_COMPRESSED) {
-					"LZ4"
-				} else if entry.flags.contains(Flags::BROTLI_COMPRESSED) {
-					"Brotli"
-				} else if entry.flags.contains(Flags::SNAPPY_COMPRESSED) {
-					"Snappy"
-				} else {
-					"None"
-				};
-
-				FileTableEntry {
-					id: &entry.id,
-					size: HumanBytes(entry.offset).to_string(),
-					flags: entry.flags,
-					compression: c_algo,
-				}
-			})
-			.collect();
-
-		let mut table = Table::new(table_entries);
-		table.with(Style::rounded()).with(Modify::list(Columns::new(..1), Alignment::left()));
-
-		println!("{}", table);
-
-		Ok(())
-	}
-}
+use std::fs::File;
+
+use indicatif::HumanBytes;
+use tabled::{
+	Table, Tabled,
+	settings::{object::Columns, *},
+};
+use vach::prelude::*;
+
+use super::CommandTrait;
+use crate::{cli, utils};
+
+#[derive(Tabled)]
+struct FileTableEntry<'a> {
+	id: &'a str,
+	size: String,
+	flags: Flags,
+	compression: String,
+}
+
+/// This command lists the entries in an archive in tabulated form
+pub struct Subcommand;
+
+impl CommandTrait for Subcommand {
+	fn version() -> &'static str {
+		"0.4"
+	}
+
+	fn evaluate(
+		&self,
+		cli: cli::CommandLine,
+	) -> anyhow::Result<()> {
+		let cli::Command::List {
+			input,
+			sort,
+			tree,
+			separator,
+		} = cli.command
+		else {
+			anyhow::bail!("Wrong implementation invoked for subcommand")
+		};
+
+		let file = File::open(input)?;
+		let archive = Archive::with_config(file, ArchiveConfig::default().separator(separator))?;
+
+		// log basic metadata
+		println!("{}", archive);
+
+		if tree {
+			print_tree(&archive, "", "", separator);
+			return Ok(());
+		}
+
+		let mut entries = archive.entries().values().collect::<Vec<_>>();
+		match sort {
+			None | Some(cli::SortSetting::Alphabetical) => entries.sort_by(|a, b| a.id.cmp(&b.id)),
+			Some(cli::SortSetting::AlphabeticalReversed) => entries.sort_by(|a, b| b.id.cmp(&a.id)),
+			Some(cli::SortSetting::SizeAscending) => entries.sort_by_key(|a| a.offset),
+			Some(cli::SortSetting::SizeDescending) => entries.sort_by_key(|b| std::cmp::Reverse(b.offset)),
+		};
+
+		let table_entries: Vec<FileTableEntry> = entries
+			.into_iter()
+			.map(|entry| {
+				let c_algo = utils::compression_name(entry);
+
+				FileTableEntry {
+					id: &entry.id,
+					size: HumanBytes(entry.offset).to_string(),
+					flags: entry.flags,
+					compression: c_algo,
+				}
+			})
+			.collect();
+
+		let mut table = Table::new(table_entries);
+		table.with(Style::rounded()).with(Modify::list(Columns::new(..1), Alignment::left()));
+
+		println!("{}", table);
+
+		Ok(())
+	}
+}
+
+/// Prints the directories and entries under `prefix`, recursively
+fn print_tree<T>(
+	archive: &Archive<T>,
+	prefix: &str,
+	indent: &str,
+	separator: char,
+) {
+	let children = archive.read_dir(prefix).unwrap_or_default();
+
+	for (idx, child) in children.iter().enumerate() {
+		let last = idx + 1 == children.len();
+		let (branch, continuation) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+
+		match child {
+			DirEntry::Dir(name) => {
+				println!("{indent}{branch}{name}{separator}");
+
+				let prefix = if prefix.is_empty() { name.to_string() } else { format!("{prefix}{separator}{name}") };
+				print_tree(archive, &prefix, &format!("{indent}{continuation}"), separator);
+			},
+			DirEntry::File(name, entry) => {
+				println!("{indent}{branch}{name} ({}, {})", HumanBytes(entry.offset), utils::compression_name(entry));
+			},
+		}
+	}
+}
@@ -1,241 +1,349 @@
-use std::{
-	collections::HashSet,
-	fs::File,
-	io::{self, Read, Write},
-	path::PathBuf,
-};
-
-use indicatif::{ProgressBar, ProgressStyle};
-use tempfile::NamedTempFile;
-use vach::{crypto_utils, prelude::*};
-use walkdir;
-
-use super::CommandTrait;
-use crate::cli;
-
-struct FileAutoDropper(PathBuf, Option<File>);
-
-impl FileAutoDropper {
-	fn new(path: PathBuf) -> Option<Leaf<FileAutoDropper>> {
-		path.exists().then(|| {
-			let id = path.to_string_lossy().to_string();
-			let handle = FileAutoDropper(path, None);
-
-			Leaf::new(handle, id)
-		})
-	}
-}
-
-impl Read for FileAutoDropper {
-	fn read(
-		&mut self,
-		buf: &mut [u8],
-	) -> io::Result<usize> {
-		// open file if None
-		let file = match self.1.as_mut() {
-			Some(file) => file,
-			None => {
-				let file = File::open(&self.0)?;
-				self.1.insert(file)
-			},
-		};
-
-		let result = file.read(buf);
-		if let Ok(0) = result {
-			// Once the file is done reading, we drop the file handle
-			self.1.take();
-		};
-
-		result
-	}
-}
-
-/// This command verifies the validity and integrity of an archive
-pub struct Subcommand;
-
-impl CommandTrait for Subcommand {
-	fn version() -> &'static str {
-		"0.6"
-	}
-
-	fn evaluate(
-		&self,
-		args: cli::CommandLine,
-	) -> anyhow::Result<()> {
-		// 1: Assemble Input Settings
-		let cli::Command::Pack {
-			output,
-			inputs,
-			directories,
-			recursive_directories,
-			exclude,
-			compress_mode,
-			compression_algorithm,
-			sign,
-			tag,
-			flags,
-			jobs,
-			encrypt,
-			keypair,
-			private_key,
-		} = args.command
-		else {
-			anyhow::bail!("Wrong implementation invoked for subcommand")
-		};
-
-		let flags = flags.clone().map(Flags::from_bits).unwrap_or_default();
-		let version = tag.unwrap_or(0);
-
-		let compress_mode = compress_mode
-			.map(|c| match c {
-				cli::CompressModeSetting::Always => CompressMode::Always,
-				cli::CompressModeSetting::Never => CompressMode::Never,
-				cli::CompressModeSetting::Auto => CompressMode::Detect,
-			})
-			.unwrap_or_default();
-
-		let compression_algo = compression_algorithm
-			.map(|a| match a {
-				cli::CompressionAlgorithmSetting::LZ4 => CompressionAlgorithm::LZ4,
-				cli::CompressionAlgorithmSetting::Snappy => CompressionAlgorithm::Snappy,
-				cli::CompressionAlgorithmSetting::Brotli => CompressionAlgorithm::Brotli(9),
-			})
-			.unwrap_or_default();
-
-		// get signing_key
-		let mut signing_key = match private_key {
-			Some(path) => {
-				let file = File::open(path)?;
-				Some(crypto_utils::read_secret_key(file)?)
-			},
-			None => match keypair {
-				Some(path) => {
-					let file = File::open(path)?;
-					Some(crypto_utils::read_keypair(file)?)
-				},
-				None => None,
-			},
-		};
-
-		// If encrypt is true, and no keypair was found: Generate and write a new keypair to a file
-		if (encrypt || sign) && signing_key.is_none() {
-			let generated = crypto_utils::gen_keypair();
-
-			let mut file = File::create("keypair.kp")?;
-			file.write_all(&generated.to_keypair_bytes())?;
-			println!("Generated a new keypair @ keypair.kp");
-
-			signing_key = Some(generated);
-		}
-
-		// combine leaf input-template
-		let template = Leaf::<&'static [u8]>::default()
-			.compress(compress_mode)
-			.compression_algo(compression_algo)
-			.encrypt(encrypt)
-			.sign(sign)
-			.version(version);
-
-		// 2: Assemble input files
-		let mut leaves = vec![];
-
-		// Extract entries to be excluded
-		let excludes = match exclude {
-			Some(val) => val
-				.iter()
-				.filter_map(|path| match path.canonicalize() {
-					Ok(path) => Some(path),
-					Err(err) => {
-						eprintln!("Failed to canonicalize: {}. Skipping due to error: {}", path.to_string_lossy(), err);
-						None
-					},
-				})
-				.collect::<HashSet<PathBuf>>(),
-			None => HashSet::new(),
-		};
-
-		// Used to filter invalid inputs and excluded inputs
-		let path_filter = |path: &PathBuf| match path.canonicalize() {
-			Ok(canonical) => !excludes.iter().any(|p| canonical.starts_with(p)) && canonical.is_file(),
-			Err(err) => {
-				eprintln!("Failed to canonicalize: {}. Skipping due to error: {}", path.to_string_lossy(), err);
-				false
-			},
-		};
-
-		if let Some(f) = inputs {
-			let iter = f.into_iter().filter(path_filter).filter_map(FileAutoDropper::new).map(|l| l.template(&template));
-
-			leaves.extend(iter);
-		};
-
-		// Extract directory inputs
-		if let Some(val) = directories {
-			let iter = val
-				.into_iter()
-				.map(|dir| {
-					walkdir::WalkDir::new(dir)
-						.max_depth(1)
-						.into_iter()
-						.map(|v| v.unwrap().into_path())
-						.filter(path_filter)
-						.filter_map(FileAutoDropper::new)
-						.map(|l| l.template(&template))
-				})
-				.flatten();
-
-			leaves.extend(iter);
-		};
-
-		// Extract recursive directory inputs
-		if let Some(val) = recursive_directories {
-			let iter = val
-				.into_iter()
-				.flat_map(|dir| walkdir::WalkDir::new(dir).into_iter())
-				.map(|v| v.unwrap().into_path())
-				.filter(|f| path_filter(f))
-				.filter_map(FileAutoDropper::new)
-				.map(|l| l.template(&template));
-
-			leaves.extend(iter);
-		}
-
-		// 3: Final Assembly
-
-		// create temporary file
-		let mut temporary_file = NamedTempFile::new().unwrap();
-
-		// assemble configuration for builder
-		let config = BuilderConfig {
-			flags,
-			signing_key,
-			num_threads: jobs.try_into().expect("Number of threads cannot be zero"),
-		};
-
-		// setup progress bar and callback to update it
-		let progress = ProgressBar::new(leaves.len() as _);
-		progress.set_style(
-			ProgressStyle::default_bar()
-				.template(super::PROGRESS_BAR_STYLE)?
-				.progress_chars("█░-")
-				.tick_chars("⢀ ⡀ ⠄ ⢂ ⡂ ⠅ ⢃ ⡃ ⠍ ⢋ ⡋ ⠍⠁⢋⠁⡋⠁⠍⠉⠋⠉⠋⠉⠉⠙⠉⠙⠉⠩⠈⢙⠈⡙⢈⠩⡀⢙⠄⡙⢂⠩⡂⢘⠅⡘⢃⠨⡃⢐⠍⡐⢋⠠⡋⢀⠍⡁⢋⠁⡋⠁⠍⠉⠋⠉⠋⠉⠉⠙⠉⠙⠉⠩⠈⢙⠈⡙⠈⠩ ⢙ ⡙ ⠩ ⢘ ⡘ ⠨ ⢐ ⡐ ⠠ ⢀ ⡀"),
-		);
-
-		// increments progress-bar by one for each entry
-		let mut callback = |entry: &RegistryEntry, _: &[u8]| {
-			progress.inc(1);
-			let message = entry.id.as_ref();
-			progress.set_message(message.to_string());
-		};
-
-		// 4: Write
-		let bytes_written = dump(&mut temporary_file, &mut leaves, &config, Some(&mut callback))?;
-		temporary_file.persist(&output)?;
-
-		progress.println(format!("Generated a new archive @ {}; Bytes written: {}", output.display(), bytes_written));
-
-		progress.finish();
-
-		Ok(())
-	}
-}
+use std::{
+	collections::HashSet,
+	fs::{self, File},
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use tempfile::NamedTempFile;
+use vach::{crypto_utils, prelude::*};
+use walkdir;
+
+use super::CommandTrait;
+use crate::{cli, utils};
+
+/// Lazily opens the file at it's path. Symlinks preserved as such hold no data, as only their target is recorded
+struct FileAutoDropper(PathBuf, Option<File>, bool);
+
+impl FileAutoDropper {
+	fn new(
+		path: PathBuf,
+		template: &Leaf,
+		preserve: &[cli::AttributeSetting],
+	) -> Option<Leaf<FileAutoDropper>> {
+		let metadata = match file_metadata(&path, preserve) {
+			Ok(metadata) => metadata,
+			Err(err) => {
+				eprintln!("Failed to read the attributes of: {}. Skipping due to error: {}", path.to_string_lossy(), err);
+				return None;
+			},
+		};
+
+		// lets large files be told apart without opening them
+		let link = metadata.get(utils::SYMLINK_KEY).is_some();
+		let size = match link {
+			true => Some(0),
+			false => fs::metadata(&path).ok().map(|attributes| attributes.len()),
+		};
+
+		let id = path.to_string_lossy().to_string();
+		let handle = FileAutoDropper(path, None, link);
+
+		let mut leaf = Leaf::new(handle, id).template(template).metadata(metadata);
+		if let Some(size) = size {
+			leaf = leaf.size_hint(size);
+		}
+
+		Some(leaf)
+	}
+}
+
+/// Records the attributes of a file selected by `--preserve` as entry metadata. Symlinks record their target's mode and mtime
+fn file_metadata(
+	path: &Path,
+	preserve: &[cli::AttributeSetting],
+) -> io::Result<Metadata> {
+	let mut metadata = Metadata::new();
+
+	if preserve.contains(&cli::AttributeSetting::Symlinks) && path.is_symlink() {
+		metadata.insert(utils::SYMLINK_KEY, fs::read_link(path)?.to_string_lossy().to_string());
+	}
+
+	// dangling symlinks have nothing else to record
+	let Ok(attributes) = fs::metadata(path) else {
+		return Ok(metadata);
+	};
+
+	if preserve.contains(&cli::AttributeSetting::Mtime) {
+		metadata.insert(utils::MTIME_KEY, utils::to_unix_time(attributes.modified()?));
+	}
+
+	#[cfg(unix)]
+	if preserve.contains(&cli::AttributeSetting::Mode) {
+		use std::os::unix::fs::PermissionsExt;
+		metadata.insert(utils::MODE_KEY, (attributes.permissions().mode() & 0o777) as u64);
+	}
+
+	Ok(metadata)
+}
+
+impl Read for FileAutoDropper {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		if self.2 {
+			return Ok(0);
+		}
+
+		// open file if None
+		let file = match self.1.as_mut() {
+			Some(file) => file,
+			None => self.1.insert(File::open(&self.0)?),
+		};
+
+		let result = file.read(buf);
+		if let Ok(0) = result {
+			// Once the file is done reading, we drop the file handle
+			self.1.take();
+		};
+
+		result
+	}
+}
+
+/// This command verifies the validity and integrity of an archive
+pub struct Subcommand;
+
+impl CommandTrait for Subcommand {
+	fn version() -> &'static str {
+		"0.9"
+	}
+
+	fn evaluate(
+		&self,
+		args: cli::CommandLine,
+	) -> anyhow::Result<()> {
+		// 1: Assemble Input Settings
+		let cli::Command::Pack {
+			output,
+			inputs,
+			directories,
+			recursive_directories,
+			exclude,
+			compress_mode,
+			compression_algorithm,
+			compression_level,
+			compression_candidates,
+			min_savings,
+			dictionary,
+			solid,
+			dedup,
+			checksums,
+			align,
+			preserve,
+			append,
+			sign,
+			tag,
+			flags,
+			jobs,
+			encrypt,
+			passphrase,
+			key_file,
+			keypair,
+			private_key,
+		} = args.command
+		else {
+			anyhow::bail!("Wrong implementation invoked for subcommand")
+		};
+
+		let flags = flags.map(Flags::from_bits).unwrap_or_default();
+		let tag = Flags::from_bits(tag.unwrap_or(0) as u32);
+
+		let compress_mode = compress_mode
+			.map(|c| match c {
+				cli::CompressModeSetting::Always => CompressMode::Always,
+				cli::CompressModeSetting::Never => CompressMode::Never,
+				cli::CompressModeSetting::Auto => CompressMode::Detect,
+				cli::CompressModeSetting::Best => CompressMode::Best,
+			})
+			.unwrap_or_default();
+
+		let to_algorithm = |a: cli::CompressionAlgorithmSetting| match a {
+			cli::CompressionAlgorithmSetting::LZ4 => CompressionAlgorithm::LZ4,
+			cli::CompressionAlgorithmSetting::Snappy => CompressionAlgorithm::Snappy,
+			cli::CompressionAlgorithmSetting::Brotli => CompressionAlgorithm::Brotli(compression_level.unwrap_or(9).try_into().unwrap_or(0)),
+			cli::CompressionAlgorithmSetting::Zstd => CompressionAlgorithm::Zstd(compression_level.unwrap_or(3)),
+		};
+
+		let compression_algo = compression_algorithm.map(to_algorithm).unwrap_or_default();
+		let compression_candidates = compression_candidates.map(|candidates| candidates.into_iter().map(to_algorithm).collect::<Vec<_>>());
+
+		// get signing_key
+		let mut signing_key = match private_key {
+			Some(path) => {
+				let file = File::open(path)?;
+				Some(crypto_utils::read_secret_key(file)?)
+			},
+			None => match keypair {
+				Some(path) => {
+					let file = File::open(path)?;
+					Some(crypto_utils::read_keypair(file)?)
+				},
+				None => None,
+			},
+		};
+
+		// If sign is true, and no keypair was found: Generate and write a new keypair to a file
+		if sign && signing_key.is_none() {
+			let generated = crypto_utils::gen_keypair();
+
+			let mut file = File::create("keypair.kp")?;
+			file.write_all(&generated.to_keypair_bytes())?;
+			println!("Generated a new keypair @ keypair.kp");
+
+			signing_key = Some(generated);
+		}
+
+		// encryption keys are read from a key file or derived from a passphrase, which is prompted for if neither was given
+		let encryption_key = utils::read_key_file(key_file)?;
+		let passphrase = match utils::read_passphrase(passphrase)? {
+			None if encrypt && encryption_key.is_none() => utils::read_passphrase(Some(None))?,
+			passphrase => passphrase,
+		};
+
+		// combine leaf input-template
+		let template = Leaf::<&'static [u8]>::default()
+			.compress(compress_mode)
+			.compression_algo(compression_algo)
+			.encrypt(encrypt)
+			.sign(sign)
+			.flags(tag);
+
+		// 2: Assemble input files
+		let mut leaves = vec![];
+
+		// Extract entries to be excluded
+		let excludes = match exclude {
+			Some(val) => val
+				.iter()
+				.filter_map(|path| match path.canonicalize() {
+					Ok(path) => Some(path),
+					Err(err) => {
+						eprintln!("Failed to canonicalize: {}. Skipping due to error: {}", path.to_string_lossy(), err);
+						None
+					},
+				})
+				.collect::<HashSet<PathBuf>>(),
+			None => HashSet::new(),
+		};
+
+		let preserve = preserve.unwrap_or_default();
+		let preserve_links = preserve.contains(&cli::AttributeSetting::Symlinks);
+
+		// Used to filter invalid inputs and excluded inputs
+		let excluded = |canonical: &Path| excludes.iter().any(|p| canonical.starts_with(p));
+		let path_filter = |path: &PathBuf| match path.canonicalize() {
+			Ok(canonical) => !excluded(&canonical) && canonical.is_file(),
+			// dangling symlinks are packed when preserving symlinks, checked for exclusion by their parent
+			Err(_) if preserve_links && path.is_symlink() => {
+				let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+				parent.canonicalize().is_ok_and(|parent| !excluded(&parent.join(path.file_name().unwrap_or_default())))
+			},
+			Err(err) => {
+				eprintln!("Failed to canonicalize: {}. Skipping due to error: {}", path.to_string_lossy(), err);
+				false
+			},
+		};
+
+		if let Some(f) = inputs {
+			let iter = f.into_iter().filter(path_filter).filter_map(|path| FileAutoDropper::new(path, &template, &preserve));
+
+			leaves.extend(iter);
+		};
+
+		// Extract directory inputs
+		if let Some(val) = directories {
+			let iter = val.into_iter().flat_map(|dir| {
+				walkdir::WalkDir::new(dir)
+					.max_depth(1)
+					.into_iter()
+					.map(|v| v.unwrap().into_path())
+					.filter(path_filter)
+					.filter_map(|path| FileAutoDropper::new(path, &template, &preserve))
+			});
+
+			leaves.extend(iter);
+		};
+
+		// Extract recursive directory inputs
+		if let Some(val) = recursive_directories {
+			let iter = val
+				.into_iter()
+				.flat_map(|dir| walkdir::WalkDir::new(dir).into_iter())
+				.map(|v| v.unwrap().into_path())
+				.filter(|f| path_filter(f))
+				.filter_map(|path| FileAutoDropper::new(path, &template, &preserve));
+
+			leaves.extend(iter);
+		}
+
+		// 3: Final Assembly
+
+		// assemble configuration for builder
+		let mut config = BuilderConfig {
+			flags,
+			signing_key,
+			encryption_key,
+			passphrase,
+			num_threads: jobs,
+			dictionary_size: dictionary,
+			solid_block_size: solid,
+			deduplicate: dedup,
+			checksums,
+			alignment: align,
+			min_savings,
+			..Default::default()
+		};
+
+		if let Some(candidates) = compression_candidates {
+			config.compression_candidates = candidates;
+		}
+
+		// setup progress bar and callback to update it
+		let progress = ProgressBar::new(leaves.len() as _);
+		progress.set_style(
+			ProgressStyle::default_bar()
+				.template(super::PROGRESS_BAR_STYLE)?
+				.progress_chars("█░-")
+				.tick_chars("⢀ ⡀ ⠄ ⢂ ⡂ ⠅ ⢃ ⡃ ⠍ ⢋ ⡋ ⠍⠁⢋⠁⡋⠁⠍⠉⠋⠉⠋⠉⠉⠙⠉⠙⠉⠩⠈⢙⠈⡙⢈⠩⡀⢙⠄⡙⢂⠩⡂⢘⠅⡘⢃⠨⡃⢐⠍⡐⢋⠠⡋⢀⠍⡁⢋⠁⡋⠁⠍⠉⠋⠉⠋⠉⠉⠙⠉⠙⠉⠩⠈⢙⠈⡙⠈⠩ ⢙ ⡙ ⠩ ⢘ ⡘ ⠨ ⢐ ⡐ ⠠ ⢀ ⡀"),
+		);
+
+		// increments progress-bar by one for each entry
+		let mut callback = |entry: &RegistryEntry| {
+			progress.inc(1);
+			let message = entry.id.as_ref();
+			progress.set_message(message.to_string());
+
+			// report which algorithm won for each entry
+			if matches!(compress_mode, CompressMode::Best) {
+				progress.suspend(|| println!("{}: {} ({})", entry.id, utils::compression_name(entry), HumanBytes(entry.offset)));
+			}
+		};
+
+		// 4: Write, appending in place or into a temporary file replacing the output
+		let stats = if append {
+			let file = File::options().read(true).write(true).open(&output)?;
+			let stats = vach::builder::append(file, &mut leaves, Some(config), Some(&mut callback))?;
+
+			progress.println(format!("Appended to the archive @ {}; Bytes written: {}", output.display(), stats.bytes_written));
+			stats
+		} else {
+			let mut temporary_file = NamedTempFile::new().unwrap();
+			let stats = dump(&mut temporary_file, &mut leaves, Some(config), Some(&mut callback))?;
+			temporary_file.persist(&output)?;
+
+			progress.println(format!("Generated a new archive @ {}; Bytes written: {}", output.display(), stats.bytes_written));
+			stats
+		};
+
+		if dedup {
+			progress.suspend(|| println!("Deduplicated {} of identical data", HumanBytes(stats.bytes_deduplicated)));
+		}
+
+		progress.finish();
+
+		Ok(())
+	}
+}
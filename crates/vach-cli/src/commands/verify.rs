@@ -1,36 +1,88 @@
-use std::fs::File;
-use vach::archive::*;
-
-use super::CommandTrait;
-use crate::cli;
-
-pub struct Subcommand;
-
-impl CommandTrait for Subcommand {
-	fn version() -> &'static str {
-		"0.2"
-	}
-
-	fn evaluate(
-		&self,
-		cli: cli::CommandLine,
-	) -> anyhow::Result<()> {
-		let cli::Command::Verify { input } = cli.command else {
-			anyhow::bail!("Wrong implementation invoked for subcommand")
-		};
-
-		let input_file = File::open(input)?;
-		if let Err(err) = Archive::new(input_file) {
-			match err {
-				InternalError::MalformedArchiveSource(m) => anyhow::bail!("Invalid Magic Sequence: {:?}", m),
-				InternalError::IncompatibleArchiveVersionError(v) => {
-					anyhow::bail!("Incompatible Archive Version: {}, expected: {}", v, vach::VERSION)
-				},
-				InternalError::MissingFeatureError(f) => anyhow::bail!("CLI wasn't compiled with the feature: {}", f),
-				e => anyhow::bail!("Unable to verify the archive source, error: {}", e.to_string()),
-			}
-		};
-
-		Ok(())
-	}
-}
+use std::fs::File;
+use vach::{archive::*, crypto_utils};
+
+use super::CommandTrait;
+use crate::{cli, utils};
+
+pub struct Subcommand;
+
+impl CommandTrait for Subcommand {
+	fn version() -> &'static str {
+		"0.3"
+	}
+
+	fn evaluate(
+		&self,
+		cli: cli::CommandLine,
+	) -> anyhow::Result<()> {
+		let cli::Command::Verify {
+			input,
+			keypair,
+			public_key,
+			passphrase,
+			key_file,
+			jobs,
+		} = cli.command
+		else {
+			anyhow::bail!("Wrong implementation invoked for subcommand")
+		};
+
+		// Attempting to extract a public key from a -p or -k input
+		let verifying_key = match keypair {
+			Some(path) => {
+				let file = File::open(&path)?;
+				Some(crypto_utils::read_keypair(file)?.verifying_key())
+			},
+			None => match public_key {
+				Some(path) => {
+					let file = File::open(&path)?;
+					Some(crypto_utils::read_verifying_key(file)?)
+				},
+				None => None,
+			},
+		};
+
+		let config = ArchiveConfig {
+			verifying_key,
+			decryption_key: utils::read_key_file(key_file)?,
+			passphrase: utils::read_passphrase(passphrase)?,
+			..Default::default()
+		};
+
+		let input_file = File::open(input)?;
+		let archive = match Archive::from_source_with_config(input_file, config) {
+			Ok(archive) => archive,
+			Err(err) => match err {
+				InternalError::MalformedArchiveSource(m) => anyhow::bail!("Invalid Magic Sequence: {:?}", m),
+				InternalError::IncompatibleArchiveVersionError(v) => {
+					anyhow::bail!("Incompatible Archive Version: {}, expected: {} to {}", v, vach::MIN_VERSION, vach::VERSION)
+				},
+				InternalError::MissingFeatureError(f) => anyhow::bail!("CLI wasn't compiled with the feature: {}", f),
+				e => anyhow::bail!("Unable to verify the archive source, error: {}", e),
+			},
+		};
+
+		// report failures, then a summary
+		let reports = archive.verify_all(jobs);
+		let (mut verified, mut unsigned, mut failed) = (0, 0, 0);
+
+		for report in &reports {
+			match report.status {
+				EntryStatus::Ok => verified += 1,
+				EntryStatus::Unsigned => unsigned += 1,
+				_ => {
+					failed += 1;
+					println!("{}: {}", report.id, report.status);
+				},
+			}
+		}
+
+		println!("Audited {} entries: {verified} verified, {unsigned} unsigned, {failed} failed", reports.len());
+
+		if failed > 0 {
+			anyhow::bail!("{failed} entries failed verification");
+		}
+
+		Ok(())
+	}
+}
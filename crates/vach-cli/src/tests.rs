@@ -0,0 +1,148 @@
+// Drives the subcommands as they would be invoked from the command line
+#![cfg(test)]
+
+use std::sync::{Mutex, MutexGuard};
+
+use clap::Parser;
+
+use crate::{
+	cli::CommandLine,
+	commands::{self, CommandTrait},
+};
+
+// the tests change the working directory, so they take turns
+static WORKING_DIRECTORY: Mutex<()> = Mutex::new(());
+
+/// Moves into a new temporary directory, entry IDs are the paths as given so tests work from within one
+fn enter_temporary_directory() -> anyhow::Result<(tempfile::TempDir, MutexGuard<'static, ()>)> {
+	let guard = WORKING_DIRECTORY.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	let directory = tempfile::tempdir()?;
+	std::env::set_current_dir(directory.path())?;
+
+	Ok((directory, guard))
+}
+
+#[test]
+#[cfg(unix)]
+fn preserve_and_restore_attributes() -> anyhow::Result<()> {
+	use std::{
+		fs,
+		os::unix::fs::{PermissionsExt, symlink},
+		path::Path,
+		time::{Duration, SystemTime},
+	};
+
+	let _directory = enter_temporary_directory()?;
+
+	let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+	fs::create_dir("input")?;
+	fs::write("input/script.sh", b"#!/bin/sh\necho vach\n")?;
+	fs::set_permissions("input/script.sh", fs::Permissions::from_mode(0o750))?;
+	fs::File::options().write(true).open("input/script.sh")?.set_modified(mtime)?;
+	symlink("script.sh", "input/link.sh")?;
+
+	let attributes = ["--preserve", "mode", "mtime", "symlinks"];
+	let pack = CommandLine::parse_from(["vach", "pack", "-d", "input", "-o", "attributes.vach", "-j", "1"].into_iter().chain(attributes));
+	commands::pack::Subcommand.evaluate(pack)?;
+
+	let restore = ["--restore", "mode", "mtime", "symlinks"];
+	let unpack = CommandLine::parse_from(["vach", "unpack", "-i", "attributes.vach", "-o", "output", "-j", "1"].into_iter().chain(restore));
+	commands::unpack::Subcommand.evaluate(unpack)?;
+
+	let script = fs::metadata("output/input/script.sh")?;
+	assert_eq!(script.permissions().mode() & 0o777, 0o750);
+	assert_eq!(script.modified()?, mtime);
+	assert_eq!(fs::read("output/input/script.sh")?, b"#!/bin/sh\necho vach\n");
+
+	// the link is restored as a link, rather than a copy of it's target
+	assert!(fs::symlink_metadata("output/input/link.sh")?.is_symlink());
+	assert_eq!(fs::read_link("output/input/link.sh")?, Path::new("script.sh"));
+
+	// only the link's target is packed, not it's data
+	let archive = vach::archive::Archive::new(fs::File::open("attributes.vach")?)?;
+	assert!(archive.fetch("input/link.sh")?.data.is_empty());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn skip_unsafe_symlinks() -> anyhow::Result<()> {
+	use std::{fs, io::Cursor, path::Path};
+	use vach::builder::{Leaf, dump};
+
+	let _directory = enter_temporary_directory()?;
+
+	// a crafted archive, with links leading out of the output directory
+	let links = [
+		("data.txt", None),
+		("link", Some("data.txt")),
+		("nested/link", Some("../data.txt")),
+		("climbing", Some("../outside.txt")),
+		("nested/climbing", Some("../../outside.txt")),
+		("absolute", Some("/etc/hostname")),
+	];
+
+	let mut leaves = links
+		.iter()
+		.map(|(id, target)| match target {
+			Some(target) => Leaf::new(b"".as_slice(), *id).meta(crate::utils::SYMLINK_KEY, *target),
+			None => Leaf::new(b"Inside".as_slice(), *id),
+		})
+		.collect::<Vec<_>>();
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, None, None)?;
+	fs::write("links.vach", target.into_inner())?;
+
+	let unpack = |output: &str, flags: &[&str]| {
+		let args = ["vach", "unpack", "-i", "links.vach", "-o", output, "-j", "1", "--restore", "symlinks"];
+		commands::unpack::Subcommand.evaluate(CommandLine::parse_from(args.iter().chain(flags)))
+	};
+
+	unpack("safe", &[])?;
+	for (id, restored) in [("link", true), ("nested/link", true), ("climbing", false), ("nested/climbing", false), ("absolute", false)] {
+		let path = Path::new("safe").join(id);
+		assert_eq!(path.symlink_metadata().is_ok(), restored, "{id}");
+	}
+
+	assert_eq!(fs::read("safe/nested/link")?, b"Inside");
+
+	// unless explicitly allowed
+	unpack("unsafe", &["--unsafe-links"])?;
+	for id in ["climbing", "nested/climbing", "absolute"] {
+		assert!(fs::symlink_metadata(Path::new("unsafe").join(id))?.is_symlink(), "{id}");
+	}
+
+	Ok(())
+}
+
+#[test]
+fn encrypt_with_key_file() -> anyhow::Result<()> {
+	use std::fs;
+
+	let _directory = enter_temporary_directory()?;
+
+	fs::create_dir("input")?;
+	fs::write("input/secret.txt", b"Nobody should read this")?;
+	fs::write("secret.key", vach::crypto_utils::gen_encryption_key())?;
+
+	// no passphrase is prompted for
+	let pack = CommandLine::parse_from(["vach", "pack", "-d", "input", "-o", "secret.vach", "-e", "--key-file", "secret.key", "-j", "1"]);
+	commands::pack::Subcommand.evaluate(pack)?;
+
+	let unpack = CommandLine::parse_from(["vach", "unpack", "-i", "secret.vach", "-o", "output", "--key-file", "secret.key", "-j", "1"]);
+	commands::unpack::Subcommand.evaluate(unpack)?;
+	assert_eq!(fs::read("output/input/secret.txt")?, b"Nobody should read this");
+
+	let keyless = CommandLine::parse_from(["vach", "unpack", "-i", "secret.vach", "-o", "keyless", "-j", "1"]);
+	assert!(commands::unpack::Subcommand.evaluate(keyless).is_err());
+
+	// a passphrase can't be given alongside a key file
+	let both = CommandLine::try_parse_from(["vach", "pack", "-d", "input", "-o", "both.vach", "-e", "--key-file", "secret.key", "--passphrase", "hunter2"]);
+	assert!(both.is_err());
+
+	Ok(())
+}
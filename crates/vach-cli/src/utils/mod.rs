@@ -1,19 +1,86 @@
-use anyhow::{Result, bail};
-use std::{fs::File, io::Write, path::PathBuf, str::FromStr};
-
-pub fn create_and_write_to_file(
-	path: &str,
-	data: &[u8],
-) -> Result<()> {
-	let path = PathBuf::from_str(path)?;
-
-	// Check if the file exists
-	if path.exists() {
-		bail!("The file {} already exists!", path.to_string_lossy());
-	}
-
-	let mut file = File::create(path)?;
-	file.write_all(data)?;
-
-	Ok(())
-}
+use anyhow::{Result, bail};
+use std::{
+	fs::File,
+	io::Write,
+	path::PathBuf,
+	str::FromStr,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use vach::{
+	crypto_utils,
+	prelude::{EncryptionKey, Flags, RegistryEntry},
+};
+
+/// Metadata key holding a file's unix permission bits
+pub const MODE_KEY: &str = "unix.mode";
+/// Metadata key holding a file's modification time, in seconds since the unix epoch
+pub const MTIME_KEY: &str = "mtime";
+/// Metadata key holding the target of a symlink
+pub const SYMLINK_KEY: &str = "symlink";
+
+pub fn create_and_write_to_file(
+	path: &str,
+	data: &[u8],
+) -> Result<()> {
+	let path = PathBuf::from_str(path)?;
+
+	// Check if the file exists
+	if path.exists() {
+		bail!("The file {} already exists!", path.to_string_lossy());
+	}
+
+	let mut file = File::create(path)?;
+	file.write_all(data)?;
+
+	Ok(())
+}
+
+/// Names the algorithm or codec an entry was compressed with, from it's flags
+pub fn compression_name(entry: &RegistryEntry) -> String {
+	let name = match entry.codec {
+		_ if entry.flags.contains(Flags::LZ4_COMPRESSED) => "LZ4".to_string(),
+		_ if entry.flags.contains(Flags::BROTLI_COMPRESSED) => "Brotli".to_string(),
+		_ if entry.flags.contains(Flags::SNAPPY_COMPRESSED) => "Snappy".to_string(),
+		_ if entry.flags.contains(Flags::ZSTD_COMPRESSED) => "Zstd".to_string(),
+		Some(codec) if entry.flags.contains(Flags::COMPRESSED_FLAG) => format!("Codec({codec})"),
+		_ => "None".to_string(),
+	};
+
+	match entry.flags.contains(Flags::SOLID_FLAG) {
+		true => format!("{name} (solid)"),
+		false => name,
+	}
+}
+
+/// Resolves a `--passphrase` argument, prompting for one if the flag was passed without a value
+pub fn read_passphrase(arg: Option<Option<String>>) -> Result<Option<String>> {
+	match arg {
+		Some(Some(passphrase)) => Ok(Some(passphrase)),
+		Some(None) => Ok(Some(rpassword::prompt_password("Passphrase: ")?)),
+		None => Ok(None),
+	}
+}
+
+/// Seconds since the unix epoch, negative for earlier times
+pub fn to_unix_time(time: SystemTime) -> i64 {
+	match time.duration_since(UNIX_EPOCH) {
+		Ok(duration) => duration.as_secs() as i64,
+		Err(err) => -(err.duration().as_secs() as i64),
+	}
+}
+
+/// The inverse of [`to_unix_time`]
+pub fn from_unix_time(seconds: i64) -> SystemTime {
+	match seconds {
+		0.. => UNIX_EPOCH + Duration::from_secs(seconds as u64),
+		_ => UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs()),
+	}
+}
+
+/// Reads the raw encryption key at the path given to `--key-file`
+pub fn read_key_file(path: Option<PathBuf>) -> Result<Option<EncryptionKey>> {
+	match path {
+		Some(path) => Ok(Some(crypto_utils::read_encryption_key(File::open(path)?)?)),
+		None => Ok(None),
+	}
+}
@@ -1,49 +1,433 @@
-#![cfg(feature = "crypto")]
-#![cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-use std::fmt;
-
-use aes_gcm::aead::{Aead, AeadCore, OsRng};
-use aes_gcm::{Aes256Gcm, KeyInit};
-pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
-
-use crate::global::error::{InternalError, InternalResult};
-
-/// Encryption - Decryption, A convenient wrapper around [`aes`](aes_gcm) encryption and decryption
-pub(crate) struct Encryptor {
-	cipher: Aes256Gcm,
-}
-
-impl fmt::Debug for Encryptor {
-	fn fmt(
-		&self,
-		f: &mut fmt::Formatter<'_>,
-	) -> fmt::Result {
-		f.debug_struct("Encryptor").field("cipher", &"<Aes256Gcm>").finish()
-	}
-}
-
-impl Encryptor {
-	pub(crate) fn new(vk: &VerifyingKey) -> Encryptor {
-		Encryptor {
-			cipher: Aes256Gcm::new_from_slice(vk.as_bytes()).unwrap(),
-		}
-	}
-
-	pub(crate) fn encrypt(
-		&self,
-		data: &[u8],
-	) -> InternalResult<(Vec<u8>, [u8; crate::NONCE_LENGTH])> {
-		let nonce = Aes256Gcm::generate_nonce(OsRng);
-
-		self.cipher.encrypt(&nonce, data).map_err(InternalError::CryptoError).map(|res| (res, *nonce.as_array().unwrap()))
-	}
-
-	pub(crate) fn decrypt(
-		&self,
-		data: &[u8],
-		nonce: &[u8; crate::NONCE_LENGTH],
-	) -> InternalResult<Vec<u8>> {
-		let nonce = aes_gcm::Nonce::from_slice(nonce);
-		self.cipher.decrypt(nonce, data).map_err(InternalError::CryptoError)
-	}
-}
+#![cfg(feature = "crypto")]
+#![cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+use std::{
+	fmt,
+	io::{self, Read},
+	sync::Arc,
+};
+
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit};
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+pub use x25519_dalek::{PublicKey as RecipientKey, StaticSecret as RecipientSecret};
+use sha2::{Digest, Sha256};
+
+/// Size of an aes256-gcm authentication tag, appended to every ciphertext
+pub(crate) const TAG_LENGTH: usize = 16;
+
+/// Size of the plaintext chunks encrypted entries are split into, from spec version 110
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Context of the prehashed Ed25519 signatures over entries, from spec version 120
+pub(crate) const SIGNATURE_CONTEXT: &[u8] = b"vach-entry";
+
+/// A symmetric aes256-gcm key, used to encrypt and decrypt entries
+pub type EncryptionKey = [u8; crate::ENCRYPTION_KEY_LENGTH];
+
+/// Parameters for deriving an [`EncryptionKey`] from a passphrase using Argon2id, stored in the archive's header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+	/// Memory cost, in KiB
+	pub memory: u32,
+	/// Number of passes over the memory
+	pub iterations: u32,
+	/// Degree of parallelism
+	pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+	fn default() -> KdfParams {
+		KdfParams {
+			memory: argon2::Params::DEFAULT_M_COST,
+			iterations: argon2::Params::DEFAULT_T_COST,
+			parallelism: argon2::Params::DEFAULT_P_COST,
+		}
+	}
+}
+
+impl KdfParams {
+	/// Derive an [`EncryptionKey`] from a passphrase and salt
+	pub(crate) fn derive_key(
+		&self,
+		passphrase: &[u8],
+		salt: &[u8],
+	) -> InternalResult<EncryptionKey> {
+		let params = argon2::Params::new(self.memory, self.iterations, self.parallelism, Some(crate::ENCRYPTION_KEY_LENGTH)).map_err(InternalError::KeyDerivationError)?;
+		let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+		let mut key = [0u8; crate::ENCRYPTION_KEY_LENGTH];
+		argon2.hash_password_into(passphrase, salt, &mut key).map_err(InternalError::KeyDerivationError)?;
+
+		Ok(key)
+	}
+}
+
+/// Generate a random salt for [`KdfParams::derive_key`]
+pub(crate) fn gen_salt() -> [u8; crate::SALT_LENGTH] {
+	let mut salt = [0u8; crate::SALT_LENGTH];
+	aes_gcm::aead::rand_core::RngCore::fill_bytes(&mut OsRng, &mut salt);
+	salt
+}
+
+use crate::global::error::{InternalError, InternalResult};
+
+/// An archive's content key, wrapped for a single recipient. Uses an ephemeral X25519 key agreement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WrappedKey {
+	pub(crate) recipient: [u8; crate::RECIPIENT_KEY_LENGTH],
+	ephemeral: [u8; crate::RECIPIENT_KEY_LENGTH],
+	nonce: [u8; crate::NONCE_LENGTH],
+	ciphertext: [u8; crate::ENCRYPTION_KEY_LENGTH + TAG_LENGTH],
+}
+
+impl WrappedKey {
+	// 32(recipient) + 32(ephemeral key) + 12(nonce) + 32(key) + 16(tag)
+	pub(crate) const SIZE: usize = 2 * crate::RECIPIENT_KEY_LENGTH + crate::NONCE_LENGTH + crate::ENCRYPTION_KEY_LENGTH + TAG_LENGTH;
+
+	/// Derives the key used to wrap the content key, from the shared secret and both public keys
+	fn wrapping_key(
+		shared: &x25519_dalek::SharedSecret,
+		ephemeral: &RecipientKey,
+		recipient: &RecipientKey,
+	) -> InternalResult<EncryptionKey> {
+		// reject low-order points, which would leave the shared secret predictable
+		if !shared.was_contributory() {
+			return Err(InternalError::ParseError("Invalid recipient public key".to_string()));
+		}
+
+		let digest = Sha256::new()
+			.chain_update(b"vach-recipient-key")
+			.chain_update(shared.as_bytes())
+			.chain_update(ephemeral.as_bytes())
+			.chain_update(recipient.as_bytes())
+			.finalize();
+
+		Ok(digest.into())
+	}
+
+	pub(crate) fn wrap(
+		key: &EncryptionKey,
+		recipient: &RecipientKey,
+	) -> InternalResult<WrappedKey> {
+		let secret = RecipientSecret::random_from_rng(OsRng);
+		let ephemeral = RecipientKey::from(&secret);
+
+		let wrapping_key = WrappedKey::wrapping_key(&secret.diffie_hellman(recipient), &ephemeral, recipient)?;
+		let (ciphertext, nonce) = Encryptor::new(&wrapping_key).encrypt(key, &[])?;
+
+		Ok(WrappedKey {
+			recipient: recipient.to_bytes(),
+			ephemeral: ephemeral.to_bytes(),
+			nonce,
+			ciphertext: ciphertext.try_into().unwrap(),
+		})
+	}
+
+	pub(crate) fn unwrap(
+		&self,
+		secret: &RecipientSecret,
+	) -> InternalResult<EncryptionKey> {
+		let ephemeral = RecipientKey::from(self.ephemeral);
+		let recipient = RecipientKey::from(secret);
+
+		let wrapping_key = WrappedKey::wrapping_key(&secret.diffie_hellman(&ephemeral), &ephemeral, &recipient)?;
+		let key = Encryptor::new(&wrapping_key).decrypt(&self.ciphertext, &self.nonce, &[])?;
+
+		Ok(key.try_into().unwrap())
+	}
+
+	/// Reads a recipients section, a `u32` count followed by that many [`WrappedKey`]s
+	pub(crate) fn read_section<T: std::io::Read>(mut handle: T) -> InternalResult<Vec<WrappedKey>> {
+		let mut count = [0u8; 4];
+		handle.read_exact(&mut count)?;
+
+		let count = u32::from_le_bytes(count) as usize;
+		let mut keys = Vec::with_capacity(count.min(1024));
+
+		for _ in 0..count {
+			let mut buffer = [0u8; WrappedKey::SIZE];
+			handle.read_exact(&mut buffer)?;
+
+			keys.push(WrappedKey {
+				recipient: buffer[0..32].try_into().unwrap(),
+				ephemeral: buffer[32..64].try_into().unwrap(),
+				nonce: buffer[64..76].try_into().unwrap(),
+				ciphertext: buffer[76..].try_into().unwrap(),
+			});
+		}
+
+		Ok(keys)
+	}
+
+	/// Reads a recipients section and unwraps the encryption key from the entry belonging to `secret`
+	pub(crate) fn open_section<T: std::io::Read>(
+		handle: T,
+		secret: &RecipientSecret,
+	) -> InternalResult<EncryptionKey> {
+		let public = RecipientKey::from(secret);
+
+		WrappedKey::read_section(handle)?
+			.iter()
+			.find(|wrapped| wrapped.recipient == public.to_bytes())
+			.ok_or(InternalError::NoKeypairError)?
+			.unwrap(secret)
+	}
+
+	/// Serializes a recipients section, see [`WrappedKey::read_section`]
+	pub(crate) fn section_bytes(keys: &[WrappedKey]) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(4 + keys.len() * WrappedKey::SIZE);
+		buffer.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+
+		for key in keys {
+			buffer.extend_from_slice(&key.recipient);
+			buffer.extend_from_slice(&key.ephemeral);
+			buffer.extend_from_slice(&key.nonce);
+			buffer.extend_from_slice(&key.ciphertext);
+		}
+
+		buffer
+	}
+}
+
+/// Encryption - Decryption, A convenient wrapper around [`aes`](aes_gcm) encryption and decryption
+pub(crate) struct Encryptor {
+	cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for Encryptor {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		f.debug_struct("Encryptor").field("cipher", &"<Aes256Gcm>").finish()
+	}
+}
+
+impl Encryptor {
+	pub(crate) fn new(key: &EncryptionKey) -> Encryptor {
+		Encryptor {
+			cipher: Aes256Gcm::new(key.into()),
+		}
+	}
+
+	/// Encrypts `data` under a random nonce, authenticating it together with the associated data `aad`
+	pub(crate) fn encrypt(
+		&self,
+		data: &[u8],
+		aad: &[u8],
+	) -> InternalResult<(Vec<u8>, [u8; crate::NONCE_LENGTH])> {
+		let nonce = Aes256Gcm::generate_nonce(OsRng);
+
+		self.cipher.encrypt(&nonce, Payload { msg: data, aad }).map_err(InternalError::CryptoError).map(|res| (res, *nonce.as_array().unwrap()))
+	}
+
+	pub(crate) fn decrypt(
+		&self,
+		data: &[u8],
+		nonce: &[u8; crate::NONCE_LENGTH],
+		aad: &[u8],
+	) -> InternalResult<Vec<u8>> {
+		let nonce = aes_gcm::Nonce::from_slice(nonce);
+		self.cipher.decrypt(nonce, Payload { msg: data, aad }).map_err(InternalError::CryptoError)
+	}
+}
+
+// Chunked encryption, see [`ChunkEncryptor`]
+impl Encryptor {
+	fn chunk_nonce(
+		nonce: &[u8; crate::NONCE_LENGTH],
+		index: u32,
+		last: bool,
+	) -> [u8; crate::NONCE_LENGTH] {
+		let mut chunk_nonce = *nonce;
+		chunk_nonce[7..11].copy_from_slice(&index.to_be_bytes());
+		chunk_nonce[11] = last as u8;
+		chunk_nonce
+	}
+
+	pub(crate) fn decrypt_chunk(
+		&self,
+		chunk: &[u8],
+		nonce: &[u8; crate::NONCE_LENGTH],
+		index: u32,
+		last: bool,
+		aad: &[u8],
+	) -> InternalResult<Vec<u8>> {
+		let chunk_nonce = Encryptor::chunk_nonce(nonce, index, last);
+		self.cipher.decrypt((&chunk_nonce).into(), Payload { msg: chunk, aad }).map_err(InternalError::CryptoError)
+	}
+
+	/// Decrypts an entry encrypted by a [`ChunkEncryptor`]
+	pub(crate) fn decrypt_chunks(
+		&self,
+		data: &[u8],
+		nonce: &[u8; crate::NONCE_LENGTH],
+		aad: &[u8],
+	) -> InternalResult<Vec<u8>> {
+		let mut output = Vec::with_capacity(data.len());
+
+		let mut remaining = data;
+		for index in 0..=u32::MAX {
+			let last = remaining.len() < CHUNK_SIZE + TAG_LENGTH;
+			let (chunk, rest) = remaining.split_at(remaining.len().min(CHUNK_SIZE + TAG_LENGTH));
+
+			output.extend_from_slice(&self.decrypt_chunk(chunk, nonce, index, last, aad)?);
+
+			if last {
+				return Ok(output);
+			}
+
+			remaining = rest;
+		}
+
+		Err(InternalError::OtherError("Entry has too many chunks".into()))
+	}
+}
+
+/// Encrypts an entry as a STREAM of [`CHUNK_SIZE`] chunks as it's written, so it can be processed with bounded memory.
+/// Each chunk's nonce is the first 7 bytes of the entry's nonce, followed by the chunk's index and a final-chunk marker.
+/// The final chunk is always shorter than a full one, and may be empty, so truncation and extension are caught
+pub(crate) struct ChunkEncryptor<'a> {
+	encryptor: &'a Encryptor,
+	nonce: [u8; crate::NONCE_LENGTH],
+	aad: Vec<u8>,
+
+	index: u32,
+	buffer: Vec<u8>,
+}
+
+impl<'a> ChunkEncryptor<'a> {
+	pub(crate) fn new(
+		encryptor: &'a Encryptor,
+		aad: Vec<u8>,
+	) -> ChunkEncryptor<'a> {
+		ChunkEncryptor {
+			encryptor,
+			nonce: *Aes256Gcm::generate_nonce(OsRng).as_array().unwrap(),
+			aad,
+			index: 0,
+			buffer: Vec::with_capacity(CHUNK_SIZE),
+		}
+	}
+
+	/// Encrypts the buffered chunk into `output`
+	fn seal<W: io::Write>(
+		&mut self,
+		last: bool,
+		output: &mut W,
+	) -> io::Result<()> {
+		let chunk_nonce = Encryptor::chunk_nonce(&self.nonce, self.index, last);
+		let payload = Payload { msg: &self.buffer, aad: &self.aad };
+
+		let ciphertext = self.encryptor.cipher.encrypt((&chunk_nonce).into(), payload).map_err(|err| io::Error::other(InternalError::CryptoError(err)))?;
+		output.write_all(&ciphertext)?;
+
+		self.buffer.clear();
+		self.index = self.index.checked_add(1).ok_or_else(|| io::Error::other("Entry is too large to be encrypted"))?;
+
+		Ok(())
+	}
+
+	/// Buffers `data`, writing out every chunk that fills up. A full chunk is never the last
+	pub(crate) fn update<W: io::Write>(
+		&mut self,
+		mut data: &[u8],
+		output: &mut W,
+	) -> io::Result<()> {
+		while !data.is_empty() {
+			let length = (CHUNK_SIZE - self.buffer.len()).min(data.len());
+			self.buffer.extend_from_slice(&data[..length]);
+			data = &data[length..];
+
+			if self.buffer.len() == CHUNK_SIZE {
+				self.seal(false, output)?;
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Writes out the final, possibly empty, chunk and returns the entry's nonce
+	pub(crate) fn finish<W: io::Write>(
+		mut self,
+		output: &mut W,
+	) -> io::Result<[u8; crate::NONCE_LENGTH]> {
+		self.seal(true, output)?;
+		Ok(self.nonce)
+	}
+}
+
+/// Decrypts a chunked entry one chunk at a time as bytes are pulled from it, see [`ChunkEncryptor`] for the layout
+pub(crate) struct DecryptingReader<'a, R> {
+	inner: R,
+	decryptor: &'a Encryptor,
+	nonce: [u8; crate::NONCE_LENGTH],
+	id: Arc<str>,
+	aad: Vec<u8>,
+
+	index: u32,
+	buffer: Vec<u8>,
+	position: usize,
+	finished: bool,
+}
+
+impl<'a, R: Read> DecryptingReader<'a, R> {
+	pub(crate) fn new(
+		inner: R,
+		decryptor: &'a Encryptor,
+		nonce: [u8; crate::NONCE_LENGTH],
+		id: Arc<str>,
+		aad: Vec<u8>,
+	) -> DecryptingReader<'a, R> {
+		DecryptingReader {
+			inner,
+			decryptor,
+			nonce,
+			id,
+			aad,
+			index: 0,
+			buffer: Vec::new(),
+			position: 0,
+			finished: false,
+		}
+	}
+
+	fn next_chunk(&mut self) -> io::Result<()> {
+		let mut chunk = Vec::with_capacity(CHUNK_SIZE + TAG_LENGTH);
+		(&mut self.inner).take((CHUNK_SIZE + TAG_LENGTH) as u64).read_to_end(&mut chunk)?;
+
+		let last = chunk.len() < CHUNK_SIZE + TAG_LENGTH;
+		let authentication_error = || io::Error::new(io::ErrorKind::InvalidData, InternalError::EntryAuthenticationError(self.id.to_string()));
+
+		self.buffer = self.decryptor.decrypt_chunk(&chunk, &self.nonce, self.index, last, &self.aad).map_err(|_| authentication_error())?;
+		self.position = 0;
+		self.finished = last;
+		self.index = match last {
+			true => self.index,
+			false => self.index.checked_add(1).ok_or_else(authentication_error)?,
+		};
+
+		Ok(())
+	}
+}
+
+impl<R: Read> Read for DecryptingReader<'_, R> {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		while self.position == self.buffer.len() {
+			if self.finished {
+				return Ok(0);
+			}
+
+			self.next_chunk()?;
+		}
+
+		let length = buf.len().min(self.buffer.len() - self.position);
+		buf[..length].copy_from_slice(&self.buffer[self.position..self.position + length]);
+		self.position += length;
+
+		Ok(length)
+	}
+}
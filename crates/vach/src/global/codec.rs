@@ -0,0 +1,70 @@
+#![cfg(feature = "compression")]
+#![cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+
+use std::{
+	collections::HashMap,
+	fmt,
+	io::{self, Read, Write},
+	sync::Arc,
+};
+
+/// A user-defined compression codec, plugged in through a [`CodecRegistry`].
+/// Entries compressed with a codec store it's numeric ID in their registry entry, so the same ID must be registered when loading them
+pub trait Codec: Send + Sync {
+	/// Compresses all of `data` into `output`
+	fn compress(
+		&self,
+		data: &mut dyn Read,
+		output: &mut dyn Write,
+	) -> io::Result<()>;
+
+	/// Wraps compressed `data` in a reader that yields the decompressed bytes
+	fn decompress<'a>(
+		&self,
+		data: Box<dyn Read + 'a>,
+	) -> io::Result<Box<dyn Read + 'a>>;
+}
+
+/// Maps numeric codec IDs to [`Codec`]s. Supplied to [`BuilderConfig`](crate::builder::BuilderConfig::codecs) when building,
+/// and to [`ArchiveConfig`](crate::archive::ArchiveConfig::codecs) when loading.
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+	codecs: HashMap<u16, Arc<dyn Codec>>,
+}
+
+impl CodecRegistry {
+	/// Registers a [`Codec`] under an ID, replacing any codec previously registered under it
+	pub fn register(
+		mut self,
+		id: u16,
+		codec: impl Codec + 'static,
+	) -> Self {
+		self.codecs.insert(id, Arc::new(codec));
+		self
+	}
+
+	/// Fetches the [`Codec`] registered under an ID
+	pub fn get(
+		&self,
+		id: u16,
+	) -> Option<&dyn Codec> {
+		self.codecs.get(&id).map(Arc::as_ref)
+	}
+
+	/// Whether no codecs are registered
+	pub fn is_empty(&self) -> bool {
+		self.codecs.is_empty()
+	}
+}
+
+impl fmt::Debug for CodecRegistry {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		let mut ids = self.codecs.keys().collect::<Vec<_>>();
+		ids.sort();
+
+		f.debug_struct("CodecRegistry").field("ids", &ids).finish()
+	}
+}
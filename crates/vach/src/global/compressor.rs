@@ -1,114 +1,228 @@
-#![cfg(feature = "compression")]
-#![cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-
-use std::io::{self, Read, Write};
-
-use super::error::*;
-use crate::prelude::Flags;
-
-use brotli;
-use lz4_flex as lz4;
-use snap;
-
-#[derive(Debug)]
-/// Exported utility compressor used by `vach`
-#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-pub struct Compressor<T: Read> {
-	data: T,
-}
-
-impl<T: Read> Compressor<T> {
-	/// Construct a new compressor over a read handle
-	pub fn new(data: T) -> Compressor<T> {
-		Compressor { data }
-	}
-	/// Pass in a compression algorithm to use, sit back and let the compressor do it's job
-	pub fn compress(
-		&mut self,
-		algo: CompressionAlgorithm,
-		output: &mut dyn Write,
-	) -> InternalResult {
-		match algo {
-			CompressionAlgorithm::LZ4 => {
-				let mut compressor = lz4::frame::FrameEncoder::new(output);
-				io::copy(&mut self.data, &mut compressor)?;
-				compressor.finish()?;
-
-				Ok(())
-			},
-			CompressionAlgorithm::Snappy => {
-				let mut compressor = snap::read::FrameEncoder::new(&mut self.data);
-				io::copy(&mut compressor, output)?;
-
-				Ok(())
-			},
-			CompressionAlgorithm::Brotli(quality) if quality < 12 && quality > 0 => {
-				let mut compressor = brotli::CompressorReader::new(&mut self.data, 4096, quality, 21u32);
-				io::copy(&mut compressor, output)?;
-
-				Ok(())
-			},
-			CompressionAlgorithm::Brotli(_) => Err(InternalError::OtherError("Maximum Brotli compression level is 11 and minimum is 1".into())),
-		}
-	}
-
-	/// Pass in a compression algorithm to use, sit back and let the decompressor do it's job. That is if the compressed data *is* compressed with the adjacent algorithm
-	/// Contains the number of bytes decompressed from the source
-	pub fn decompress(
-		&mut self,
-		algo: CompressionAlgorithm,
-		output: &mut Vec<u8>,
-	) -> InternalResult<usize> {
-		match algo {
-			CompressionAlgorithm::LZ4 => {
-				let mut rdr = lz4::frame::FrameDecoder::new(&mut self.data);
-				rdr.read_to_end(output).map_err(InternalError::IOError)
-			},
-			CompressionAlgorithm::Snappy => {
-				let mut rdr = snap::read::FrameDecoder::new(&mut self.data);
-				rdr.read_to_end(output).map_err(InternalError::IOError)
-			},
-			CompressionAlgorithm::Brotli(_) => {
-				let mut rdr = brotli::Decompressor::new(&mut self.data, 4096);
-				rdr.read_to_end(output).map_err(InternalError::IOError)
-			},
-		}
-	}
-}
-
-/// Allows the user to specify which of three `Compression Algorithms` to use.
-#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-#[derive(Clone, Copy, Debug, Default)]
-pub enum CompressionAlgorithm {
-	/// Uses [snappy](https://crates.io/crates/snap) for a well balanced compression experienced
-	Snappy,
-	/// Uses [LZ4](https://crates.io/crates/lz4_flex) for very fast decompression with average compression ratios
-	#[default]
-	LZ4,
-	/// Uses [brotli](https://crates.io/crates/brotli) for higher compression ratios but *much* slower compression speed
-	/// Allows one to specify the quality of the compression, from 1-11. (9 Recommended, 11 for extra compression)
-	Brotli(u32),
-}
-
-impl std::fmt::Display for CompressionAlgorithm {
-	fn fmt(
-		&self,
-		f: &mut std::fmt::Formatter<'_>,
-	) -> std::fmt::Result {
-		match self {
-			CompressionAlgorithm::Snappy => write!(f, "Snappy"),
-			CompressionAlgorithm::LZ4 => write!(f, "LZ4"),
-			CompressionAlgorithm::Brotli(_) => write!(f, "Brotli"),
-		}
-	}
-}
-
-impl From<CompressionAlgorithm> for u32 {
-	fn from(algo: CompressionAlgorithm) -> Self {
-		match algo {
-			CompressionAlgorithm::Snappy => Flags::SNAPPY_COMPRESSED,
-			CompressionAlgorithm::LZ4 => Flags::LZ4_COMPRESSED,
-			CompressionAlgorithm::Brotli(_) => Flags::BROTLI_COMPRESSED,
-		}
-	}
-}
+#![cfg(feature = "compression")]
+#![cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+
+use std::io::{self, Read, Write};
+
+use super::error::*;
+use crate::prelude::Flags;
+
+use brotli;
+use lz4_flex as lz4;
+use snap;
+use zstd;
+
+#[derive(Debug)]
+/// Exported utility compressor used by `vach`
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+pub struct Compressor<T: Read> {
+	data: T,
+}
+
+impl<T: Read> Compressor<T> {
+	/// Construct a new compressor over a read handle
+	pub fn new(data: T) -> Compressor<T> {
+		Compressor { data }
+	}
+	/// Pass in a compression algorithm to use, sit back and let the compressor do it's job
+	pub fn compress(
+		&mut self,
+		algo: CompressionAlgorithm,
+		output: &mut dyn Write,
+	) -> InternalResult {
+		match algo {
+			CompressionAlgorithm::LZ4 => {
+				let mut compressor = lz4::frame::FrameEncoder::new(output);
+				io::copy(&mut self.data, &mut compressor)?;
+				compressor.finish()?;
+
+				Ok(())
+			},
+			CompressionAlgorithm::Snappy => {
+				let mut compressor = snap::read::FrameEncoder::new(&mut self.data);
+				io::copy(&mut compressor, output)?;
+
+				Ok(())
+			},
+			CompressionAlgorithm::Brotli(quality) if quality < 12 && quality > 0 => {
+				let mut compressor = brotli::CompressorReader::new(&mut self.data, 4096, quality, 21u32);
+				io::copy(&mut compressor, output)?;
+
+				Ok(())
+			},
+			CompressionAlgorithm::Brotli(_) => Err(InternalError::OtherError("Maximum Brotli compression level is 11 and minimum is 1".into())),
+			CompressionAlgorithm::Zstd(level) if zstd::compression_level_range().contains(&level) => {
+				let mut compressor = zstd::stream::write::Encoder::new(output, level)?;
+				io::copy(&mut self.data, &mut compressor)?;
+				compressor.finish()?;
+
+				Ok(())
+			},
+			CompressionAlgorithm::Zstd(_) => Err(zstd_level_error()),
+		}
+	}
+
+	/// Compresses the data with Zstd, against a dictionary trained with [`train_dictionary`]
+	pub(crate) fn compress_with_dictionary(
+		&mut self,
+		level: i32,
+		dictionary: &[u8],
+		output: &mut dyn Write,
+	) -> InternalResult {
+		if !zstd::compression_level_range().contains(&level) {
+			return Err(zstd_level_error());
+		}
+
+		let mut compressor = zstd::stream::write::Encoder::with_dictionary(output, level, dictionary)?;
+		io::copy(&mut self.data, &mut compressor)?;
+		compressor.finish()?;
+
+		Ok(())
+	}
+
+	/// Consumes the [`Compressor`], returning a streaming decoder that decompresses the underlying data as bytes are pulled from it.
+	/// Memory usage is bounded by the decoder's internal buffers, regardless of the size of the decompressed data
+	pub fn into_decoder<'a>(
+		self,
+		algo: CompressionAlgorithm,
+	) -> InternalResult<Box<dyn Read + 'a>>
+	where
+		T: 'a,
+	{
+		match algo {
+			CompressionAlgorithm::LZ4 => Ok(Box::new(lz4::frame::FrameDecoder::new(self.data))),
+			CompressionAlgorithm::Snappy => Ok(Box::new(snap::read::FrameDecoder::new(self.data))),
+			CompressionAlgorithm::Brotli(_) => Ok(Box::new(brotli::Decompressor::new(self.data, 4096))),
+			CompressionAlgorithm::Zstd(_) => Ok(Box::new(zstd::stream::read::Decoder::new(self.data)?)),
+		}
+	}
+
+	/// Same as [`into_decoder`](Compressor::into_decoder), for entries compressed against the archive's [`Dictionary`]
+	pub(crate) fn into_dictionary_decoder<'a>(
+		self,
+		dictionary: &'a Dictionary,
+	) -> InternalResult<Box<dyn Read + 'a>>
+	where
+		T: 'a,
+	{
+		let decoder = zstd::stream::read::Decoder::with_prepared_dictionary(io::BufReader::new(self.data), &dictionary.0)?;
+		Ok(Box::new(decoder))
+	}
+
+	/// Pass in a compression algorithm to use, sit back and let the decompressor do it's job. That is if the compressed data *is* compressed with the adjacent algorithm
+	/// Contains the number of bytes decompressed from the source
+	pub fn decompress(
+		&mut self,
+		algo: CompressionAlgorithm,
+		output: &mut Vec<u8>,
+	) -> InternalResult<usize> {
+		match algo {
+			CompressionAlgorithm::LZ4 => {
+				let mut rdr = lz4::frame::FrameDecoder::new(&mut self.data);
+				rdr.read_to_end(output).map_err(InternalError::IOError)
+			},
+			CompressionAlgorithm::Snappy => {
+				let mut rdr = snap::read::FrameDecoder::new(&mut self.data);
+				rdr.read_to_end(output).map_err(InternalError::IOError)
+			},
+			CompressionAlgorithm::Brotli(_) => {
+				let mut rdr = brotli::Decompressor::new(&mut self.data, 4096);
+				rdr.read_to_end(output).map_err(InternalError::IOError)
+			},
+			CompressionAlgorithm::Zstd(_) => {
+				let mut rdr = zstd::stream::read::Decoder::new(&mut self.data)?;
+				rdr.read_to_end(output).map_err(InternalError::IOError)
+			},
+		}
+	}
+}
+
+fn zstd_level_error() -> InternalError {
+	let range = zstd::compression_level_range();
+	InternalError::OtherError(format!("Zstd compression levels range from {} to {}", range.start(), range.end()).into())
+}
+
+/// Trains a Zstd dictionary of at most `max_size` bytes from samples of leaf data.
+/// Returns [`None`] if the samples are too few or too small to train on
+pub(crate) fn train_dictionary(
+	samples: &[&[u8]],
+	max_size: usize,
+) -> Option<Vec<u8>> {
+	zstd::dict::from_samples(samples, max_size).ok()
+}
+
+/// A Zstd dictionary read from an archive's header, digested once and shared by every entry compressed against it
+pub(crate) struct Dictionary(zstd::dict::DecoderDictionary<'static>);
+
+impl Dictionary {
+	pub(crate) fn new(data: &[u8]) -> Dictionary {
+		Dictionary(zstd::dict::DecoderDictionary::copy(data))
+	}
+}
+
+impl std::fmt::Debug for Dictionary {
+	fn fmt(
+		&self,
+		f: &mut std::fmt::Formatter<'_>,
+	) -> std::fmt::Result {
+		f.debug_struct("Dictionary").finish_non_exhaustive()
+	}
+}
+
+/// Allows the user to specify which of four `Compression Algorithms` to use.
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub enum CompressionAlgorithm {
+	/// Uses [snappy](https://crates.io/crates/snap) for a well balanced compression experienced
+	Snappy,
+	/// Uses [LZ4](https://crates.io/crates/lz4_flex) for very fast decompression with average compression ratios
+	#[default]
+	LZ4,
+	/// Uses [brotli](https://crates.io/crates/brotli) for higher compression ratios but *much* slower compression speed
+	/// Allows one to specify the quality of the compression, from 1-11. (9 Recommended, 11 for extra compression)
+	Brotli(u32),
+	/// Uses [Zstandard](https://crates.io/crates/zstd) for high compression ratios at fast speeds, decompressing quickly at all levels.
+	/// Allows one to specify the compression level, from 1-22 (3 is zstd's default, 19+ are very slow). Negative levels trade ratio for speed
+	Zstd(i32),
+}
+
+impl CompressionAlgorithm {
+	/// Determines which algorithm was used to compress an entry, from it's [`Flags`]
+	pub(crate) fn from_flags(flags: Flags) -> Option<CompressionAlgorithm> {
+		if flags.contains(Flags::LZ4_COMPRESSED) {
+			Some(CompressionAlgorithm::LZ4)
+		} else if flags.contains(Flags::BROTLI_COMPRESSED) {
+			Some(CompressionAlgorithm::Brotli(0))
+		} else if flags.contains(Flags::SNAPPY_COMPRESSED) {
+			Some(CompressionAlgorithm::Snappy)
+		} else if flags.contains(Flags::ZSTD_COMPRESSED) {
+			Some(CompressionAlgorithm::Zstd(0))
+		} else {
+			None
+		}
+	}
+}
+
+impl std::fmt::Display for CompressionAlgorithm {
+	fn fmt(
+		&self,
+		f: &mut std::fmt::Formatter<'_>,
+	) -> std::fmt::Result {
+		match self {
+			CompressionAlgorithm::Snappy => write!(f, "Snappy"),
+			CompressionAlgorithm::LZ4 => write!(f, "LZ4"),
+			CompressionAlgorithm::Brotli(_) => write!(f, "Brotli"),
+			CompressionAlgorithm::Zstd(_) => write!(f, "Zstd"),
+		}
+	}
+}
+
+impl From<CompressionAlgorithm> for u32 {
+	fn from(algo: CompressionAlgorithm) -> Self {
+		match algo {
+			CompressionAlgorithm::Snappy => Flags::SNAPPY_COMPRESSED,
+			CompressionAlgorithm::LZ4 => Flags::LZ4_COMPRESSED,
+			CompressionAlgorithm::Brotli(_) => Flags::BROTLI_COMPRESSED,
+			CompressionAlgorithm::Zstd(_) => Flags::ZSTD_COMPRESSED,
+		}
+	}
+}
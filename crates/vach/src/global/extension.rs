@@ -0,0 +1,117 @@
+use super::error::*;
+use std::io::Read;
+
+#[cfg(feature = "crypto")]
+use crate::crypto;
+
+/// Optional sections of the header, following the capacity. Introduced in spec version 80.
+/// Each is laid out as a `u16` kind, a `u32` length and then the payload.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HeaderExtension {
+	/// Salt and parameters of the KDF used to derive the encryption key from a passphrase
+	#[cfg(feature = "crypto")]
+	Passphrase {
+		params: crypto::KdfParams,
+		salt: [u8; crate::SALT_LENGTH],
+	},
+	/// Location of the section holding the content key, wrapped for each recipient. Introduced in spec version 90.
+	/// The section sits after the leaves so it can be rewritten without touching the header or registry
+	Recipients { offset: u64 },
+	/// A Zstd dictionary trained from the archive's small leaves, entries flagged [`DICTIONARY_COMPRESSED`](crate::prelude::Flags::DICTIONARY_COMPRESSED)
+	/// were compressed against it. Introduced in spec version 140
+	Dictionary(Vec<u8>),
+	/// Location of the registry, which otherwise follows the header. Set once leaves are appended to the archive, see [`append`](crate::builder::append).
+	/// Introduced in spec version 200
+	Registry { offset: u64 },
+	/// Extensions not known to this implementation, or whose cargo feature is disabled, are kept as-is
+	Unknown(u16, Vec<u8>),
+}
+
+impl HeaderExtension {
+	// 2(kind) + 4(length)
+	pub(crate) const CONSTANT: usize = 6;
+
+	#[cfg(feature = "crypto")]
+	pub(crate) const PASSPHRASE: u16 = 1;
+	pub(crate) const RECIPIENTS: u16 = 2;
+	pub(crate) const DICTIONARY: u16 = 3;
+	pub(crate) const REGISTRY: u16 = 4;
+
+	/// The number of bytes taken up by the serialized extension
+	pub(crate) fn size(&self) -> usize {
+		HeaderExtension::CONSTANT + self.payload().len()
+	}
+
+	fn kind(&self) -> u16 {
+		match self {
+			#[cfg(feature = "crypto")]
+			HeaderExtension::Passphrase { .. } => HeaderExtension::PASSPHRASE,
+			HeaderExtension::Recipients { .. } => HeaderExtension::RECIPIENTS,
+			HeaderExtension::Dictionary(_) => HeaderExtension::DICTIONARY,
+			HeaderExtension::Registry { .. } => HeaderExtension::REGISTRY,
+			HeaderExtension::Unknown(kind, _) => *kind,
+		}
+	}
+
+	fn payload(&self) -> Vec<u8> {
+		match self {
+			#[cfg(feature = "crypto")]
+			HeaderExtension::Passphrase { params, salt } => {
+				let mut buffer = Vec::with_capacity(12 + crate::SALT_LENGTH);
+				buffer.extend_from_slice(&params.memory.to_le_bytes());
+				buffer.extend_from_slice(&params.iterations.to_le_bytes());
+				buffer.extend_from_slice(&params.parallelism.to_le_bytes());
+				buffer.extend_from_slice(salt);
+				buffer
+			},
+			HeaderExtension::Recipients { offset } | HeaderExtension::Registry { offset } => offset.to_le_bytes().to_vec(),
+			HeaderExtension::Dictionary(dictionary) => dictionary.clone(),
+			HeaderExtension::Unknown(_, payload) => payload.clone(),
+		}
+	}
+
+	pub(crate) fn from_handle<T: Read>(mut handle: T) -> InternalResult<HeaderExtension> {
+		let mut buffer = [0u8; HeaderExtension::CONSTANT];
+		handle.read_exact(&mut buffer)?;
+
+		let kind = u16::from_le_bytes([buffer[0], buffer[1]]);
+		let length = u32::from_le_bytes(buffer[2..6].try_into().unwrap());
+
+		let mut payload = Vec::new();
+		handle.take(length as u64).read_to_end(&mut payload)?;
+
+		if payload.len() != length as usize {
+			return Err(InternalError::IOError(std::io::ErrorKind::UnexpectedEof.into()));
+		}
+
+		match kind {
+			#[cfg(feature = "crypto")]
+			HeaderExtension::PASSPHRASE if payload.len() == 12 + crate::SALT_LENGTH => Ok(HeaderExtension::Passphrase {
+				params: crypto::KdfParams {
+					memory: u32::from_le_bytes(payload[0..4].try_into().unwrap()),
+					iterations: u32::from_le_bytes(payload[4..8].try_into().unwrap()),
+					parallelism: u32::from_le_bytes(payload[8..12].try_into().unwrap()),
+				},
+				salt: payload[12..].try_into().unwrap(),
+			}),
+			HeaderExtension::RECIPIENTS if payload.len() == 8 => Ok(HeaderExtension::Recipients {
+				offset: u64::from_le_bytes(payload[..].try_into().unwrap()),
+			}),
+			HeaderExtension::REGISTRY if payload.len() == 8 => Ok(HeaderExtension::Registry {
+				offset: u64::from_le_bytes(payload[..].try_into().unwrap()),
+			}),
+			HeaderExtension::DICTIONARY => Ok(HeaderExtension::Dictionary(payload)),
+			_ => Ok(HeaderExtension::Unknown(kind, payload)),
+		}
+	}
+
+	pub(crate) fn to_bytes(&self) -> Vec<u8> {
+		let payload = self.payload();
+
+		let mut buffer = Vec::with_capacity(HeaderExtension::CONSTANT + payload.len());
+		buffer.extend_from_slice(&self.kind().to_le_bytes());
+		buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+		buffer.extend_from_slice(&payload);
+		buffer
+	}
+}
@@ -1,71 +1,184 @@
-use super::{error::*, flags::Flags};
-use std::io::Read;
-
-#[derive(Debug)]
-pub(crate) struct Header {
-	pub(crate) magic: [u8; crate::MAGIC_LENGTH],
-	pub flags: Flags,
-	pub version: u16,
-	pub capacity: u16,
-}
-
-impl Default for Header {
-	#[inline(always)]
-	fn default() -> Header {
-		Header {
-			magic: crate::MAGIC,
-			flags: Flags::default(),
-			version: crate::VERSION,
-			capacity: 0,
-		}
-	}
-}
-
-impl Header {
-	pub const BASE_SIZE: usize = crate::MAGIC_LENGTH + Flags::BYTES + Self::VERSION_SIZE + Self::CAPACITY_SIZE;
-
-	// Data appears in this order
-	pub const VERSION_SIZE: usize = 2;
-	pub const CAPACITY_SIZE: usize = 2;
-
-	/// Validates this Header's MAGIC and ARCHIVE_VERSION
-	pub(crate) fn validate(&self) -> InternalResult {
-		// Validate magic
-		if self.magic != crate::MAGIC {
-			return Err(InternalError::MalformedArchiveSource(self.magic));
-		};
-
-		// Validate version
-		if crate::VERSION != self.version {
-			return Err(InternalError::IncompatibleArchiveVersionError(self.version));
-		};
-
-		Ok(())
-	}
-
-	pub(crate) fn from_handle<T: Read>(mut handle: T) -> InternalResult<Header> {
-		let mut buffer: [u8; Header::BASE_SIZE] = [0u8; Header::BASE_SIZE];
-		handle.read_exact(&mut buffer)?;
-
-		// Construct header
-		Ok(Header {
-			// Read magic, [u8;5]
-			magic: buffer[0..crate::MAGIC_LENGTH].try_into().unwrap(),
-			// Read flags, u32 from [u8;4]
-			flags: Flags::from_bits(u32::from_le_bytes(buffer[crate::MAGIC_LENGTH..9].try_into().unwrap())),
-			// Read version, u16 from [u8;2]
-			version: u16::from_le_bytes(buffer[9..11].try_into().unwrap()),
-			// Read the capacity of the archive, u16 from [u8;2]
-			capacity: u16::from_le_bytes(buffer[11..13].try_into().unwrap()),
-		})
-	}
-
-	pub(crate) fn to_bytes(&self) -> [u8; Header::BASE_SIZE] {
-		let mut buffer: [u8; Header::BASE_SIZE] = [0u8; Header::BASE_SIZE];
-		buffer[0..crate::MAGIC_LENGTH].copy_from_slice(&self.magic);
-		buffer[crate::MAGIC_LENGTH..9].copy_from_slice(&self.flags.bits().to_le_bytes());
-		buffer[9..11].copy_from_slice(&self.version.to_le_bytes());
-		buffer[11..13].copy_from_slice(&self.capacity.to_le_bytes());
-		buffer
-	}
-}
+use super::{error::*, extension::HeaderExtension, flags::Flags};
+use std::io::Read;
+
+#[cfg(feature = "crypto")]
+use crate::crypto;
+
+#[derive(Debug)]
+pub(crate) struct Header {
+	pub(crate) magic: [u8; crate::MAGIC_LENGTH],
+	pub flags: Flags,
+	pub version: u16,
+	pub capacity: u32,
+	/// Optional sections following the capacity, see [`HeaderExtension`]
+	pub extensions: Vec<HeaderExtension>,
+	/// Signature over the header and the whole registry, present if the header has the [`SIGNED_FLAG`](Flags::SIGNED_FLAG)
+	#[cfg(feature = "crypto")]
+	pub signature: Option<crypto::Signature>,
+}
+
+impl Default for Header {
+	#[inline(always)]
+	fn default() -> Header {
+		Header {
+			magic: crate::MAGIC,
+			flags: Flags::default(),
+			version: crate::VERSION,
+			capacity: 0,
+			extensions: Vec::new(),
+			#[cfg(feature = "crypto")]
+			signature: None,
+		}
+	}
+}
+
+impl Header {
+	pub const BASE_SIZE: usize = crate::MAGIC_LENGTH + Flags::BYTES + Self::VERSION_SIZE + Self::CAPACITY_SIZE;
+
+	// Data appears in this order
+	pub const VERSION_SIZE: usize = 2;
+	pub const CAPACITY_SIZE: usize = 4;
+
+	/// Spec version before which the capacity was stored as a `u16`
+	pub const WIDE_CAPACITY_VERSION: u16 = 50;
+	pub const LEGACY_CAPACITY_SIZE: usize = 2;
+
+	/// Spec version from which signed archives carry a signature of the header and registry, right after the header
+	pub const MANIFEST_VERSION: u16 = 60;
+
+	/// Spec version from which entries are encrypted using a dedicated key, rather than one derived from the verifying key
+	#[cfg(feature = "crypto")]
+	pub const ENCRYPTION_KEY_VERSION: u16 = 70;
+
+	/// Spec version from which the capacity is followed by a `u16` count of [`HeaderExtension`]s
+	pub const EXTENSIONS_VERSION: u16 = 80;
+	pub const EXTENSION_COUNT_SIZE: usize = 2;
+
+	/// Spec version from which encrypted entries bind their ID and flags as associated data
+	#[cfg(feature = "crypto")]
+	pub const AAD_VERSION: u16 = 100;
+
+	/// Spec version from which encrypted entries are split into separately authenticated chunks, so they can be streamed
+	#[cfg(feature = "crypto")]
+	pub const CHUNKED_ENCRYPTION_VERSION: u16 = 110;
+
+	/// Spec version from which entries are signed using prehashed Ed25519, so they can be signed as they are streamed
+	#[cfg(feature = "crypto")]
+	pub const PREHASHED_SIGNATURE_VERSION: u16 = 120;
+
+	/// Whether this header is followed by a signature over the header and registry
+	#[inline(always)]
+	pub(crate) fn has_manifest(&self) -> bool {
+		self.version >= Header::MANIFEST_VERSION && self.flags.contains(Flags::SIGNED_FLAG)
+	}
+
+	/// The number of bytes taken up by the header and it's extensions, excluding the manifest signature
+	pub(crate) fn encoded_size(&self) -> usize {
+		if self.version < Header::WIDE_CAPACITY_VERSION {
+			Header::BASE_SIZE - Header::CAPACITY_SIZE + Header::LEGACY_CAPACITY_SIZE
+		} else if self.version < Header::EXTENSIONS_VERSION {
+			Header::BASE_SIZE
+		} else {
+			Header::BASE_SIZE + Header::EXTENSION_COUNT_SIZE + self.extensions.iter().map(HeaderExtension::size).sum::<usize>()
+		}
+	}
+
+	/// Where the registry starts, if moved by a [`Registry`](HeaderExtension::Registry) extension. Otherwise it follows the header and manifest signature
+	pub(crate) fn registry_offset(&self) -> Option<u64> {
+		self.extensions.iter().find_map(|extension| match extension {
+			HeaderExtension::Registry { offset } => Some(*offset),
+			_ => None,
+		})
+	}
+
+	/// Validates this Header's MAGIC and ARCHIVE_VERSION
+	pub(crate) fn validate(&self) -> InternalResult {
+		// Validate magic
+		if self.magic != crate::MAGIC {
+			return Err(InternalError::MalformedArchiveSource(self.magic));
+		};
+
+		// Validate version, any spec version within the supported range can be loaded
+		if !(crate::MIN_VERSION..=crate::VERSION).contains(&self.version) || !self.version.is_multiple_of(10) {
+			return Err(InternalError::IncompatibleArchiveVersionError(self.version));
+		};
+
+		Ok(())
+	}
+
+	pub(crate) fn from_handle<T: Read>(mut handle: T) -> InternalResult<Header> {
+		let mut buffer: [u8; Header::BASE_SIZE] = [0u8; Header::BASE_SIZE];
+
+		// magic, flags and version are laid out the same across versions
+		let (prefix, capacity) = buffer.split_at_mut(Header::BASE_SIZE - Header::CAPACITY_SIZE);
+		handle.read_exact(prefix)?;
+
+		// Read version, u16 from [u8;2]
+		let version = u16::from_le_bytes(prefix[9..11].try_into().unwrap());
+
+		// Read the capacity of the archive, u32 from [u8;4] or u16 from [u8;2] in older versions
+		let capacity = if version < Header::WIDE_CAPACITY_VERSION {
+			handle.read_exact(&mut capacity[..Header::LEGACY_CAPACITY_SIZE])?;
+			u16::from_le_bytes(capacity[..2].try_into().unwrap()) as u32
+		} else {
+			handle.read_exact(capacity)?;
+			u32::from_le_bytes(capacity.try_into().unwrap())
+		};
+
+		// Construct header
+		#[allow(unused_mut)]
+		let mut header = Header {
+			// Read magic, [u8;5]
+			magic: buffer[0..crate::MAGIC_LENGTH].try_into().unwrap(),
+			// Read flags, u32 from [u8;4]
+			flags: Flags::from_bits(u32::from_le_bytes(buffer[crate::MAGIC_LENGTH..9].try_into().unwrap())),
+			version,
+			capacity,
+			extensions: Vec::new(),
+			#[cfg(feature = "crypto")]
+			signature: None,
+		};
+
+		// the rest of the header depends on the version, so it has to be supported
+		header.validate()?;
+
+		// read extensions, if the version has them
+		if version >= Header::EXTENSIONS_VERSION {
+			let mut count = [0u8; Header::EXTENSION_COUNT_SIZE];
+			handle.read_exact(&mut count)?;
+
+			for _ in 0..u16::from_le_bytes(count) {
+				header.extensions.push(HeaderExtension::from_handle(&mut handle)?);
+			}
+		}
+
+		// read the manifest signature, if present. If the `crypto` feature is turned off then the bytes are just read then discarded
+		if header.has_manifest() {
+			let mut sig_bytes: [u8; crate::SIGNATURE_LENGTH] = [0u8; crate::SIGNATURE_LENGTH];
+			handle.read_exact(&mut sig_bytes)?;
+
+			#[cfg(feature = "crypto")]
+			{
+				header.signature = Some(crypto::Signature::from(sig_bytes));
+			}
+		}
+
+		Ok(header)
+	}
+
+	/// Serializes the header and it's extensions, excluding the manifest signature
+	pub(crate) fn to_bytes(&self) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(self.encoded_size());
+		buffer.extend_from_slice(&self.magic);
+		buffer.extend_from_slice(&self.flags.bits().to_le_bytes());
+		buffer.extend_from_slice(&self.version.to_le_bytes());
+		buffer.extend_from_slice(&self.capacity.to_le_bytes());
+
+		buffer.extend_from_slice(&(self.extensions.len() as u16).to_le_bytes());
+		for extension in &self.extensions {
+			buffer.extend_from_slice(&extension.to_bytes());
+		}
+
+		buffer
+	}
+}
@@ -0,0 +1,321 @@
+use super::error::*;
+use std::{collections::BTreeMap, io::Read};
+
+/// A typed value in an entry's [`Metadata`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaValue {
+	/// A boolean
+	Bool(bool),
+	/// A signed integer, eg a modification time
+	Int(i64),
+	/// An unsigned integer
+	UInt(u64),
+	/// A floating point number
+	Float(f64),
+	/// A UTF-8 string, eg a MIME type or source path
+	Str(String),
+	/// Arbitrary bytes, eg a content hash or serialized import settings
+	Bytes(Vec<u8>),
+}
+
+impl MetaValue {
+	const BOOL: u8 = 0;
+	const INT: u8 = 1;
+	const UINT: u8 = 2;
+	const FLOAT: u8 = 3;
+	const STR: u8 = 4;
+	const BYTES: u8 = 5;
+
+	/// The value, if it's a [`Bool`](MetaValue::Bool)
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			MetaValue::Bool(value) => Some(*value),
+			_ => None,
+		}
+	}
+
+	/// The value, if it's an [`Int`](MetaValue::Int)
+	pub fn as_int(&self) -> Option<i64> {
+		match self {
+			MetaValue::Int(value) => Some(*value),
+			_ => None,
+		}
+	}
+
+	/// The value, if it's a [`UInt`](MetaValue::UInt)
+	pub fn as_uint(&self) -> Option<u64> {
+		match self {
+			MetaValue::UInt(value) => Some(*value),
+			_ => None,
+		}
+	}
+
+	/// The value, if it's a [`Float`](MetaValue::Float)
+	pub fn as_float(&self) -> Option<f64> {
+		match self {
+			MetaValue::Float(value) => Some(*value),
+			_ => None,
+		}
+	}
+
+	/// The value, if it's a [`Str`](MetaValue::Str)
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			MetaValue::Str(value) => Some(value),
+			_ => None,
+		}
+	}
+
+	/// The value, if it's [`Bytes`](MetaValue::Bytes)
+	pub fn as_bytes(&self) -> Option<&[u8]> {
+		match self {
+			MetaValue::Bytes(value) => Some(value),
+			_ => None,
+		}
+	}
+
+	fn tag(&self) -> u8 {
+		match self {
+			MetaValue::Bool(_) => MetaValue::BOOL,
+			MetaValue::Int(_) => MetaValue::INT,
+			MetaValue::UInt(_) => MetaValue::UINT,
+			MetaValue::Float(_) => MetaValue::FLOAT,
+			MetaValue::Str(_) => MetaValue::STR,
+			MetaValue::Bytes(_) => MetaValue::BYTES,
+		}
+	}
+
+	fn size(&self) -> usize {
+		match self {
+			MetaValue::Bool(_) => 1,
+			MetaValue::Int(_) | MetaValue::UInt(_) | MetaValue::Float(_) => 8,
+			MetaValue::Str(value) => 4 + value.len(),
+			MetaValue::Bytes(value) => 4 + value.len(),
+		}
+	}
+}
+
+impl From<bool> for MetaValue {
+	fn from(value: bool) -> Self {
+		MetaValue::Bool(value)
+	}
+}
+
+impl From<i64> for MetaValue {
+	fn from(value: i64) -> Self {
+		MetaValue::Int(value)
+	}
+}
+
+impl From<u64> for MetaValue {
+	fn from(value: u64) -> Self {
+		MetaValue::UInt(value)
+	}
+}
+
+impl From<f64> for MetaValue {
+	fn from(value: f64) -> Self {
+		MetaValue::Float(value)
+	}
+}
+
+impl From<&str> for MetaValue {
+	fn from(value: &str) -> Self {
+		MetaValue::Str(value.to_string())
+	}
+}
+
+impl From<String> for MetaValue {
+	fn from(value: String) -> Self {
+		MetaValue::Str(value)
+	}
+}
+
+impl From<&[u8]> for MetaValue {
+	fn from(value: &[u8]) -> Self {
+		MetaValue::Bytes(value.to_vec())
+	}
+}
+
+impl From<Vec<u8>> for MetaValue {
+	fn from(value: Vec<u8>) -> Self {
+		MetaValue::Bytes(value)
+	}
+}
+
+/// Typed key/value attributes attached to an entry, stored in it's registry entry if flagged with [`Flags::METADATA_FLAG`](crate::prelude::Flags::METADATA_FLAG).
+/// So they can be read without fetching the entry's data. Keys are at most [`MAX_KEY_LENGTH`](Metadata::MAX_KEY_LENGTH) bytes long
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+	attributes: BTreeMap<String, MetaValue>,
+}
+
+impl Metadata {
+	/// Maximum size of any key, in bytes
+	pub const MAX_KEY_LENGTH: usize = u8::MAX as usize;
+
+	/// Creates an empty [`Metadata`]
+	pub fn new() -> Metadata {
+		Metadata::default()
+	}
+
+	/// Inserts an attribute, returning the previous value under the same key
+	pub fn insert<K: Into<String>, V: Into<MetaValue>>(
+		&mut self,
+		key: K,
+		value: V,
+	) -> Option<MetaValue> {
+		self.attributes.insert(key.into(), value.into())
+	}
+
+	/// Inserts an attribute, builder style
+	pub fn with<K: Into<String>, V: Into<MetaValue>>(
+		mut self,
+		key: K,
+		value: V,
+	) -> Self {
+		self.insert(key, value);
+		self
+	}
+
+	/// Fetches the value under a key
+	pub fn get(
+		&self,
+		key: impl AsRef<str>,
+	) -> Option<&MetaValue> {
+		self.attributes.get(key.as_ref())
+	}
+
+	/// Removes and returns the value under a key
+	pub fn remove(
+		&mut self,
+		key: impl AsRef<str>,
+	) -> Option<MetaValue> {
+		self.attributes.remove(key.as_ref())
+	}
+
+	/// Iterates over all attributes, sorted by key
+	pub fn iter(&self) -> impl Iterator<Item = (&str, &MetaValue)> {
+		self.attributes.iter().map(|(key, value)| (key.as_str(), value))
+	}
+
+	/// The number of attributes
+	pub fn len(&self) -> usize {
+		self.attributes.len()
+	}
+
+	/// Whether there are no attributes
+	pub fn is_empty(&self) -> bool {
+		self.attributes.is_empty()
+	}
+
+	// 4(section length) + 2(count)
+	const CONSTANT: usize = 6;
+
+	/// The number of bytes taken up by the serialized section, zero if empty as the section is then left out
+	pub(crate) fn size(&self) -> usize {
+		if self.is_empty() {
+			return 0;
+		}
+
+		Metadata::CONSTANT + self.attributes.iter().map(|(key, value)| 2 + key.len() + value.size()).sum::<usize>()
+	}
+
+	/// Laid out as a `u32` section length, a `u16` count, then each attribute as a `u8` key length, the key, a `u8` type tag and the value.
+	/// Strings and bytes are prefixed with their `u32` length
+	pub(crate) fn to_bytes(&self) -> InternalResult<Vec<u8>> {
+		let size = self.size();
+
+		if self.len() > u16::MAX as usize || size > u32::MAX as usize {
+			return Err(InternalError::MetadataError(format!("Metadata holds too many attributes: {}, or is too large: {size} bytes", self.len())));
+		}
+
+		let mut buffer = Vec::with_capacity(size);
+		buffer.extend_from_slice(&(size as u32).to_le_bytes());
+		buffer.extend_from_slice(&(self.len() as u16).to_le_bytes());
+
+		for (key, value) in &self.attributes {
+			if key.len() > Metadata::MAX_KEY_LENGTH {
+				return Err(InternalError::MetadataError(format!("The key: {key} is longer than {} bytes", Metadata::MAX_KEY_LENGTH)));
+			}
+
+			buffer.push(key.len() as u8);
+			buffer.extend_from_slice(key.as_bytes());
+			buffer.push(value.tag());
+
+			match value {
+				MetaValue::Bool(value) => buffer.push(*value as u8),
+				MetaValue::Int(value) => buffer.extend_from_slice(&value.to_le_bytes()),
+				MetaValue::UInt(value) => buffer.extend_from_slice(&value.to_le_bytes()),
+				MetaValue::Float(value) => buffer.extend_from_slice(&value.to_le_bytes()),
+				MetaValue::Str(value) => {
+					buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+					buffer.extend_from_slice(value.as_bytes());
+				},
+				MetaValue::Bytes(value) => {
+					buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+					buffer.extend_from_slice(value);
+				},
+			}
+		}
+
+		Ok(buffer)
+	}
+
+	pub(crate) fn from_handle<T: Read>(mut handle: T) -> InternalResult<Metadata> {
+		let mut buffer = [0u8; Metadata::CONSTANT];
+		handle.read_exact(&mut buffer)?;
+
+		let size = u32::from_le_bytes(buffer[0..4].try_into().unwrap()) as usize;
+		let count = u16::from_le_bytes([buffer[4], buffer[5]]);
+
+		// read the whole section up front, so a corrupt length can't make us read past it
+		let mut section = Vec::new();
+		handle.take(size.saturating_sub(Metadata::CONSTANT) as u64).read_to_end(&mut section)?;
+
+		if section.len() + Metadata::CONSTANT != size {
+			return Err(InternalError::IOError(std::io::ErrorKind::UnexpectedEof.into()));
+		}
+
+		let malformed = || InternalError::MetadataError("Malformed metadata section".to_string());
+		let mut cursor = section.as_slice();
+		let mut take = |len: usize| -> InternalResult<&[u8]> {
+			if cursor.len() < len {
+				return Err(malformed());
+			}
+
+			let (head, tail) = cursor.split_at(len);
+			cursor = tail;
+			Ok(head)
+		};
+
+		let mut metadata = Metadata::new();
+
+		for _ in 0..count {
+			let key_length = take(1)?[0] as usize;
+			let key = String::from_utf8(take(key_length)?.to_vec()).map_err(|_| malformed())?;
+
+			let value = match take(1)?[0] {
+				MetaValue::BOOL => MetaValue::Bool(take(1)?[0] != 0),
+				MetaValue::INT => MetaValue::Int(i64::from_le_bytes(take(8)?.try_into().unwrap())),
+				MetaValue::UINT => MetaValue::UInt(u64::from_le_bytes(take(8)?.try_into().unwrap())),
+				MetaValue::FLOAT => MetaValue::Float(f64::from_le_bytes(take(8)?.try_into().unwrap())),
+				tag @ (MetaValue::STR | MetaValue::BYTES) => {
+					let length = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+					let bytes = take(length)?.to_vec();
+
+					if tag == MetaValue::STR {
+						MetaValue::Str(String::from_utf8(bytes).map_err(|_| malformed())?)
+					} else {
+						MetaValue::Bytes(bytes)
+					}
+				},
+				_ => return Err(malformed()),
+			};
+
+			metadata.attributes.insert(key, value);
+		}
+
+		Ok(metadata)
+	}
+}
@@ -1,8 +1,11 @@
-// Globally available exports
-pub mod error;
-
-pub mod flags;
-pub mod header;
-pub mod reg_entry;
-
-pub mod compressor;
+// Globally available exports
+pub mod error;
+
+pub mod extension;
+pub mod flags;
+pub mod header;
+pub mod metadata;
+pub mod reg_entry;
+
+pub mod codec;
+pub mod compressor;
@@ -1,152 +1,333 @@
-use super::{error::*, flags::Flags};
-use std::{
-	fmt,
-	io::{Read, Seek},
-	sync::Arc,
-};
-
-#[cfg(feature = "crypto")]
-use crate::crypto;
-
-/// Stand-alone meta-data for an archive entry(Leaf). This can be fetched without reading from the archive.
-#[derive(Debug, Clone, PartialEq)]
-pub struct RegistryEntry {
-	/// Self explanatory?
-	pub id: Arc<str>,
-	/// The flags extracted from the archive entry and parsed into a accessible struct
-	pub flags: Flags,
-	/// The location of the file in the archive, as an offset of bytes from the beginning of the file
-	pub location: u64,
-	/// The offset|size of the [`Leaf`](crate::builder::Leaf), in bytes. This is the actual number of bytes in the leaf endpoint. But the size of the data may vary once processed, ie when decompressed
-	pub offset: u64,
-	/// The signature of the data in the archive, used when verifying data authenticity
-	#[cfg(feature = "crypto")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-	pub signature: Option<crypto::Signature>,
-	/// Cryptographic nonce, for cryptographic integrity
-	#[cfg(feature = "crypto")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-	pub nonce: Option<[u8; 12]>,
-}
-
-impl RegistryEntry {
-	// (flags) + 8(location) + 8(offset) + 2(id length)
-	pub(crate) const CONSTANT: usize = Flags::BYTES + 18;
-
-	#[inline(always)]
-	pub(crate) fn empty() -> RegistryEntry {
-		RegistryEntry {
-			id: Arc::from("None"),
-			flags: Flags::new(),
-			location: 0,
-			offset: 0,
-
-			#[cfg(feature = "crypto")]
-			signature: None,
-			#[cfg(feature = "crypto")]
-			nonce: None,
-		}
-	}
-
-	/// Given a read handle, will proceed to read and parse bytes into a [`RegistryEntry`] struct. (de-serialization)
-	pub(crate) fn from_handle<T: Seek + Read>(mut handle: T) -> InternalResult<RegistryEntry> {
-		let mut base = RegistryEntry::empty();
-
-		let mut buffer: [u8; RegistryEntry::CONSTANT] = [0u8; RegistryEntry::CONSTANT];
-		handle.read_exact(&mut buffer)?;
-
-		// Construct entry
-		base.flags = Flags::from_bits(u32::from_le_bytes(buffer[0..4].try_into().unwrap()));
-
-		base.location = u64::from_le_bytes(buffer[4..12].try_into().unwrap());
-		base.offset = u64::from_le_bytes(buffer[12..20].try_into().unwrap());
-
-		let id_length = u16::from_le_bytes([buffer[20], buffer[21]]);
-
-		/* dynamically sized data */
-
-		// read signature, if present
-		#[cfg(feature = "crypto")]
-		if base.flags.contains(Flags::SIGNED_FLAG) {
-			let mut sig_bytes: [u8; crate::SIGNATURE_LENGTH] = [0u8; crate::SIGNATURE_LENGTH];
-			handle.read_exact(&mut sig_bytes)?;
-
-			base.signature = Some(crypto::Signature::from(sig_bytes));
-		};
-
-		// read nonce, if present
-		#[cfg(feature = "crypto")]
-		if base.flags.contains(Flags::ENCRYPTED_FLAG) {
-			let mut nonce: [u8; crate::NONCE_LENGTH] = [0u8; crate::NONCE_LENGTH];
-			handle.read_exact(&mut nonce)?;
-
-			base.nonce = Some(nonce);
-		};
-
-		#[cfg(not(feature = "crypto"))]
-		if base.flags.contains(Flags::SIGNED_FLAG) {
-			handle.seek(std::io::SeekFrom::Current(crate::SIGNATURE_LENGTH as i64))?;
-		}
-
-		#[cfg(not(feature = "crypto"))]
-		if base.flags.contains(Flags::ENCRYPTED_FLAG) {
-			handle.seek(std::io::SeekFrom::Current(crate::NONCE_LENGTH as i64))?;
-		}
-
-		// Construct ID
-		let mut id = String::with_capacity(id_length as usize);
-		handle.take(id_length as u64).read_to_string(&mut id)?;
-		base.id = Arc::from(id);
-
-		Ok(base)
-	}
-
-	/// Serializes a [`RegistryEntry`] struct into an array of bytes
-	pub(crate) fn to_bytes(&self) -> InternalResult<Vec<u8>> {
-		// Make sure the ID is not too big or else it will break the archive
-		let id = self.id.as_ref();
-
-		if id.len() >= crate::MAX_ID_LENGTH {
-			let copy = id.to_string();
-			return Err(InternalError::IDSizeOverflowError(copy));
-		};
-
-		let mut buffer = Vec::with_capacity(RegistryEntry::CONSTANT + id.len());
-		buffer.extend_from_slice(&self.flags.bits().to_le_bytes());
-		buffer.extend_from_slice(&self.location.to_le_bytes());
-		buffer.extend_from_slice(&self.offset.to_le_bytes());
-		buffer.extend_from_slice(&(id.len() as u16).to_le_bytes());
-
-		#[cfg(feature = "crypto")]
-		{
-			if let Some(signature) = self.signature {
-				buffer.extend_from_slice(&signature.to_bytes())
-			}
-
-			if let Some(nonce) = self.nonce {
-				buffer.extend_from_slice(&nonce)
-			}
-		};
-
-		// Append id
-		buffer.extend_from_slice(id.as_bytes());
-
-		Ok(buffer)
-	}
-}
-
-impl Default for RegistryEntry {
-	#[inline(always)]
-	fn default() -> RegistryEntry {
-		RegistryEntry::empty()
-	}
-}
-
-impl fmt::Display for RegistryEntry {
-	fn fmt(
-		&self,
-		f: &mut fmt::Formatter,
-	) -> fmt::Result {
-		write!(f, "[RegistryEntry] location: {}, length: {}, flags: {}", self.location, self.offset, self.flags.bits())
-	}
-}
+use super::{error::*, flags::Flags, metadata::Metadata};
+use std::{
+	fmt,
+	io::{Read, Seek},
+	sync::Arc,
+};
+
+#[cfg(feature = "crypto")]
+use {crate::crypto, std::borrow::Cow};
+
+/// Where a solid entry's data sits within it's decompressed block, see [`Flags::SOLID_FLAG`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRange {
+	/// Offset of the entry's data from the start of the decompressed block
+	pub start: u64,
+	/// Length of the entry's data
+	pub length: u64,
+}
+
+/// Stand-alone meta-data for an archive entry(Leaf). This can be fetched without reading from the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistryEntry {
+	/// Self explanatory?
+	pub id: Arc<str>,
+	/// The flags extracted from the archive entry and parsed into a accessible struct
+	pub flags: Flags,
+	/// The location of the file in the archive, as an offset of bytes from the beginning of the file
+	pub location: u64,
+	/// The offset|size of the [`Leaf`](crate::builder::Leaf), in bytes. This is the actual number of bytes in the leaf endpoint. But the size of the data may vary once processed, ie when decompressed
+	pub offset: u64,
+	/// The signature of the data in the archive, used when verifying data authenticity
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub signature: Option<crypto::Signature>,
+	/// Cryptographic nonce, for cryptographic integrity
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub nonce: Option<[u8; 12]>,
+	/// ID of the user-defined [`Codec`](crate::prelude::Codec) the entry was compressed with, present if flagged with [`Flags::CODEC_FLAG`]
+	pub codec: Option<u16>,
+	/// The entry's range within it's solid block, present if flagged with [`Flags::SOLID_FLAG`].
+	/// The [`location`](RegistryEntry::location) and [`offset`](RegistryEntry::offset) of solid entries point to the whole block
+	pub block: Option<BlockRange>,
+	/// Checksum of the entry's data as stored in the archive, present if flagged with [`Flags::CHECKSUM_FLAG`]. Solid entries carry their block's checksum
+	pub checksum: Option<u64>,
+	/// The power of two the entry's [`location`](RegistryEntry::location) is a multiple of, present if flagged with [`Flags::ALIGNED_FLAG`]. Solid entries share their block's alignment
+	pub alignment: Option<u64>,
+	/// Key/value attributes attached to the entry, empty unless flagged with [`Flags::METADATA_FLAG`]
+	pub metadata: Metadata,
+	/// The content version of entries read from v30 archives, [`None`] in later versions which dropped the field
+	pub content_version: Option<u8>,
+}
+
+impl RegistryEntry {
+	// (flags) + 8(location) + 8(offset) + 2(id length)
+	pub(crate) const CONSTANT: usize = Flags::BYTES + 18;
+
+	// (flags) + 1(content version) + 8(location) + 8(offset) + 2(id length)
+	pub(crate) const CONSTANT_V30: usize = Flags::BYTES + 19;
+
+	/// v30 archives encrypted every entry using this one nonce
+	#[cfg(feature = "crypto")]
+	pub(crate) const NONCE_V30: [u8; crate::NONCE_LENGTH] = [178, 5, 239, 228, 165, 44, 169, b'V', b'f', b'A', b'C', b'H'];
+
+	#[inline(always)]
+	pub(crate) fn empty() -> RegistryEntry {
+		RegistryEntry {
+			id: Arc::from("None"),
+			flags: Flags::new(),
+			location: 0,
+			offset: 0,
+
+			#[cfg(feature = "crypto")]
+			signature: None,
+			#[cfg(feature = "crypto")]
+			nonce: None,
+			codec: None,
+			block: None,
+			checksum: None,
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		}
+	}
+
+	/// Given a read handle, will proceed to read and parse bytes into a [`RegistryEntry`] struct. (de-serialization)
+	pub(crate) fn from_handle<T: Seek + Read>(mut handle: T) -> InternalResult<RegistryEntry> {
+		let mut base = RegistryEntry::empty();
+
+		let mut buffer: [u8; RegistryEntry::CONSTANT] = [0u8; RegistryEntry::CONSTANT];
+		handle.read_exact(&mut buffer)?;
+
+		// Construct entry
+		base.flags = Flags::from_bits(u32::from_le_bytes(buffer[0..4].try_into().unwrap()));
+
+		base.location = u64::from_le_bytes(buffer[4..12].try_into().unwrap());
+		base.offset = u64::from_le_bytes(buffer[12..20].try_into().unwrap());
+
+		let id_length = u16::from_le_bytes([buffer[20], buffer[21]]);
+
+		/* dynamically sized data */
+
+		// read signature, if present
+		#[cfg(feature = "crypto")]
+		if base.flags.contains(Flags::SIGNED_FLAG) {
+			let mut sig_bytes: [u8; crate::SIGNATURE_LENGTH] = [0u8; crate::SIGNATURE_LENGTH];
+			handle.read_exact(&mut sig_bytes)?;
+
+			base.signature = Some(crypto::Signature::from(sig_bytes));
+		};
+
+		// read nonce, if present
+		#[cfg(feature = "crypto")]
+		if base.flags.contains(Flags::ENCRYPTED_FLAG) {
+			let mut nonce: [u8; crate::NONCE_LENGTH] = [0u8; crate::NONCE_LENGTH];
+			handle.read_exact(&mut nonce)?;
+
+			base.nonce = Some(nonce);
+		};
+
+		#[cfg(not(feature = "crypto"))]
+		if base.flags.contains(Flags::SIGNED_FLAG) {
+			handle.seek(std::io::SeekFrom::Current(crate::SIGNATURE_LENGTH as i64))?;
+		}
+
+		#[cfg(not(feature = "crypto"))]
+		if base.flags.contains(Flags::ENCRYPTED_FLAG) {
+			handle.seek(std::io::SeekFrom::Current(crate::NONCE_LENGTH as i64))?;
+		}
+
+		// read codec ID, if present
+		if base.flags.contains(Flags::CODEC_FLAG) {
+			let mut codec = [0u8; 2];
+			handle.read_exact(&mut codec)?;
+
+			base.codec = Some(u16::from_le_bytes(codec));
+		}
+
+		// read block range, if present
+		if base.flags.contains(Flags::SOLID_FLAG) {
+			let mut range = [0u8; 16];
+			handle.read_exact(&mut range)?;
+
+			base.block = Some(BlockRange {
+				start: u64::from_le_bytes(range[0..8].try_into().unwrap()),
+				length: u64::from_le_bytes(range[8..16].try_into().unwrap()),
+			});
+		}
+
+		// read checksum, if present
+		if base.flags.contains(Flags::CHECKSUM_FLAG) {
+			let mut checksum = [0u8; 8];
+			handle.read_exact(&mut checksum)?;
+
+			base.checksum = Some(u64::from_le_bytes(checksum));
+		}
+
+		// read alignment, stored as it's base 2 logarithm
+		if base.flags.contains(Flags::ALIGNED_FLAG) {
+			let mut exponent = [0u8; 1];
+			handle.read_exact(&mut exponent)?;
+
+			let alignment = 1u64.checked_shl(exponent[0] as u32).ok_or_else(|| InternalError::AlignmentError(format!("Entry alignment of 2^{} is out of range", exponent[0])))?;
+			base.alignment = Some(alignment);
+		}
+
+		// read metadata, if present
+		if base.flags.contains(Flags::METADATA_FLAG) {
+			base.metadata = Metadata::from_handle(&mut handle)?;
+		}
+
+		// Construct ID
+		let mut id = String::with_capacity(id_length as usize);
+		handle.take(id_length as u64).read_to_string(&mut id)?;
+		base.id = Arc::from(id);
+
+		Ok(base)
+	}
+
+	/// Parses a [`RegistryEntry`] laid out as in v30 archives, which store a content version and no nonce
+	pub(crate) fn from_handle_v30<T: Seek + Read>(mut handle: T) -> InternalResult<RegistryEntry> {
+		let mut base = RegistryEntry::empty();
+
+		let mut buffer: [u8; RegistryEntry::CONSTANT_V30] = [0u8; RegistryEntry::CONSTANT_V30];
+		handle.read_exact(&mut buffer)?;
+
+		// Construct entry
+		base.flags = Flags::from_bits(u32::from_le_bytes(buffer[0..4].try_into().unwrap()));
+		base.content_version = Some(buffer[4]);
+
+		base.location = u64::from_le_bytes(buffer[5..13].try_into().unwrap());
+		base.offset = u64::from_le_bytes(buffer[13..21].try_into().unwrap());
+
+		let id_length = u16::from_le_bytes([buffer[21], buffer[22]]);
+
+		// read signature, if present
+		#[cfg(feature = "crypto")]
+		if base.flags.contains(Flags::SIGNED_FLAG) {
+			let mut sig_bytes: [u8; crate::SIGNATURE_LENGTH] = [0u8; crate::SIGNATURE_LENGTH];
+			handle.read_exact(&mut sig_bytes)?;
+
+			base.signature = Some(crypto::Signature::from(sig_bytes));
+		};
+
+		#[cfg(not(feature = "crypto"))]
+		if base.flags.contains(Flags::SIGNED_FLAG) {
+			handle.seek(std::io::SeekFrom::Current(crate::SIGNATURE_LENGTH as i64))?;
+		}
+
+		// the nonce was fixed, so entries can be decrypted like any other
+		#[cfg(feature = "crypto")]
+		if base.flags.contains(Flags::ENCRYPTED_FLAG) {
+			base.nonce = Some(RegistryEntry::NONCE_V30);
+		}
+
+		// Construct ID
+		let mut id = String::with_capacity(id_length as usize);
+		handle.take(id_length as u64).read_to_string(&mut id)?;
+		base.id = Arc::from(id);
+
+		Ok(base)
+	}
+
+	/// The bytes appended to an entry's raw data to form the message it's signature covers.
+	/// This is the ID, but v30 archives signed the whole serialized entry, sans signature
+	#[cfg(feature = "crypto")]
+	pub(crate) fn signed_suffix(&self) -> Cow<'_, [u8]> {
+		let id = self.id.as_bytes();
+
+		match self.content_version {
+			Some(content_version) => {
+				let mut buffer = Vec::with_capacity(RegistryEntry::CONSTANT_V30 + id.len());
+				buffer.extend_from_slice(&self.flags.bits().to_le_bytes());
+				buffer.push(content_version);
+				buffer.extend_from_slice(&self.location.to_le_bytes());
+				buffer.extend_from_slice(&self.offset.to_le_bytes());
+				buffer.extend_from_slice(&(id.len() as u16).to_le_bytes());
+				buffer.extend_from_slice(id);
+
+				Cow::Owned(buffer)
+			},
+			None => Cow::Borrowed(id),
+		}
+	}
+
+	/// The associated data bound to an encrypted entry's ciphertext: it's [authenticated flags](Flags::AUTHENTICATED_MASK), codec ID if any, followed by it's ID
+	#[cfg(feature = "crypto")]
+	pub(crate) fn associated_data(&self) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(Flags::BYTES + 2 + self.id.len());
+		buffer.extend_from_slice(&(self.flags.bits() & Flags::AUTHENTICATED_MASK).to_le_bytes());
+
+		if let Some(codec) = self.codec {
+			buffer.extend_from_slice(&codec.to_le_bytes());
+		}
+
+		buffer.extend_from_slice(self.id.as_bytes());
+		buffer
+	}
+
+	/// Serializes a [`RegistryEntry`] struct into an array of bytes
+	pub(crate) fn to_bytes(&self) -> InternalResult<Vec<u8>> {
+		// Make sure the ID is not too big or else it will break the archive
+		let id = self.id.as_ref();
+
+		if id.len() >= crate::MAX_ID_LENGTH {
+			let copy = id.to_string();
+			return Err(InternalError::IDSizeOverflowError(copy));
+		};
+
+		let mut buffer = Vec::with_capacity(RegistryEntry::CONSTANT + id.len());
+		buffer.extend_from_slice(&self.flags.bits().to_le_bytes());
+		buffer.extend_from_slice(&self.location.to_le_bytes());
+		buffer.extend_from_slice(&self.offset.to_le_bytes());
+		buffer.extend_from_slice(&(id.len() as u16).to_le_bytes());
+
+		#[cfg(feature = "crypto")]
+		{
+			if let Some(signature) = self.signature {
+				buffer.extend_from_slice(&signature.to_bytes())
+			}
+
+			if let Some(nonce) = self.nonce {
+				buffer.extend_from_slice(&nonce)
+			}
+		};
+
+		if let Some(codec) = self.codec {
+			buffer.extend_from_slice(&codec.to_le_bytes());
+		}
+
+		if let Some(block) = self.block {
+			buffer.extend_from_slice(&block.start.to_le_bytes());
+			buffer.extend_from_slice(&block.length.to_le_bytes());
+		}
+
+		if let Some(checksum) = self.checksum {
+			buffer.extend_from_slice(&checksum.to_le_bytes());
+		}
+
+		if let Some(alignment) = self.alignment {
+			if !alignment.is_power_of_two() {
+				return Err(InternalError::AlignmentError(format!("Alignments must be powers of two, found {alignment}")));
+			}
+
+			buffer.push(alignment.trailing_zeros() as u8);
+		}
+
+		if !self.metadata.is_empty() {
+			buffer.extend_from_slice(&self.metadata.to_bytes()?);
+		}
+
+		// Append id
+		buffer.extend_from_slice(id.as_bytes());
+
+		Ok(buffer)
+	}
+}
+
+impl Default for RegistryEntry {
+	#[inline(always)]
+	fn default() -> RegistryEntry {
+		RegistryEntry::empty()
+	}
+}
+
+impl fmt::Display for RegistryEntry {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter,
+	) -> fmt::Result {
+		write!(f, "[RegistryEntry] location: {}, length: {}, flags: {}", self.location, self.offset, self.flags.bits())
+	}
+}
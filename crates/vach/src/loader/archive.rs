@@ -1,294 +1,962 @@
-use std::{
-	collections::HashMap,
-	io::{Read, Seek, SeekFrom},
-	ops::DerefMut,
-	str,
-	sync::{Arc, Mutex},
-};
-
-use super::resource::Resource;
-use crate::global::{error::*, flags::Flags, header::Header, reg_entry::RegistryEntry};
-
-#[cfg(feature = "crypto")]
-use crate::crypto;
-
-#[cfg(feature = "compression")]
-use crate::global::compressor::*;
-
-/// Parses an Archive from a read handle.
-/// > Wraps handle in a [`Mutex`] internally for shared access, use [`fetch_mut`](Archive::fetch_mut) for lock-free access.
-#[derive(Debug)]
-pub struct Archive<T> {
-	/// Wrapping `handle` in a Mutex means that we only ever lock when reading from the underlying buffer, thus ensuring maximum performance across threads
-	/// Since all other work is done per thread
-	handle: Mutex<T>,
-
-	// Registry Data
-	header: Header,
-	entries: HashMap<Arc<str>, RegistryEntry>,
-
-	// Optional parts
-	#[cfg(feature = "crypto")]
-	decryptor: Option<crypto::Encryptor>,
-	#[cfg(feature = "crypto")]
-	key: Option<crypto::VerifyingKey>,
-}
-
-impl<T> std::fmt::Display for Archive<T> {
-	fn fmt(
-		&self,
-		f: &mut std::fmt::Formatter<'_>,
-	) -> std::fmt::Result {
-		let bytes = self.entries.values().map(|re| re.offset).reduce(|a, b| a + b).unwrap_or(0);
-
-		write!(
-			f,
-			"[Archive Header] Version: {}, Members: {}, Compressed Size: {bytes}B, Header-Flags: <{:#x} : {:#016b}>",
-			self.header.version,
-			self.entries.len(),
-			self.header.flags.bits,
-			self.header.flags.bits,
-		)
-	}
-}
-
-impl<T> Archive<T> {
-	/// Consume the [`Archive`] and return the underlying source. Only valid if internal [`Mutex`] isn't poisoned
-	pub fn into_inner(self) -> Result<T, std::sync::PoisonError<T>> {
-		self.handle.into_inner()
-	}
-
-	// Decompress and|or Decrypt some data
-	#[inline(never)]
-	fn process(
-		&self,
-		entry: &RegistryEntry,
-		mut raw: Vec<u8>,
-	) -> InternalResult<(Vec<u8>, bool)> {
-		/* Literally the hottest function in the block (🕶) */
-
-		// buffer_a originally contains the raw data
-		let mut decrypted = None;
-		let mut verified = false;
-
-		// Signature validation
-		// Validate signature only if a public key is present
-		#[cfg(feature = "crypto")]
-		if let Some(pk) = self.key {
-			// If there is an error the data is flagged as invalid
-			if let Some(signature) = entry.signature {
-				let id_bytes = entry.id.as_bytes();
-				raw.extend_from_slice(id_bytes);
-
-				verified = pk.verify_strict(&raw, &signature).is_ok();
-				raw.truncate(raw.len() - id_bytes.len());
-			}
-		}
-
-		// 1: Decryption layer
-		if entry.flags.contains(Flags::ENCRYPTED_FLAG) {
-			#[cfg(feature = "crypto")]
-			match self.decryptor.as_ref() {
-				Some(dc) => match entry.nonce.as_ref() {
-					Some(n) => decrypted = Some(dc.decrypt(&raw, n)?),
-					None => return Err(InternalError::OtherError(format!("Entry {} is flagged as encrypted but doesn't contain a nonce", entry.id).into())),
-				},
-				None => return Err(InternalError::NoKeypairError),
-			}
-
-			#[cfg(not(feature = "crypto"))]
-			return Err(InternalError::MissingFeatureError("crypto"));
-		}
-
-		// 2: Decompression layer
-		if entry.flags.contains(Flags::COMPRESSED_FLAG) {
-			#[cfg(feature = "compression")]
-			{
-				let (source, mut target) = match decrypted {
-					// data was decrypted and stored.
-					Some(vec) => {
-						raw.clear();
-						(vec, raw)
-					},
-					// data was not decrypted nor stored.
-					None => {
-						let capacity = raw.capacity();
-						(raw, Vec::with_capacity(capacity))
-					},
-				};
-
-				if entry.flags.contains(Flags::LZ4_COMPRESSED) {
-					Compressor::new(source.as_slice()).decompress(CompressionAlgorithm::LZ4, &mut target)?
-				} else if entry.flags.contains(Flags::BROTLI_COMPRESSED) {
-					Compressor::new(source.as_slice()).decompress(CompressionAlgorithm::Brotli(0), &mut target)?
-				} else if entry.flags.contains(Flags::SNAPPY_COMPRESSED) {
-					Compressor::new(source.as_slice()).decompress(CompressionAlgorithm::Snappy, &mut target)?
-				} else {
-					return InternalResult::Err(InternalError::OtherError(format!("Unable to determine the compression algorithm used for entry: {}", entry).into()));
-				};
-
-				Ok((target, verified))
-			}
-
-			#[cfg(not(feature = "compression"))]
-			Err(InternalError::MissingFeatureError("compression"))
-		} else {
-			match decrypted {
-				Some(decrypted) => Ok((decrypted, verified)),
-				None => Ok((raw, verified)),
-			}
-		}
-	}
-}
-
-impl<T> Archive<T>
-where
-	T: Seek + Read,
-{
-	/// Parses an [`Archive`] from the given source
-	pub fn new(mut handle: T) -> InternalResult<Archive<T>> {
-		// Start reading from the start of the input
-		handle.seek(SeekFrom::Start(0))?;
-
-		let header = Header::from_handle(&mut handle)?;
-		header.validate()?;
-
-		// Generate and store Registry Entries
-		let mut entries = HashMap::new();
-
-		// Construct entries map
-		for _ in 0..header.capacity {
-			let entry = RegistryEntry::from_handle(&mut handle)?;
-			entries.insert(entry.id.clone(), entry);
-		}
-
-		let archive = Archive {
-			header,
-			handle: Mutex::new(handle),
-			entries,
-
-			#[cfg(feature = "crypto")]
-			key: None,
-			#[cfg(feature = "crypto")]
-			decryptor: None,
-		};
-
-		Ok(archive)
-	}
-
-	/// Parse an [`Archive`], with an optional [`VerifyingKey`](crypto::VerifyingKey).
-	#[cfg(feature = "crypto")]
-	pub fn with_key(
-		mut handle: T,
-		vk: &ed25519_dalek::VerifyingKey,
-	) -> InternalResult<Archive<T>> {
-		// Start reading from the start of the input
-		handle.seek(SeekFrom::Start(0))?;
-
-		let header = Header::from_handle(&mut handle)?;
-		header.validate()?;
-
-		// Generate and store Registry Entries
-		let mut entries = HashMap::new();
-
-		// Construct entries map
-		for _ in 0..header.capacity {
-			let entry = RegistryEntry::from_handle(&mut handle)?;
-			entries.insert(entry.id.clone(), entry);
-		}
-
-		let archive = Archive {
-			header,
-			handle: Mutex::new(handle),
-			entries,
-			key: Some(*vk),
-			decryptor: Some(crypto::Encryptor::new(vk)),
-		};
-
-		Ok(archive)
-	}
-
-	/// Fetch a [`RegistryEntry`] from this [`Archive`].
-	/// This can be used for debugging, as the [`RegistryEntry`] holds information on data with the adjacent ID.
-	pub fn fetch_entry(
-		&self,
-		id: impl AsRef<str>,
-	) -> Option<RegistryEntry> {
-		self.entries.get(id.as_ref()).cloned()
-	}
-
-	/// Returns an immutable reference to the underlying [`HashMap`]. This hashmap stores [`RegistryEntry`] values and uses `String` keys.
-	#[inline(always)]
-	pub fn entries(&self) -> &HashMap<Arc<str>, RegistryEntry> {
-		&self.entries
-	}
-
-	/// Global flags extracted from the `Header` section of the source
-	#[inline(always)]
-	pub fn flags(&self) -> &Flags {
-		&self.header.flags
-	}
-}
-
-impl<T> Archive<T>
-where
-	T: Read + Seek,
-{
-	/// Read a [`RegistryEntry's`](RegistryEntry) adjacent raw data
-	pub(crate) fn read_raw(
-		handle: &mut T,
-		entry: &RegistryEntry,
-	) -> InternalResult<Vec<u8>> {
-		let mut buffer = Vec::with_capacity(entry.offset as usize + 64);
-		handle.seek(SeekFrom::Start(entry.location))?;
-
-		let mut take = handle.take(entry.offset);
-		take.read_to_end(&mut buffer)?;
-
-		Ok(buffer)
-	}
-
-	/// Cheaper alternative to [`fetch`](Archive::fetch) that doesn't lock the underlying [Mutex]
-	pub fn fetch_mut(
-		&mut self,
-		id: impl AsRef<str>,
-	) -> InternalResult<Resource> {
-		if let Some(entry) = self.fetch_entry(&id) {
-			let raw = Archive::read_raw(self.handle.get_mut().unwrap(), &entry)?;
-			let (buffer, verified) = self.process(&entry, raw)?;
-
-			Ok(Resource {
-				flags: entry.flags,
-				data: buffer.into_boxed_slice(),
-				verified,
-			})
-		} else {
-			Err(InternalError::MissingResourceError(id.as_ref().to_string()))
-		}
-	}
-
-	/// Fetch a [`Resource`] with the given `ID`.
-	/// Locks the underlying [`Mutex`], for a cheaper non-locking operation refer to [`Archive::fetch_mut`]
-	pub fn fetch(
-		&self,
-		id: impl AsRef<str>,
-	) -> InternalResult<Resource> {
-		if let Some(entry) = self.fetch_entry(&id) {
-			let raw = {
-				let mut guard = self.handle.lock().unwrap();
-				Archive::read_raw(guard.deref_mut(), &entry)?
-			};
-
-			// Decompress and|or decrypt the data
-			let (buffer, is_secure) = self.process(&entry, raw)?;
-
-			Ok(Resource {
-				flags: entry.flags,
-				data: buffer.into_boxed_slice(),
-				verified: is_secure,
-			})
-		} else {
-			Err(InternalError::MissingResourceError(id.as_ref().to_string()))
-		}
-	}
-}
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	io::{BufReader, Cursor, Read, Seek, SeekFrom},
+	str,
+	sync::Arc,
+	thread,
+};
+
+use twox_hash::XxHash64;
+
+use super::{
+	cache::{BlockCache, DEFAULT_BLOCK_CACHE},
+	config::ArchiveConfig,
+	reader::{ChecksumReader, EntrySource, ResourceReader, STREAM_BUFFER_SIZE},
+	report::{EntryReport, EntryStatus},
+	resource::{BorrowedResource, Resource},
+	source::{poisoned, Handle, HandleReader, ReadAt},
+	tree::{DEFAULT_SEPARATOR, DirEntry, DirTree},
+};
+use crate::global::{
+	error::*,
+	flags::Flags,
+	header::Header,
+	metadata::Metadata,
+	reg_entry::{BlockRange, RegistryEntry},
+};
+
+#[cfg(feature = "crypto")]
+use {
+	crate::crypto,
+	sha2::{Digest, Sha512},
+};
+
+#[cfg(any(feature = "crypto", feature = "compression"))]
+use crate::global::extension::HeaderExtension;
+
+#[cfg(feature = "compression")]
+use crate::global::{codec::CodecRegistry, compressor::*};
+
+/// Parses an Archive from a read handle.
+/// > [`Read`] + [`Seek`] handles are wrapped in a [`Mutex`](std::sync::Mutex) internally for shared access, use [`fetch_mut`](Archive::fetch_mut) for lock-free access.
+/// > Sources implementing [`ReadAt`] can instead be loaded using [`from_source`](Archive::from_source), and are fetched from concurrently without any locking.
+#[derive(Debug)]
+pub struct Archive<T> {
+	/// Wrapping `handle` in a Mutex means that we only ever lock when reading from the underlying buffer, thus ensuring maximum performance across threads
+	/// Since all other work is done per thread. Positional sources skip the Mutex entirely
+	handle: Handle<T>,
+
+	// Registry Data
+	header: Header,
+	entries: HashMap<Arc<str>, RegistryEntry>,
+
+	// Optional parts
+	#[cfg(feature = "crypto")]
+	decryptor: Option<crypto::Encryptor>,
+	#[cfg(feature = "crypto")]
+	key: Option<crypto::VerifyingKey>,
+	/// Where the registry starts and ends, the header and registry are covered by the manifest signature
+	#[cfg(feature = "crypto")]
+	registry_start: u64,
+	#[cfg(feature = "crypto")]
+	registry_end: u64,
+	/// Dictionary shared by small entries compressed against it, loaded once from the header
+	#[cfg(feature = "compression")]
+	dictionary: Option<Dictionary>,
+	/// User-defined codecs supplied at load time, see [`ArchiveConfig::codecs`]
+	#[cfg(feature = "compression")]
+	codecs: CodecRegistry,
+	/// Recently decompressed solid blocks, see [`ArchiveConfig::block_cache`]
+	blocks: BlockCache,
+	/// Entries indexed as a directory tree, see [`ArchiveConfig::separator`]
+	tree: DirTree,
+}
+
+impl<T> std::fmt::Display for Archive<T> {
+	fn fmt(
+		&self,
+		f: &mut std::fmt::Formatter<'_>,
+	) -> std::fmt::Result {
+		// entries in a solid block share it's bytes, so count each block once
+		let mut blocks = std::collections::HashSet::new();
+		let bytes = self
+			.entries
+			.values()
+			.filter(|re| re.block.is_none() || blocks.insert(re.location))
+			.map(|re| re.offset)
+			.sum::<u64>();
+
+		write!(
+			f,
+			"[Archive Header] Version: {}, Members: {}, Compressed Size: {bytes}B, Header-Flags: <{:#x} : {:#016b}>",
+			self.header.version,
+			self.entries.len(),
+			self.header.flags.bits,
+			self.header.flags.bits,
+		)
+	}
+}
+
+impl<T> Archive<T> {
+	/// Consume the [`Archive`] and return the underlying source. Only valid if internal [`Mutex`](std::sync::Mutex) isn't poisoned
+	pub fn into_inner(self) -> Result<T, std::sync::PoisonError<T>> {
+		self.handle.into_inner()
+	}
+
+	/// Parses the header and registry from a [`Handle`]
+	fn load(handle: Handle<T>) -> InternalResult<Archive<T>> {
+		let mut reader = BufReader::new(HandleReader { handle: &handle, position: 0 });
+
+		let header = Header::from_handle(&mut reader)?;
+
+		// appended archives point at their latest registry, older registries are left unreferenced
+		if let Some(offset) = header.registry_offset() {
+			reader.seek(SeekFrom::Start(offset))?;
+		}
+
+		#[cfg(feature = "crypto")]
+		let registry_start = reader.stream_position()?;
+
+		// Generate and store Registry Entries
+		let mut entries = HashMap::new();
+
+		// Construct entries map
+		for _ in 0..header.capacity {
+			// registry entries are decoded according to the archive's spec version
+			let entry = match header.version {
+				30 => RegistryEntry::from_handle_v30(&mut reader)?,
+				_ => RegistryEntry::from_handle(&mut reader)?,
+			};
+			entries.insert(entry.id.clone(), entry);
+		}
+
+		#[cfg(feature = "crypto")]
+		let registry_end = reader.stream_position()?;
+		drop(reader);
+
+		#[cfg(feature = "compression")]
+		let dictionary = header.extensions.iter().find_map(|extension| match extension {
+			HeaderExtension::Dictionary(data) => Some(Dictionary::new(data)),
+			_ => None,
+		});
+
+		let tree = DirTree::new(entries.keys(), DEFAULT_SEPARATOR);
+
+		let archive = Archive {
+			header,
+			handle,
+			entries,
+
+			#[cfg(feature = "crypto")]
+			key: None,
+			#[cfg(feature = "crypto")]
+			decryptor: None,
+			#[cfg(feature = "crypto")]
+			registry_start,
+			#[cfg(feature = "crypto")]
+			registry_end,
+			#[cfg(feature = "compression")]
+			dictionary,
+			#[cfg(feature = "compression")]
+			codecs: CodecRegistry::default(),
+			blocks: BlockCache::new(DEFAULT_BLOCK_CACHE),
+			tree,
+		};
+
+		Ok(archive)
+	}
+
+	/// Parses the header and registry from a [`Handle`], then applies the keys in the [`ArchiveConfig`]
+	#[allow(unused_variables, unused_mut)]
+	fn load_with_config(
+		handle: Handle<T>,
+		config: ArchiveConfig,
+	) -> InternalResult<Archive<T>> {
+		let mut archive = Archive::load(handle)?;
+		archive.blocks = BlockCache::new(config.block_cache);
+
+		if config.separator != archive.tree.separator {
+			archive.tree = DirTree::new(archive.entries.keys(), config.separator);
+		}
+
+		#[cfg(feature = "compression")]
+		{
+			archive.codecs = config.codecs;
+		}
+
+		#[cfg(feature = "crypto")]
+		{
+			if let Some(vk) = config.verifying_key {
+				archive.verify_manifest(&vk)?;
+				archive.key = Some(vk);
+			}
+
+			let key = match (config.decryption_key, config.passphrase, config.recipient_key) {
+				(Some(key), _, _) => Some(key),
+				(None, Some(passphrase), _) => archive.derive_key(&passphrase)?,
+				(None, None, Some(secret)) => Some(archive.unwrap_key(&secret)?),
+				// older archives derived their encryption key from the verifying key
+				(None, None, None) if archive.header.version < Header::ENCRYPTION_KEY_VERSION => archive.key.map(|vk| vk.to_bytes()),
+				(None, None, None) => None,
+			};
+
+			archive.decryptor = key.map(|key| crypto::Encryptor::new(&key));
+		}
+
+		Ok(archive)
+	}
+
+	/// Derives the encryption key from a passphrase, using the KDF parameters in the header. [`None`] if the archive wasn't built with a passphrase
+	#[cfg(feature = "crypto")]
+	fn derive_key(
+		&self,
+		passphrase: &str,
+	) -> InternalResult<Option<crypto::EncryptionKey>> {
+		for extension in &self.header.extensions {
+			if let HeaderExtension::Passphrase { params, salt } = extension {
+				return params.derive_key(passphrase.as_bytes(), salt).map(Some);
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Unwraps the encryption key from the recipients section, using a recipient's private key
+	#[cfg(feature = "crypto")]
+	fn unwrap_key(
+		&self,
+		secret: &crypto::RecipientSecret,
+	) -> InternalResult<crypto::EncryptionKey> {
+		let offset = self.header.extensions.iter().find_map(|extension| match extension {
+			HeaderExtension::Recipients { offset } => Some(*offset),
+			_ => None,
+		});
+
+		let Some(offset) = offset else {
+			return Err(InternalError::OtherError("Archive has no recipients section".into()));
+		};
+
+		let reader = BufReader::new(HandleReader { handle: &self.handle, position: offset });
+		crypto::WrappedKey::open_section(reader, secret)
+	}
+
+	/// Checks the signature over the header and registry. Archives predating the manifest are only verified per entry,
+	/// later ones are rejected if the signature is missing, since stripping it would otherwise go unnoticed
+	#[cfg(feature = "crypto")]
+	fn verify_manifest(
+		&self,
+		vk: &crypto::VerifyingKey,
+	) -> InternalResult {
+		if self.header.version < Header::MANIFEST_VERSION {
+			return Ok(());
+		}
+
+		let signature = self.header.signature.ok_or(InternalError::ManifestVerificationError)?;
+
+		// the manifest is the header and registry, without the signature between them. Appended archives keep their registry after the leaves
+		let header_size = self.header.encoded_size();
+		let mut manifest = vec![0u8; header_size + (self.registry_end - self.registry_start) as usize];
+
+		let (header, registry) = manifest.split_at_mut(header_size);
+		self.handle.read_exact_at(header, 0)?;
+		self.handle.read_exact_at(registry, self.registry_start)?;
+
+		vk.verify_strict(&manifest, &signature).map_err(|_| InternalError::ManifestVerificationError)
+	}
+
+	/// Decrypts an entry's data, archives from spec version 100 bind the entry's ID and flags as associated data, and from 110 encrypt it in chunks
+	#[cfg(feature = "crypto")]
+	fn decrypt(
+		&self,
+		decryptor: &crypto::Encryptor,
+		entry: &RegistryEntry,
+		nonce: &[u8; crate::NONCE_LENGTH],
+		data: &[u8],
+	) -> InternalResult<Vec<u8>> {
+		if self.header.version < Header::AAD_VERSION {
+			return decryptor.decrypt(data, nonce, &[]);
+		}
+
+		let aad = entry.associated_data();
+		let result = match self.header.version < Header::CHUNKED_ENCRYPTION_VERSION {
+			true => decryptor.decrypt(data, nonce, &aad),
+			false => decryptor.decrypt_chunks(data, nonce, &aad),
+		};
+
+		result.map_err(|_| InternalError::EntryAuthenticationError(entry.id.to_string()))
+	}
+
+	/// Decodes an entry compressed against the archive's dictionary
+	#[cfg(feature = "compression")]
+	fn dictionary_decoder<'a, R: Read + 'a>(
+		&'a self,
+		entry: &RegistryEntry,
+		data: R,
+	) -> InternalResult<Box<dyn Read + 'a>> {
+		match self.dictionary.as_ref() {
+			Some(dictionary) => Compressor::new(data).into_dictionary_decoder(dictionary),
+			None => Err(InternalError::OtherError(format!("Entry {} was compressed against a dictionary, but the archive has none", entry.id).into())),
+		}
+	}
+
+	/// Decodes an entry compressed with a user-defined codec, which must be registered through [`ArchiveConfig::codecs`]
+	#[cfg(feature = "compression")]
+	fn codec_decoder<'a>(
+		&self,
+		entry: &RegistryEntry,
+		data: Box<dyn Read + 'a>,
+	) -> InternalResult<Box<dyn Read + 'a>> {
+		let id = entry.codec.unwrap_or_default();
+
+		match self.codecs.get(id) {
+			Some(codec) => codec.decompress(data).map_err(InternalError::IOError),
+			None => Err(InternalError::UnknownCodecError(id)),
+		}
+	}
+
+	/// Checks an entry's raw data against it's checksum, if it has one
+	fn verify_checksum(
+		entry: &RegistryEntry,
+		raw: &[u8],
+	) -> InternalResult {
+		match entry.checksum {
+			Some(checksum) if XxHash64::oneshot(0, raw) != checksum => Err(InternalError::ChecksumMismatchError(entry.id.to_string())),
+			_ => Ok(()),
+		}
+	}
+
+	/// Checks an entry's signature over it's raw data. `false` if it isn't signed, or no verifying key was supplied
+	#[cfg(feature = "crypto")]
+	fn verify_signature(
+		&self,
+		entry: &RegistryEntry,
+		raw: &mut Vec<u8>,
+	) -> bool {
+		let (Some(pk), Some(signature)) = (self.key, entry.signature) else {
+			return false;
+		};
+
+		if self.header.version >= Header::PREHASHED_SIGNATURE_VERSION {
+			let hasher = Sha512::new().chain_update(&raw).chain_update(entry.id.as_bytes());
+			pk.verify_prehashed_strict(hasher, Some(crypto::SIGNATURE_CONTEXT), &signature).is_ok()
+		} else {
+			let suffix = entry.signed_suffix();
+			raw.extend_from_slice(&suffix);
+
+			let verified = pk.verify_strict(raw, &signature).is_ok();
+			raw.truncate(raw.len() - suffix.len());
+			verified
+		}
+	}
+
+	/// Decrypts an entry's raw data, [`None`] if it isn't encrypted
+	fn decrypt_entry(
+		&self,
+		entry: &RegistryEntry,
+		raw: &[u8],
+	) -> InternalResult<Option<Vec<u8>>> {
+		if !entry.flags.contains(Flags::ENCRYPTED_FLAG) {
+			return Ok(None);
+		}
+
+		#[cfg(feature = "crypto")]
+		match (self.decryptor.as_ref(), entry.nonce.as_ref()) {
+			(Some(dc), Some(n)) => self.decrypt(dc, entry, n, raw).map(Some),
+			(Some(_), None) => Err(InternalError::OtherError(format!("Entry {} is flagged as encrypted but doesn't contain a nonce", entry.id).into())),
+			(None, _) => Err(InternalError::NoKeypairError),
+		}
+
+		#[cfg(not(feature = "crypto"))]
+		{
+			let _ = raw;
+			Err(InternalError::MissingFeatureError("crypto"))
+		}
+	}
+
+	/// Decompresses an entry's decrypted data into `target`
+	fn decompress_entry(
+		&self,
+		entry: &RegistryEntry,
+		source: &[u8],
+		target: &mut Vec<u8>,
+	) -> InternalResult {
+		#[cfg(feature = "compression")]
+		{
+			match CompressionAlgorithm::from_flags(entry.flags) {
+				Some(_) if entry.flags.contains(Flags::DICTIONARY_COMPRESSED) => self.dictionary_decoder(entry, source)?.read_to_end(target)?,
+				Some(algo) => Compressor::new(source).decompress(algo, target)?,
+				None if entry.flags.contains(Flags::CODEC_FLAG) => self.codec_decoder(entry, Box::new(source))?.read_to_end(target)?,
+				None => return Err(InternalError::OtherError(format!("Unable to determine the compression algorithm used for entry: {}", entry).into())),
+			};
+
+			Ok(())
+		}
+
+		#[cfg(not(feature = "compression"))]
+		{
+			let _ = (entry, source, target);
+			Err(InternalError::MissingFeatureError("compression"))
+		}
+	}
+
+	// Decompress and|or Decrypt some data
+	#[inline(never)]
+	fn process(
+		&self,
+		entry: &RegistryEntry,
+		mut raw: Vec<u8>,
+	) -> InternalResult<(Vec<u8>, bool)> {
+		/* Literally the hottest function in the block (🕶) */
+
+		// corrupted data is caught before it reaches the decryptor or decompressor
+		Archive::<T>::verify_checksum(entry, &raw)?;
+
+		// Validate signature only if a public key is present, if there is an error the data is flagged as invalid
+		#[cfg(feature = "crypto")]
+		let verified = self.verify_signature(entry, &mut raw);
+		#[cfg(not(feature = "crypto"))]
+		let verified = false;
+
+		// 1: Decryption layer
+		let decrypted = self.decrypt_entry(entry, &raw)?;
+
+		if !entry.flags.contains(Flags::COMPRESSED_FLAG) {
+			return Ok((decrypted.unwrap_or(raw), verified));
+		}
+
+		// 2: Decompression layer, reusing the raw buffer for output if the data was decrypted
+		let (source, mut target) = match decrypted {
+			Some(vec) => {
+				raw.clear();
+				(vec, raw)
+			},
+			None => {
+				let capacity = raw.capacity();
+				(raw, Vec::with_capacity(capacity))
+			},
+		};
+
+		self.decompress_entry(entry, &source, &mut target)?;
+		Ok((target, verified))
+	}
+}
+
+impl<T> Archive<T>
+where
+	T: Seek + Read,
+{
+	/// Parses an [`Archive`] from the given source
+	pub fn new(handle: T) -> InternalResult<Archive<T>> {
+		Archive::load(Handle::locked(handle))
+	}
+
+	/// Parse an [`Archive`], with an optional [`VerifyingKey`](crypto::VerifyingKey).
+	/// Archives from spec version 60 onward fail with [`ManifestVerificationError`](InternalError::ManifestVerificationError) unless their header and registry were signed by the matching [`SigningKey`](crypto::SigningKey).
+	/// From spec version 70, entries are encrypted using a separate key which has to be supplied through [`with_config`](Archive::with_config).
+	#[cfg(feature = "crypto")]
+	pub fn with_key(
+		handle: T,
+		vk: &ed25519_dalek::VerifyingKey,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::locked(handle), ArchiveConfig::default().key(*vk))
+	}
+
+	/// Parse an [`Archive`] built with a [`passphrase`](crate::builder::BuilderConfig::passphrase), deriving the decryption key from it
+	#[cfg(feature = "crypto")]
+	pub fn with_passphrase(
+		handle: T,
+		passphrase: &str,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::locked(handle), ArchiveConfig::default().passphrase(passphrase))
+	}
+
+	/// Parse an [`Archive`] using the keys in an [`ArchiveConfig`], allowing for a decryption key separate from the verifying key
+	pub fn with_config(
+		handle: T,
+		config: ArchiveConfig,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::locked(handle), config)
+	}
+}
+
+impl<T> Archive<T>
+where
+	T: ReadAt,
+{
+	/// Parses an [`Archive`] from a source supporting positional reads, eg a [`File`](std::fs::File) or byte buffer.
+	/// [`fetch`](Archive::fetch) reads from the source concurrently, without locking.
+	pub fn from_source(source: T) -> InternalResult<Archive<T>> {
+		Archive::load(Handle::positional(source))
+	}
+
+	/// Parse an [`Archive`] from a source supporting positional reads, with an optional [`VerifyingKey`](crypto::VerifyingKey).
+	/// Archives from spec version 60 onward fail with [`ManifestVerificationError`](InternalError::ManifestVerificationError) unless their header and registry were signed by the matching [`SigningKey`](crypto::SigningKey).
+	#[cfg(feature = "crypto")]
+	pub fn from_source_with_key(
+		source: T,
+		vk: &ed25519_dalek::VerifyingKey,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::positional(source), ArchiveConfig::default().key(*vk))
+	}
+
+	/// Parse an [`Archive`] from a source supporting positional reads, deriving the decryption key from a [`passphrase`](crate::builder::BuilderConfig::passphrase)
+	#[cfg(feature = "crypto")]
+	pub fn from_source_with_passphrase(
+		source: T,
+		passphrase: &str,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::positional(source), ArchiveConfig::default().passphrase(passphrase))
+	}
+
+	/// Parse an [`Archive`] from a source supporting positional reads, using the keys in an [`ArchiveConfig`]
+	pub fn from_source_with_config(
+		source: T,
+		config: ArchiveConfig,
+	) -> InternalResult<Archive<T>> {
+		Archive::load_with_config(Handle::positional(source), config)
+	}
+}
+
+impl<T> Archive<T> {
+	/// Fetch a [`RegistryEntry`] from this [`Archive`].
+	/// This can be used for debugging, as the [`RegistryEntry`] holds information on data with the adjacent ID.
+	pub fn fetch_entry(
+		&self,
+		id: impl AsRef<str>,
+	) -> Option<RegistryEntry> {
+		self.entries.get(id.as_ref()).cloned()
+	}
+
+	/// Fetch the key/value [`Metadata`] attached to an entry, without reading it's data
+	pub fn fetch_metadata(
+		&self,
+		id: impl AsRef<str>,
+	) -> Option<&Metadata> {
+		self.entries.get(id.as_ref()).map(|entry| &entry.metadata)
+	}
+
+	/// Lists the directories and entries directly under a directory, [`None`] if it doesn't exist.
+	/// IDs are split into directories at the [`separator`](ArchiveConfig::separator), a trailing separator is ignored and an empty prefix is the root
+	pub fn read_dir(
+		&self,
+		prefix: impl AsRef<str>,
+	) -> Option<Vec<DirEntry<'_>>> {
+		self.tree.read_dir(&self.entries, prefix.as_ref())
+	}
+
+	/// Every entry under a directory, recursively. Depth first, with a directory's entries before it's subdirectories
+	pub fn walk(
+		&self,
+		prefix: impl AsRef<str>,
+	) -> Vec<&RegistryEntry> {
+		self.tree.walk(&self.entries, prefix.as_ref())
+	}
+
+	/// Every entry whose ID matches a glob pattern, sorted by ID. Within a component `*` matches any run of characters and `?` any single character,
+	/// a `**` component matches zero or more directories, eg `sfx/**/*.wav`
+	pub fn glob(
+		&self,
+		pattern: impl AsRef<str>,
+	) -> Vec<&RegistryEntry> {
+		self.tree.glob(&self.entries, pattern.as_ref())
+	}
+
+	/// Returns an immutable reference to the underlying [`HashMap`]. This hashmap stores [`RegistryEntry`] values and uses `String` keys.
+	#[inline(always)]
+	pub fn entries(&self) -> &HashMap<Arc<str>, RegistryEntry> {
+		&self.entries
+	}
+
+	/// Global flags extracted from the `Header` section of the source
+	#[inline(always)]
+	pub fn flags(&self) -> &Flags {
+		&self.header.flags
+	}
+
+	/// The spec version the source was written in, anywhere from [`MIN_VERSION`](crate::MIN_VERSION) to [`VERSION`](crate::VERSION)
+	#[inline(always)]
+	pub fn version(&self) -> u16 {
+		self.header.version
+	}
+}
+
+impl<T> Archive<T> {
+	/// Allocates a buffer for a [`RegistryEntry's`](RegistryEntry) adjacent raw data, with some leeway for the signature check
+	fn raw_buffer(entry: &RegistryEntry) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(entry.offset as usize + 64);
+		buffer.resize(entry.offset as usize, 0);
+		buffer
+	}
+
+	/// Fetches an entry stored in a solid block, decompressing the block unless it's still cached.
+	/// Signatures are never checked, since solid blocks only hold unsigned entries
+	fn fetch_solid(
+		&self,
+		entry: &RegistryEntry,
+		range: BlockRange,
+	) -> InternalResult<Vec<u8>> {
+		let block = match self.blocks.get(entry.location) {
+			Some(block) => block,
+			None => {
+				let mut raw = Archive::<T>::raw_buffer(entry);
+				self.handle.read_exact_at(&mut raw, entry.location)?;
+
+				let block: Arc<[u8]> = self.process(entry, raw)?.0.into();
+				self.blocks.insert(entry.location, block.clone());
+				block
+			},
+		};
+
+		usize::try_from(range.start)
+			.ok()
+			.zip(usize::try_from(range.start + range.length).ok())
+			.and_then(|(start, end)| block.get(start..end))
+			.map(<[u8]>::to_vec)
+			.ok_or(InternalError::IOError(std::io::ErrorKind::UnexpectedEof.into()))
+	}
+
+	/// Cheaper alternative to [`fetch`](Archive::fetch) that doesn't lock the underlying [Mutex](std::sync::Mutex)
+	pub fn fetch_mut(
+		&mut self,
+		id: impl AsRef<str>,
+	) -> InternalResult<Resource> {
+		if let Some(entry) = self.fetch_entry(&id) {
+			if let Some(range) = entry.block {
+				return Ok(Resource {
+					flags: entry.flags,
+					data: self.fetch_solid(&entry, range)?.into_boxed_slice(),
+					verified: false,
+				});
+			}
+
+			let mut raw = Archive::<T>::raw_buffer(&entry);
+			self.handle.read_exact_at_mut(&mut raw, entry.location)?;
+
+			let (buffer, verified) = self.process(&entry, raw)?;
+
+			Ok(Resource {
+				flags: entry.flags,
+				data: buffer.into_boxed_slice(),
+				verified,
+			})
+		} else {
+			Err(InternalError::MissingResourceError(id.as_ref().to_string()))
+		}
+	}
+
+	/// Fetch a streaming [`ResourceReader`] over the entry with the given `ID`, decompressing data as bytes are pulled from it.
+	/// The underlying [`Mutex`](std::sync::Mutex) is only locked while the reader refills it's internal buffer, so memory usage stays bounded no matter how big the entry is.
+	///
+	/// > Signatures are not checked, since that requires the whole entry. Use [`fetch`](Archive::fetch) if you need [`Resource::verified`].
+	/// > Encrypted entries are decrypted chunk by chunk, except in archives older than spec version 110 which are decrypted in full before decompression is streamed.
+	/// > Checksums are checked once the whole entry has been read, the read completing a corrupted entry fails with [`ChecksumMismatchError`](InternalError::ChecksumMismatchError).
+	pub fn fetch_reader(
+		&self,
+		id: impl AsRef<str>,
+	) -> InternalResult<ResourceReader<'_>> {
+		let Some(entry) = self.entries.get(id.as_ref()) else {
+			return Err(InternalError::MissingResourceError(id.as_ref().to_string()));
+		};
+
+		// solid blocks are decompressed whole, so there's nothing left to stream
+		if let Some(range) = entry.block {
+			let data = self.fetch_solid(entry, range)?;
+			return Ok(ResourceReader {
+				flags: entry.flags,
+				inner: Box::new(Cursor::new(data)),
+			});
+		}
+
+		// both come straight from the registry, so a malformed entry may point past the end of any source
+		let end = entry.location.checked_add(entry.offset).ok_or(InternalError::IOError(std::io::ErrorKind::UnexpectedEof.into()))?;
+		let source = EntrySource {
+			handle: &self.handle,
+			position: entry.location,
+			end,
+		};
+
+		// the checksum is checked once the whole entry has been read
+		let mut inner: Box<dyn Read + '_> = match entry.checksum {
+			Some(expected) => {
+				let source = ChecksumReader {
+					inner: source,
+					hasher: XxHash64::with_seed(0),
+					expected,
+					remaining: entry.offset,
+					id: &entry.id,
+				};
+
+				Box::new(BufReader::with_capacity(STREAM_BUFFER_SIZE, source))
+			},
+			None => Box::new(BufReader::with_capacity(STREAM_BUFFER_SIZE, source)),
+		};
+
+		// Decryption layer, archives from spec version 110 are decrypted chunk by chunk as they are streamed. Older ones apply AES-GCM over the whole leaf, so it's read in full first
+		if entry.flags.contains(Flags::ENCRYPTED_FLAG) {
+			#[cfg(feature = "crypto")]
+			match (self.decryptor.as_ref(), entry.nonce.as_ref()) {
+				(Some(dc), Some(nonce)) if self.header.version >= Header::CHUNKED_ENCRYPTION_VERSION => {
+					inner = Box::new(crypto::DecryptingReader::new(inner, dc, *nonce, entry.id.clone(), entry.associated_data()));
+				},
+				(Some(dc), Some(nonce)) => {
+					let mut raw = Vec::with_capacity(entry.offset as usize);
+					inner.read_to_end(&mut raw)?;
+
+					inner = Box::new(Cursor::new(self.decrypt(dc, entry, nonce, &raw)?));
+				},
+				(Some(_), None) => return Err(InternalError::OtherError(format!("Entry {} is flagged as encrypted but doesn't contain a nonce", entry.id).into())),
+				(None, _) => return Err(InternalError::NoKeypairError),
+			}
+
+			#[cfg(not(feature = "crypto"))]
+			return Err(InternalError::MissingFeatureError("crypto"));
+		}
+
+		// Decompression layer, streamed through the adjacent frame decoder
+		if entry.flags.contains(Flags::COMPRESSED_FLAG) {
+			#[cfg(feature = "compression")]
+			match CompressionAlgorithm::from_flags(entry.flags) {
+				Some(_) if entry.flags.contains(Flags::DICTIONARY_COMPRESSED) => inner = self.dictionary_decoder(entry, inner)?,
+				Some(algo) => inner = Compressor::new(inner).into_decoder(algo)?,
+				None if entry.flags.contains(Flags::CODEC_FLAG) => inner = self.codec_decoder(entry, inner)?,
+				None => return Err(InternalError::OtherError(format!("Unable to determine the compression algorithm used for entry: {}", entry).into())),
+			}
+
+			#[cfg(not(feature = "compression"))]
+			return Err(InternalError::MissingFeatureError("compression"));
+		}
+
+		Ok(ResourceReader { flags: entry.flags, inner })
+	}
+
+	/// Fetch a [`Resource`] with the given `ID`.
+	/// Locks the underlying [`Mutex`](std::sync::Mutex) of [`Read`] + [`Seek`] handles, for a cheaper non-locking operation refer to [`Archive::fetch_mut`].
+	/// Archives loaded using [`from_source`](Archive::from_source) are never locked.
+	pub fn fetch(
+		&self,
+		id: impl AsRef<str>,
+	) -> InternalResult<Resource> {
+		if let Some(entry) = self.fetch_entry(&id) {
+			if let Some(range) = entry.block {
+				return Ok(Resource {
+					flags: entry.flags,
+					data: self.fetch_solid(&entry, range)?.into_boxed_slice(),
+					verified: false,
+				});
+			}
+
+			let mut raw = Archive::<T>::raw_buffer(&entry);
+			self.handle.read_exact_at(&mut raw, entry.location)?;
+
+			// Decompress and|or decrypt the data
+			let (buffer, is_secure) = self.process(&entry, raw)?;
+
+			Ok(Resource {
+				flags: entry.flags,
+				data: buffer.into_boxed_slice(),
+				verified: is_secure,
+			})
+		} else {
+			Err(InternalError::MissingResourceError(id.as_ref().to_string()))
+		}
+	}
+}
+
+impl<T> Archive<T> {
+	/// Shared implementation of [`fetch_borrowed`](Archive::fetch_borrowed), over the source's underlying slice
+	fn fetch_borrowed_from<'a>(
+		&self,
+		source: &'a [u8],
+		id: &str,
+	) -> InternalResult<BorrowedResource<'a>> {
+		let Some(entry) = self.entries.get(id) else {
+			return Err(InternalError::MissingResourceError(id.to_string()));
+		};
+
+		if let Some(range) = entry.block {
+			return Ok(BorrowedResource {
+				data: Cow::Owned(self.fetch_solid(entry, range)?),
+				flags: entry.flags,
+				verified: false,
+			});
+		}
+
+		let raw = usize::try_from(entry.location)
+			.ok()
+			.zip(entry.location.checked_add(entry.offset).and_then(|end| usize::try_from(end).ok()))
+			.and_then(|(start, end)| source.get(start..end))
+			.ok_or(InternalError::IOError(std::io::ErrorKind::UnexpectedEof.into()))?;
+
+		let mut needs_processing = entry.flags.contains(Flags::COMPRESSED_FLAG) || entry.flags.contains(Flags::ENCRYPTED_FLAG);
+
+		#[cfg(feature = "crypto")]
+		{
+			needs_processing |= self.key.is_some() && entry.signature.is_some();
+		}
+
+		if needs_processing {
+			let (buffer, verified) = self.process(entry, raw.to_vec())?;
+
+			Ok(BorrowedResource {
+				data: Cow::Owned(buffer),
+				flags: entry.flags,
+				verified,
+			})
+		} else {
+			Archive::<T>::verify_checksum(entry, raw)?;
+
+			Ok(BorrowedResource {
+				data: Cow::Borrowed(raw),
+				flags: entry.flags,
+				verified: false,
+			})
+		}
+	}
+}
+
+impl<T> Archive<T>
+where
+	Archive<T>: Sync,
+{
+	/// Audits every entry in the archive, reporting on each without stopping at the first failure.
+	/// Entries are checked to lie within the source, then their checksums and signatures are checked and they are decrypted and decompressed.
+	/// The work is spread across `threads` threads, set to 1 to check every entry on the calling thread. Reports are sorted by ID
+	pub fn verify_all(
+		&self,
+		threads: usize,
+	) -> Vec<EntryReport> {
+		// neighbouring entries are checked together, so entries sharing a solid block only decompress it once
+		let mut entries = self.entries.values().collect::<Vec<_>>();
+		entries.sort_by_key(|entry| entry.location);
+
+		let audit = |entry: &&RegistryEntry| EntryReport {
+			id: entry.id.clone(),
+			status: self.entry_status(entry),
+		};
+
+		let mut reports = if threads > 1 && entries.len() > 1 {
+			let chunk_size = entries.len().div_ceil(threads);
+
+			thread::scope(|s| {
+				let workers = entries.chunks(chunk_size).map(|chunk| s.spawn(move || chunk.iter().map(audit).collect::<Vec<_>>())).collect::<Vec<_>>();
+				workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect::<Vec<_>>()
+			})
+		} else {
+			entries.iter().map(audit).collect()
+		};
+
+		reports.sort_by(|a, b| a.id.cmp(&b.id));
+		reports
+	}
+
+	/// Audits a single entry, see [`verify_all`](Archive::verify_all)
+	fn entry_status(
+		&self,
+		entry: &RegistryEntry,
+	) -> EntryStatus {
+		// the last byte is probed first, so bogus offsets aren't allocated
+		let Some(end) = entry.location.checked_add(entry.offset) else {
+			return EntryStatus::OutOfBounds;
+		};
+
+		if entry.offset > 0 {
+			match self.handle.read_exact_at(&mut [0], end - 1) {
+				Ok(()) => {},
+				Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return EntryStatus::OutOfBounds,
+				Err(err) => return EntryStatus::ReadFailure(err.into()),
+			}
+		}
+
+		let Some(range) = entry.block else {
+			return match self.audit(entry) {
+				Ok((_, status)) | Err(status) => status,
+			};
+		};
+
+		// solid blocks are cached once checked, entries sharing them only check their range
+		let block = match self.blocks.get(entry.location) {
+			Some(block) => block,
+			None => match self.audit(entry) {
+				Ok((data, _)) => {
+					let block: Arc<[u8]> = data.into();
+					self.blocks.insert(entry.location, block.clone());
+					block
+				},
+				Err(status) => return status,
+			},
+		};
+
+		match range.start.checked_add(range.length) {
+			Some(end) if end <= block.len() as u64 => EntryStatus::Unsigned,
+			_ => EntryStatus::OutOfBounds,
+		}
+	}
+
+	/// Runs an entry through each stage of [`process`](Archive::process), attributing failures to the stage they happened in.
+	/// Returns the processed data, and whether it's signature holds
+	fn audit(
+		&self,
+		entry: &RegistryEntry,
+	) -> Result<(Vec<u8>, EntryStatus), EntryStatus> {
+		let mut raw = Archive::<T>::raw_buffer(entry);
+		self.handle.read_exact_at(&mut raw, entry.location).map_err(|err| EntryStatus::ReadFailure(err.into()))?;
+
+		Archive::<T>::verify_checksum(entry, &raw).map_err(|_| EntryStatus::ChecksumMismatch)?;
+
+		#[cfg(feature = "crypto")]
+		let status = match (self.key.is_some() && entry.signature.is_some(), self.verify_signature(entry, &mut raw)) {
+			(true, true) => EntryStatus::Ok,
+			(true, false) => return Err(EntryStatus::BadSignature),
+			(false, _) => EntryStatus::Unsigned,
+		};
+		#[cfg(not(feature = "crypto"))]
+		let status = EntryStatus::Unsigned;
+
+		let data = self.decrypt_entry(entry, &raw).map_err(EntryStatus::DecryptFailure)?.unwrap_or(raw);
+
+		if !entry.flags.contains(Flags::COMPRESSED_FLAG) {
+			return Ok((data, status));
+		}
+
+		let mut target = Vec::with_capacity(data.len());
+		self.decompress_entry(entry, &data, &mut target).map_err(EntryStatus::DecompressFailure)?;
+
+		Ok((target, status))
+	}
+}
+
+impl<'a> Archive<Cursor<&'a [u8]>> {
+	/// Fetch a [`BorrowedResource`] with the given `ID`, from an [`Archive`] backed by a byte slice or memory map.
+	/// Plain entries are returned as slices pointing straight into the source, without copying.
+	/// Entries that are compressed, encrypted or have a signature to check are processed into an owned buffer as in [`fetch`](Archive::fetch)
+	pub fn fetch_borrowed(
+		&self,
+		id: impl AsRef<str>,
+	) -> InternalResult<BorrowedResource<'a>> {
+		// copy out the slice, so it outlives the guard
+		let source: &'a [u8] = match &self.handle {
+			Handle::Locked(handle, _) => handle.lock().map_err(poisoned)?.get_ref(),
+			Handle::Positional(handle, _) => handle.get_ref(),
+		};
+
+		self.fetch_borrowed_from(source, id.as_ref())
+	}
+}
+
+impl<'a> Archive<&'a [u8]> {
+	/// Fetch a [`BorrowedResource`] with the given `ID`, from an [`Archive`] backed by a byte slice or memory map.
+	/// Plain entries are returned as slices pointing straight into the source, without copying.
+	/// Entries that are compressed, encrypted or have a signature to check are processed into an owned buffer as in [`fetch`](Archive::fetch)
+	pub fn fetch_borrowed(
+		&self,
+		id: impl AsRef<str>,
+	) -> InternalResult<BorrowedResource<'a>> {
+		let source: &'a [u8] = match &self.handle {
+			Handle::Locked(handle, _) => *handle.lock().map_err(poisoned)?,
+			Handle::Positional(handle, _) => handle,
+		};
+
+		self.fetch_borrowed_from(source, id.as_ref())
+	}
+}
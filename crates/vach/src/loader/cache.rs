@@ -0,0 +1,64 @@
+use std::{
+	collections::VecDeque,
+	fmt,
+	sync::{Arc, Mutex},
+};
+
+/// Default number of decompressed solid blocks kept around by an [`Archive`](crate::archive::Archive)
+pub(crate) const DEFAULT_BLOCK_CACHE: usize = 8;
+
+/// Recently decompressed solid blocks keyed by their location, so neighbouring entries don't decompress the same block again.
+/// Evicts the least recently used block once full
+pub(crate) struct BlockCache {
+	capacity: usize,
+	blocks: Mutex<VecDeque<(u64, Arc<[u8]>)>>,
+}
+
+impl BlockCache {
+	pub(crate) fn new(capacity: usize) -> BlockCache {
+		BlockCache {
+			capacity,
+			blocks: Mutex::new(VecDeque::with_capacity(capacity)),
+		}
+	}
+
+	/// Fetches the block at a location, marking it as the most recently used
+	pub(crate) fn get(
+		&self,
+		location: u64,
+	) -> Option<Arc<[u8]>> {
+		let mut blocks = self.blocks.lock().unwrap();
+
+		let idx = blocks.iter().position(|(l, _)| *l == location)?;
+		let block = blocks.remove(idx)?;
+		blocks.push_front(block.clone());
+
+		Some(block.1)
+	}
+
+	pub(crate) fn insert(
+		&self,
+		location: u64,
+		block: Arc<[u8]>,
+	) {
+		if self.capacity == 0 {
+			return;
+		}
+
+		let mut blocks = self.blocks.lock().unwrap();
+
+		blocks.retain(|(l, _)| *l != location);
+		blocks.push_front((location, block));
+		blocks.truncate(self.capacity);
+	}
+}
+
+impl fmt::Debug for BlockCache {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		let cached = self.blocks.lock().map(|blocks| blocks.len()).unwrap_or(0);
+		f.debug_struct("BlockCache").field("capacity", &self.capacity).field("cached", &cached).finish()
+	}
+}
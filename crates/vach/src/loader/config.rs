@@ -0,0 +1,153 @@
+use std::fmt;
+
+#[cfg(feature = "crypto")]
+use crate::crypto;
+
+#[cfg(feature = "compression")]
+use crate::global::codec::{Codec, CodecRegistry};
+use super::{cache::DEFAULT_BLOCK_CACHE, tree::DEFAULT_SEPARATOR};
+
+/// Settings for loading an [`Archive`](crate::archive::Archive), see [`Archive::with_config`](crate::archive::Archive::with_config)
+#[derive(Clone)]
+pub struct ArchiveConfig {
+	/// An optional public key, used to verify the archive's manifest and entry signatures
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub verifying_key: Option<crypto::VerifyingKey>,
+	/// An optional symmetric key, used to decrypt encrypted entries. Should match the [`BuilderConfig::encryption_key`](crate::builder::BuilderConfig::encryption_key) used when building.
+	/// Archives older than spec version 70 derive this from the [`verifying_key`](ArchiveConfig::verifying_key) instead
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub decryption_key: Option<crypto::EncryptionKey>,
+	/// An optional passphrase, for archives built with [`BuilderConfig::passphrase`](crate::builder::BuilderConfig::passphrase).
+	/// The encryption key is derived using the salt and parameters stored in the archive's header, unless a [`decryption_key`](ArchiveConfig::decryption_key) is provided
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub passphrase: Option<String>,
+	/// An optional private key, for archives built with [`BuilderConfig::recipients`](crate::builder::BuilderConfig::recipients).
+	/// The encryption key is unwrapped from the archive's recipients section, unless a [`decryption_key`](ArchiveConfig::decryption_key) or [`passphrase`](ArchiveConfig::passphrase) is provided
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub recipient_key: Option<crypto::RecipientSecret>,
+	/// User-defined codecs, used to decompress entries compressed with them. Should register the same IDs as [`BuilderConfig::codecs`](crate::builder::BuilderConfig::codecs)
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub codecs: CodecRegistry,
+	/// How many decompressed solid blocks to keep in memory, so fetching neighbouring entries from the same block doesn't decompress it again. Defaults to 8
+	pub block_cache: usize,
+	/// Splits entry IDs into directories, for [`Archive::read_dir`](crate::archive::Archive::read_dir), [`walk`](crate::archive::Archive::walk) and [`glob`](crate::archive::Archive::glob). Defaults to `/`
+	pub separator: char,
+}
+
+impl Default for ArchiveConfig {
+	fn default() -> Self {
+		ArchiveConfig {
+			#[cfg(feature = "crypto")]
+			verifying_key: None,
+			#[cfg(feature = "crypto")]
+			decryption_key: None,
+			#[cfg(feature = "crypto")]
+			passphrase: None,
+			#[cfg(feature = "crypto")]
+			recipient_key: None,
+			#[cfg(feature = "compression")]
+			codecs: CodecRegistry::default(),
+			block_cache: DEFAULT_BLOCK_CACHE,
+			separator: DEFAULT_SEPARATOR,
+		}
+	}
+}
+
+// Helper functions
+impl ArchiveConfig {
+	/// Setter for the [`verifying_key`](ArchiveConfig::verifying_key) field
+	#[cfg(feature = "crypto")]
+	pub fn key(
+		mut self,
+		verifying_key: crypto::VerifyingKey,
+	) -> Self {
+		self.verifying_key = Some(verifying_key);
+		self
+	}
+
+	/// Setter for the [`decryption_key`](ArchiveConfig::decryption_key) field
+	#[cfg(feature = "crypto")]
+	pub fn decryption_key(
+		mut self,
+		decryption_key: crypto::EncryptionKey,
+	) -> Self {
+		self.decryption_key = Some(decryption_key);
+		self
+	}
+
+	/// Setter for the [`passphrase`](ArchiveConfig::passphrase) field
+	#[cfg(feature = "crypto")]
+	pub fn passphrase(
+		mut self,
+		passphrase: impl Into<String>,
+	) -> Self {
+		self.passphrase = Some(passphrase.into());
+		self
+	}
+
+	/// Setter for the [`recipient_key`](ArchiveConfig::recipient_key) field
+	#[cfg(feature = "crypto")]
+	pub fn recipient_key(
+		mut self,
+		recipient_key: crypto::RecipientSecret,
+	) -> Self {
+		self.recipient_key = Some(recipient_key);
+		self
+	}
+
+	/// Registers a [`Codec`] under an ID in the [`codecs`](ArchiveConfig::codecs) field
+	#[cfg(feature = "compression")]
+	pub fn codec(
+		mut self,
+		id: u16,
+		codec: impl Codec + 'static,
+	) -> Self {
+		self.codecs = self.codecs.register(id, codec);
+		self
+	}
+
+	/// Setter for the [`block_cache`](ArchiveConfig::block_cache) field
+	pub fn block_cache(
+		mut self,
+		blocks: usize,
+	) -> Self {
+		self.block_cache = blocks;
+		self
+	}
+
+	/// Setter for the [`separator`](ArchiveConfig::separator) field
+	pub fn separator(
+		mut self,
+		separator: char,
+	) -> Self {
+		self.separator = separator;
+		self
+	}
+}
+
+impl fmt::Debug for ArchiveConfig {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		let mut debug = f.debug_struct("ArchiveConfig");
+
+		// keep secrets out of logs
+		#[cfg(feature = "crypto")]
+		debug
+			.field("verifying_key", &self.verifying_key)
+			.field("decryption_key", &self.decryption_key.map(|_| "<EncryptionKey>"))
+			.field("passphrase", &self.passphrase.as_ref().map(|_| "<Passphrase>"))
+			.field("recipient_key", &self.recipient_key.as_ref().map(|_| "<RecipientSecret>"));
+
+		#[cfg(feature = "compression")]
+		debug.field("codecs", &self.codecs);
+
+		debug.field("block_cache", &self.block_cache).field("separator", &self.separator).finish()
+	}
+}
@@ -1,2 +1,8 @@
-pub mod archive;
-pub mod resource;
+pub mod archive;
+mod cache;
+pub mod config;
+pub mod reader;
+pub mod report;
+pub mod resource;
+pub mod source;
+pub mod tree;
@@ -0,0 +1,96 @@
+use std::{
+	fmt,
+	hash::Hasher,
+	io::{self, Read},
+};
+
+use twox_hash::XxHash64;
+
+use super::source::Handle;
+use crate::global::{error::InternalError, flags::Flags};
+
+/// Size of the buffer sitting between a [`ResourceReader`] and the archive's source, limits how often the source is locked
+pub(crate) const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A streaming counterpart to [`Resource`](super::resource::Resource), returned by [`Archive::fetch_reader`](crate::archive::Archive::fetch_reader).
+/// Data is decompressed as bytes are pulled from the reader, so memory usage stays bounded regardless of the size of the entry.
+pub struct ResourceReader<'a> {
+	/// The flags extracted from the archive's registry entry
+	pub flags: Flags,
+	pub(crate) inner: Box<dyn Read + 'a>,
+}
+
+impl Read for ResourceReader<'_> {
+	#[inline(always)]
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		self.inner.read(buf)
+	}
+}
+
+impl fmt::Debug for ResourceReader<'_> {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		f.debug_struct("ResourceReader").field("flags", &self.flags).field("inner", &"<dyn Read>").finish()
+	}
+}
+
+/// Reads a bounded region of a shared source, only locking [`Read`] + [`Seek`] sources for the duration of each individual read
+pub(crate) struct EntrySource<'a, T> {
+	pub(crate) handle: &'a Handle<T>,
+	pub(crate) position: u64,
+	pub(crate) end: u64,
+}
+
+impl<T> Read for EntrySource<'_, T> {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		let remaining = self.end.saturating_sub(self.position);
+		if remaining == 0 || buf.is_empty() {
+			return Ok(0);
+		}
+
+		// the entry is expected to span the whole region, so a short source is an error
+		let length = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+		self.handle.read_exact_at(&mut buf[..length], self.position)?;
+
+		self.position += length as u64;
+		Ok(length)
+	}
+}
+
+/// Checksums an entry's data as it's read, failing the read that completes it if it doesn't match the entry's checksum
+pub(crate) struct ChecksumReader<'a, R> {
+	pub(crate) inner: R,
+	pub(crate) hasher: XxHash64,
+	pub(crate) expected: u64,
+	pub(crate) remaining: u64,
+	pub(crate) id: &'a str,
+}
+
+impl<R: Read> Read for ChecksumReader<'_, R> {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		if read == 0 {
+			return Ok(0);
+		}
+
+		self.hasher.write(&buf[..read]);
+		self.remaining = self.remaining.saturating_sub(read as u64);
+
+		if self.remaining == 0 && self.hasher.finish() != self.expected {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, InternalError::ChecksumMismatchError(self.id.to_string())));
+		}
+
+		Ok(read)
+	}
+}
@@ -0,0 +1,58 @@
+use std::{fmt, sync::Arc};
+
+use crate::global::error::InternalError;
+
+/// The outcome of auditing a single entry, see [`Archive::verify_all`](crate::archive::Archive::verify_all)
+#[derive(Debug)]
+pub enum EntryStatus {
+	/// The entry's data was read, decrypted and decompressed, and it's signature holds
+	Ok,
+	/// The entry's data was read, decrypted and decompressed, but no signature was checked. Either it isn't signed, or the archive was loaded without a verifying key
+	Unsigned,
+	/// The entry's signature doesn't hold, it's data or ID may have been tampered with
+	BadSignature,
+	/// The entry's data doesn't match it's checksum, see [`Flags::CHECKSUM_FLAG`](crate::prelude::Flags::CHECKSUM_FLAG)
+	ChecksumMismatch,
+	/// The entry's data lies past the end of the source, or outside it's solid block
+	OutOfBounds,
+	/// Reading the entry's data from the source failed
+	ReadFailure(InternalError),
+	/// The entry failed to decrypt, either it's data was tampered with or the decryption key is wrong or missing
+	DecryptFailure(InternalError),
+	/// The entry failed to decompress
+	DecompressFailure(InternalError),
+}
+
+impl EntryStatus {
+	/// Whether the entry's data could be read in full, [`Ok`](EntryStatus::Ok) or [`Unsigned`](EntryStatus::Unsigned)
+	pub fn is_intact(&self) -> bool {
+		matches!(self, EntryStatus::Ok | EntryStatus::Unsigned)
+	}
+}
+
+impl fmt::Display for EntryStatus {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		match self {
+			EntryStatus::Ok => write!(f, "ok"),
+			EntryStatus::Unsigned => write!(f, "unsigned"),
+			EntryStatus::BadSignature => write!(f, "bad signature"),
+			EntryStatus::ChecksumMismatch => write!(f, "checksum mismatch"),
+			EntryStatus::OutOfBounds => write!(f, "out of bounds"),
+			EntryStatus::ReadFailure(err) => write!(f, "read failure: {err}"),
+			EntryStatus::DecryptFailure(err) => write!(f, "decrypt failure: {err}"),
+			EntryStatus::DecompressFailure(err) => write!(f, "decompress failure: {err}"),
+		}
+	}
+}
+
+/// An entry's ID and the outcome of auditing it, returned by [`Archive::verify_all`](crate::archive::Archive::verify_all)
+#[derive(Debug)]
+pub struct EntryReport {
+	/// The ID of the audited entry
+	pub id: Arc<str>,
+	/// What was found
+	pub status: EntryStatus,
+}
@@ -1,22 +1,48 @@
-use crate::global::flags::Flags;
-use std::fmt;
-
-/// Basically processed data obtained from an archive returned by [`archive.fetch(...)`](crate::archive::Archive::fetch) and [`archive.fetch_mut(...)`](crate::archive::Archive::fetch_mut)
-#[derive(Debug, Clone)]
-pub struct Resource {
-	/// The parsed data
-	pub data: Box<[u8]>,
-	/// The flags extracted from the archive's registry entry
-	pub flags: Flags,
-	/// If a [`Resource's`](Resource) bytes were signed and the signature check passed
-	pub verified: bool,
-}
-
-impl fmt::Display for Resource {
-	fn fmt(
-		&self,
-		f: &mut fmt::Formatter,
-	) -> fmt::Result {
-		write!(f, "[Resource] size: {size} bytes, flags: {flags}", size = self.data.len(), flags = &self.flags,)
-	}
-}
+use crate::global::flags::Flags;
+use std::{borrow::Cow, fmt};
+
+/// Basically processed data obtained from an archive returned by [`archive.fetch(...)`](crate::archive::Archive::fetch) and [`archive.fetch_mut(...)`](crate::archive::Archive::fetch_mut)
+#[derive(Debug, Clone)]
+pub struct Resource {
+	/// The parsed data
+	pub data: Box<[u8]>,
+	/// The flags extracted from the archive's registry entry
+	pub flags: Flags,
+	/// If a [`Resource's`](Resource) bytes were signed and the signature check passed
+	pub verified: bool,
+}
+
+impl fmt::Display for Resource {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter,
+	) -> fmt::Result {
+		write!(f, "[Resource] size: {size} bytes, flags: {flags}", size = self.data.len(), flags = &self.flags,)
+	}
+}
+
+/// A [`Resource`] that may borrow it's data straight from the archive's source, returned by [`archive.fetch_borrowed(...)`](crate::archive::Archive::fetch_borrowed).
+/// Plain entries point directly into the source, entries that need decryption or decompression fall back to an owned buffer.
+#[derive(Debug, Clone)]
+pub struct BorrowedResource<'a> {
+	/// The parsed data, [`Cow::Borrowed`] if no processing was necessary
+	pub data: Cow<'a, [u8]>,
+	/// The flags extracted from the archive's registry entry
+	pub flags: Flags,
+	/// If a [`BorrowedResource's`](BorrowedResource) bytes were signed and the signature check passed
+	pub verified: bool,
+}
+
+impl fmt::Display for BorrowedResource<'_> {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter,
+	) -> fmt::Result {
+		let kind = match self.data {
+			Cow::Borrowed(_) => "borrowed",
+			Cow::Owned(_) => "owned",
+		};
+
+		write!(f, "[BorrowedResource] size: {size} bytes ({kind}), flags: {flags}", size = self.data.len(), flags = &self.flags,)
+	}
+}
@@ -0,0 +1,268 @@
+use std::{
+	fmt, fs,
+	io::{self, Cursor, Read, Seek, SeekFrom},
+	sync::{Arc, Mutex, PoisonError},
+};
+
+/// Sources that can be read from at arbitrary offsets through a shared reference, ie `pread`.
+/// An [`Archive`](crate::archive::Archive) built over one using [`Archive::from_source`](crate::archive::Archive::from_source) fetches concurrently without locking.
+pub trait ReadAt {
+	/// Read bytes starting at `offset` into `buf`, returning the number of bytes read. Does not affect any cursor
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize>;
+
+	/// Fill `buf` with bytes starting at `offset`, failing with [`io::ErrorKind::UnexpectedEof`] if the source is too short
+	fn read_exact_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<()> {
+		fill(|buf, offset| self.read_at(buf, offset), buf, offset)
+	}
+}
+
+impl ReadAt for [u8] {
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		let start = usize::try_from(offset).unwrap_or(usize::MAX).min(self.len());
+		let length = buf.len().min(self.len() - start);
+
+		buf[..length].copy_from_slice(&self[start..start + length]);
+		Ok(length)
+	}
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		(**self).read_at(buf, offset)
+	}
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		(**self).read_at(buf, offset)
+	}
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		(**self).read_at(buf, offset)
+	}
+}
+
+impl ReadAt for Vec<u8> {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		self.as_slice().read_at(buf, offset)
+	}
+}
+
+/// Ignores the cursor's position, reading directly from the inner buffer
+impl<T: AsRef<[u8]>> ReadAt for Cursor<T> {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		self.get_ref().as_ref().read_at(buf, offset)
+	}
+}
+
+#[cfg(unix)]
+impl ReadAt for fs::File {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		std::os::unix::fs::FileExt::read_at(self, buf, offset)
+	}
+}
+
+/// On windows, this moves the file's cursor
+#[cfg(windows)]
+impl ReadAt for fs::File {
+	#[inline(always)]
+	fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+	}
+}
+
+/// How an [`Archive`](crate::archive::Archive) reaches it's source.
+/// The read functions are resolved at construction, when the source's capabilities are known
+pub(crate) enum Handle<T> {
+	/// [`Read`] + [`Seek`] sources, which have to be locked for every `seek` + `read`
+	Locked(Mutex<T>, fn(&mut T, &mut [u8], u64) -> io::Result<usize>),
+	/// [`ReadAt`] sources, read from concurrently
+	Positional(T, fn(&T, &mut [u8], u64) -> io::Result<usize>),
+}
+
+impl<T> Handle<T> {
+	pub(crate) fn locked(handle: T) -> Handle<T>
+	where
+		T: Read + Seek,
+	{
+		Handle::Locked(Mutex::new(handle), |handle, buf, offset| {
+			handle.seek(SeekFrom::Start(offset))?;
+			handle.read(buf)
+		})
+	}
+
+	pub(crate) fn positional(handle: T) -> Handle<T>
+	where
+		T: ReadAt,
+	{
+		Handle::Positional(handle, |handle, buf, offset| handle.read_at(buf, offset))
+	}
+
+	/// Read bytes starting at `offset` into `buf`, only locks [`Handle::Locked`] sources
+	pub(crate) fn read_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<usize> {
+		match self {
+			Handle::Locked(handle, read) => read(&mut *handle.lock().map_err(poisoned)?, buf, offset),
+			Handle::Positional(handle, read) => read(handle, buf, offset),
+		}
+	}
+
+	/// Fill `buf` with bytes starting at `offset`, [`Handle::Locked`] sources are locked once for the whole read
+	pub(crate) fn read_exact_at(
+		&self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<()> {
+		match self {
+			Handle::Locked(handle, read) => {
+				let mut guard = handle.lock().map_err(poisoned)?;
+				fill(|buf, offset| read(&mut guard, buf, offset), buf, offset)
+			},
+			Handle::Positional(handle, read) => fill(|buf, offset| read(handle, buf, offset), buf, offset),
+		}
+	}
+
+	/// Lock-free alternative to [`read_exact_at`](Handle::read_exact_at)
+	pub(crate) fn read_exact_at_mut(
+		&mut self,
+		buf: &mut [u8],
+		offset: u64,
+	) -> io::Result<()> {
+		match self {
+			Handle::Locked(handle, read) => {
+				let handle = handle.get_mut().map_err(poisoned)?;
+				fill(|buf, offset| read(handle, buf, offset), buf, offset)
+			},
+			Handle::Positional(handle, read) => fill(|buf, offset| read(handle, buf, offset), buf, offset),
+		}
+	}
+
+	pub(crate) fn into_inner(self) -> Result<T, PoisonError<T>> {
+		match self {
+			Handle::Locked(handle, _) => handle.into_inner(),
+			Handle::Positional(handle, _) => Ok(handle),
+		}
+	}
+}
+
+impl<T: fmt::Debug> fmt::Debug for Handle<T> {
+	fn fmt(
+		&self,
+		f: &mut fmt::Formatter<'_>,
+	) -> fmt::Result {
+		match self {
+			Handle::Locked(handle, _) => f.debug_tuple("Locked").field(handle).finish(),
+			Handle::Positional(handle, _) => f.debug_tuple("Positional").field(handle).finish(),
+		}
+	}
+}
+
+#[inline(always)]
+pub(crate) fn poisoned<G>(_: PoisonError<G>) -> io::Error {
+	io::Error::other("Archive source Mutex was poisoned")
+}
+
+/// Repeatedly calls `read` until `buf` is full
+fn fill(
+	mut read: impl FnMut(&mut [u8], u64) -> io::Result<usize>,
+	mut buf: &mut [u8],
+	mut offset: u64,
+) -> io::Result<()> {
+	while !buf.is_empty() {
+		match read(buf, offset) {
+			Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+			Ok(n) => {
+				buf = &mut buf[n..];
+				offset += n as u64;
+			},
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(())
+}
+
+/// Sequential [`Read`] + [`Seek`] access over a [`Handle`], used when parsing the header and registry
+pub(crate) struct HandleReader<'a, T> {
+	pub(crate) handle: &'a Handle<T>,
+	pub(crate) position: u64,
+}
+
+impl<T> Read for HandleReader<'_, T> {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		let read = self.handle.read_at(buf, self.position)?;
+		self.position += read as u64;
+
+		Ok(read)
+	}
+}
+
+impl<T> Seek for HandleReader<'_, T> {
+	fn seek(
+		&mut self,
+		pos: SeekFrom,
+	) -> io::Result<u64> {
+		self.position = match pos {
+			SeekFrom::Start(s) => s,
+			SeekFrom::Current(c) => self.position.checked_add_signed(c).ok_or(io::ErrorKind::InvalidInput)?,
+			SeekFrom::End(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "Archive sources can't seek from the end")),
+		};
+
+		Ok(self.position)
+	}
+}
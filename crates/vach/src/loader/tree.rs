@@ -0,0 +1,212 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::Arc,
+};
+
+use crate::global::reg_entry::RegistryEntry;
+
+/// Default separator between the components of entry IDs, see [`ArchiveConfig::separator`](crate::archive::ArchiveConfig::separator)
+pub(crate) const DEFAULT_SEPARATOR: char = '/';
+
+/// An item directly under a directory, returned by [`Archive::read_dir`](crate::archive::Archive::read_dir)
+#[derive(Debug, Clone, Copy)]
+pub enum DirEntry<'a> {
+	/// A directory, holding it's name
+	Dir(&'a str),
+	/// An entry, holding the last component of it's ID and it's [`RegistryEntry`]
+	File(&'a str, &'a RegistryEntry),
+}
+
+impl<'a> DirEntry<'a> {
+	/// The name of the directory, or the last component of the entry's ID
+	pub fn name(&self) -> &'a str {
+		match self {
+			DirEntry::Dir(name) | DirEntry::File(name, _) => name,
+		}
+	}
+
+	/// Whether this is a directory
+	pub fn is_dir(&self) -> bool {
+		matches!(self, DirEntry::Dir(_))
+	}
+}
+
+/// A directory, it's children keyed by their name. An ID may name both an entry and a directory, eg `a` and `a/b`
+#[derive(Debug, Default)]
+struct DirNode {
+	dirs: BTreeMap<Box<str>, DirNode>,
+	files: BTreeMap<Box<str>, Arc<str>>,
+}
+
+impl DirNode {
+	/// Every entry under this directory, depth first with a directory's entries before it's subdirectories
+	fn collect<'a>(
+		&'a self,
+		output: &mut Vec<&'a Arc<str>>,
+	) {
+		output.extend(self.files.values());
+
+		for dir in self.dirs.values() {
+			dir.collect(output);
+		}
+	}
+
+	/// Entries matching the glob `segments`, which is never empty
+	fn glob<'a>(
+		&'a self,
+		segments: &[&str],
+		output: &mut Vec<&'a Arc<str>>,
+	) {
+		let (segment, rest) = (segments[0], &segments[1..]);
+
+		match (segment, rest.is_empty()) {
+			// a trailing `**` matches everything below
+			("**", true) => self.collect(output),
+			// `**` matches zero or more directories
+			("**", false) => {
+				self.glob(rest, output);
+
+				for dir in self.dirs.values() {
+					dir.glob(segments, output);
+				}
+			},
+			(segment, true) => output.extend(self.files.iter().filter(|(name, _)| wildcard_match(segment, name)).map(|(_, id)| id)),
+			// literal segments are looked up directly
+			(segment, false) if !segment.contains(['*', '?']) => {
+				if let Some(dir) = self.dirs.get(segment) {
+					dir.glob(rest, output);
+				}
+			},
+			(segment, false) => {
+				for (_, dir) in self.dirs.iter().filter(|(name, _)| wildcard_match(segment, name)) {
+					dir.glob(rest, output);
+				}
+			},
+		}
+	}
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` any single character
+fn wildcard_match(
+	pattern: &str,
+	name: &str,
+) -> bool {
+	let (pattern, name) = (pattern.chars().collect::<Vec<_>>(), name.chars().collect::<Vec<_>>());
+	let (mut p, mut n) = (0, 0);
+
+	// where to resume from if the last `*` has to swallow another character
+	let mut backtrack = None;
+
+	while n < name.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				backtrack = Some((p, n));
+				p += 1;
+			},
+			Some(c) if *c == '?' || *c == name[n] => {
+				p += 1;
+				n += 1;
+			},
+			_ => match backtrack {
+				Some((star, matched)) => {
+					backtrack = Some((star, matched + 1));
+					p = star + 1;
+					n = matched + 1;
+				},
+				None => return false,
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Index of an archive's entries as a directory tree, splitting their IDs at a separator. Built once on load
+#[derive(Debug)]
+pub(crate) struct DirTree {
+	pub(crate) separator: char,
+	root: DirNode,
+}
+
+impl DirTree {
+	pub(crate) fn new<'a>(
+		ids: impl Iterator<Item = &'a Arc<str>>,
+		separator: char,
+	) -> DirTree {
+		let mut root = DirNode::default();
+
+		for id in ids {
+			let (parent, name) = match id.rsplit_once(separator) {
+				Some((parent, name)) => (Some(parent), name),
+				None => (None, id.as_ref()),
+			};
+
+			let mut node = &mut root;
+			for component in parent.into_iter().flat_map(|parent| parent.split(separator)) {
+				node = node.dirs.entry(component.into()).or_default();
+			}
+
+			node.files.insert(name.into(), id.clone());
+		}
+
+		DirTree { separator, root }
+	}
+
+	/// The directory at `prefix`, a trailing separator is ignored and an empty prefix is the root
+	fn find(
+		&self,
+		prefix: &str,
+	) -> Option<&DirNode> {
+		let prefix = prefix.strip_suffix(self.separator).unwrap_or(prefix);
+
+		if prefix.is_empty() {
+			return Some(&self.root);
+		}
+
+		prefix.split(self.separator).try_fold(&self.root, |node, component| node.dirs.get(component))
+	}
+
+	pub(crate) fn read_dir<'a>(
+		&'a self,
+		entries: &'a HashMap<Arc<str>, RegistryEntry>,
+		prefix: &str,
+	) -> Option<Vec<DirEntry<'a>>> {
+		let node = self.find(prefix)?;
+
+		let dirs = node.dirs.keys().map(|name| DirEntry::Dir(name));
+		let files = node.files.iter().filter_map(|(name, id)| Some(DirEntry::File(name, entries.get(id)?)));
+
+		Some(dirs.chain(files).collect())
+	}
+
+	pub(crate) fn walk<'a>(
+		&'a self,
+		entries: &'a HashMap<Arc<str>, RegistryEntry>,
+		prefix: &str,
+	) -> Vec<&'a RegistryEntry> {
+		let mut ids = Vec::new();
+
+		if let Some(node) = self.find(prefix) {
+			node.collect(&mut ids);
+		}
+
+		ids.into_iter().filter_map(|id| entries.get(id)).collect()
+	}
+
+	pub(crate) fn glob<'a>(
+		&'a self,
+		entries: &'a HashMap<Arc<str>, RegistryEntry>,
+		pattern: &str,
+	) -> Vec<&'a RegistryEntry> {
+		let segments = pattern.split(self.separator).collect::<Vec<_>>();
+
+		let mut ids = Vec::new();
+		self.root.glob(&segments, &mut ids);
+
+		// several `**` may reach the same entry in different ways
+		ids.sort();
+		ids.dedup();
+
+		ids.into_iter().filter_map(|id| entries.get(id)).collect()
+	}
+}
@@ -1,486 +1,1965 @@
-// This is meant to mirror as closely as possible, how users should use the crate
-#![cfg(test)]
-
-use crate::prelude::*;
-use std::{fs::File, str};
-
-// Contains both the public key and secret key in the same file:
-// secret -> [u8; crate::SECRET_KEY_LENGTH], public -> [u8; crate::PUBLIC_KEY_LENGTH]
-#[cfg(feature = "crypto")]
-const KEYPAIR: &[u8; crate::SECRET_KEY_LENGTH + crate::PUBLIC_KEY_LENGTH] = include_bytes!("../test_data/pair.pub");
-
-// The paths to the Archives, to be written|loaded
-const SIGNED_TARGET: &str = "test_data/signed.vach";
-const SIMPLE_TARGET: &str = "test_data/simple.vach";
-const ENCRYPTED_TARGET: &str = "test_data/encrypted.vach";
-
-// Custom bitflag tests
-const CUSTOM_FLAG_1: u32 = 0b0000_0000_0000_0000_0000_1000_0000_0000;
-const CUSTOM_FLAG_2: u32 = 0b0000_0000_0000_0000_0000_0100_0000_0000;
-const CUSTOM_FLAG_3: u32 = 0b0000_0000_0000_0000_0000_0000_1000_0000;
-const CUSTOM_FLAG_4: u32 = 0b0000_0000_0000_0000_0000_0000_0001_0000;
-
-fn leaves_from_dir<'a>(
-	path: impl AsRef<std::path::Path>,
-	template: Option<&Leaf<&'static [u8]>>,
-) -> InternalResult<Vec<Leaf<File>>> {
-	use std::fs;
-
-	let mut leaves = vec![];
-	let directory = fs::read_dir(path)?;
-
-	for file in directory {
-		let path = file?.path();
-
-		let v = path.iter().map(|u| u.to_string_lossy()).collect::<Vec<_>>();
-
-		if path.is_file() && path.extension().map(|s| s.to_str().unwrap()) != Some("vach") {
-			let file = fs::File::open(&path)?;
-			let id = v.last().unwrap();
-
-			let leaf = match template {
-				Some(t) => Leaf::new(file, id).template(t),
-				None => Leaf::new(file, id),
-			};
-
-			leaves.push(leaf);
-		}
-	}
-
-	Ok(leaves)
-}
-
-#[test]
-#[cfg(feature = "archive")]
-fn custom_bitflags() -> InternalResult {
-	let target = File::open(SIMPLE_TARGET)?;
-	let archive = Archive::new(target)?;
-
-	let entry = archive.fetch_entry("poem").unwrap();
-	let flags = entry.flags;
-
-	assert_eq!(flags.bits(), entry.flags.bits());
-	assert!(flags.contains(CUSTOM_FLAG_1 | CUSTOM_FLAG_2 | CUSTOM_FLAG_3 | CUSTOM_FLAG_4));
-
-	Ok(())
-}
-
-#[test]
-fn flag_restricted_access() {
-	let mut flag = Flags::from_bits(0b1111_1000_0000_0000);
-
-	// This should return an error
-	if let Err(error) = flag.set(Flags::COMPRESSED_FLAG, true) {
-		assert!(matches!(error, InternalError::RestrictedFlagAccessError));
-	} else {
-		panic!("Access to restricted flags has been allowed, this should not be feasible")
-	};
-}
-
-#[test]
-fn flags_set_intersects() {
-	let mut flag = Flags::new();
-
-	flag.force_set(Flags::COMPRESSED_FLAG, true);
-	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG);
-
-	flag.force_set(Flags::COMPRESSED_FLAG, true);
-	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG);
-
-	flag.force_set(Flags::SIGNED_FLAG, true);
-	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG);
-
-	flag.force_set(Flags::COMPRESSED_FLAG, false);
-	assert_eq!(flag.bits(), Flags::SIGNED_FLAG);
-
-	flag.force_set(Flags::COMPRESSED_FLAG, false);
-	assert_eq!(flag.bits(), Flags::SIGNED_FLAG);
-
-	flag.force_set(Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG, true);
-	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG);
-}
-
-#[test]
-#[cfg(feature = "crypto")]
-fn registry_entry_roundtrip() {
-	let entries = [
-		RegistryEntry {
-			id: "brotato".into(),
-			flags: Flags { bits: Flags::COMPRESSED_FLAG },
-			location: 12,
-			offset: 360,
-			signature: None,
-			nonce: None,
-		},
-		RegistryEntry {
-			id: "potato".into(),
-			flags: Flags {
-				bits: Flags::ENCRYPTED_FLAG | Flags::SIGNED_FLAG,
-			},
-			location: 120,
-			offset: 3600,
-			signature: Some([12u8; crate::SIGNATURE_LENGTH].into()),
-			nonce: Some([34u8; crate::NONCE_LENGTH]),
-		},
-		RegistryEntry {
-			id: "tomato".into(),
-			flags: Flags { bits: Flags::SIGNED_FLAG },
-			location: 1200,
-			offset: 36000,
-			signature: Some([12u8; crate::SIGNATURE_LENGTH].into()),
-			nonce: None,
-		},
-	];
-
-	for entry in entries {
-		let data = entry.to_bytes().unwrap();
-		let mut cursor = std::io::Cursor::new(data);
-
-		let parsed = RegistryEntry::from_handle(&mut cursor).unwrap();
-		assert_eq!(entry, parsed);
-	}
-}
-
-#[test]
-#[cfg(all(feature = "compression", feature = "builder"))]
-fn builder_no_signature() {
-	let mut poem_flags = Flags::default();
-	poem_flags.set(CUSTOM_FLAG_1 | CUSTOM_FLAG_2 | CUSTOM_FLAG_3 | CUSTOM_FLAG_4, true).unwrap();
-
-	let mut leaves = [
-		Leaf::new(File::open("test_data/song.txt").unwrap(), "song").compress(CompressMode::Never),
-		Leaf::new(File::open("test_data/lorem.txt").unwrap(), "lorem"),
-		Leaf::new(File::open("test_data/bee.script").unwrap(), "script"),
-		Leaf::new(File::open("test_data/quicksort.wasm").unwrap(), "wasm"),
-		Leaf::new(File::open("test_data/poem.txt").unwrap(), "poem").compress(CompressMode::Always).flags(poem_flags),
-	];
-
-	let mut target = File::create(SIMPLE_TARGET).unwrap();
-	let mut count = 0usize;
-	let written = dump(&mut target, &mut leaves, None, Some(&mut |_, _| count += 1)).unwrap();
-
-	assert_eq!(count, leaves.len(),);
-	assert_eq!(target.metadata().unwrap().len(), written);
-}
-
-#[test]
-#[cfg(all(feature = "compression", feature = "archive"))]
-fn fetch_no_signature() -> InternalResult {
-	use std::fs;
-
-	let target = File::open(SIMPLE_TARGET)?;
-
-	let mut archive = Archive::new(target)?;
-	let resource = archive.fetch_mut("wasm")?;
-
-	assert_eq!(resource.data.len(), 106537);
-	assert!(!resource.verified);
-	assert!(!resource.flags.contains(Flags::COMPRESSED_FLAG));
-
-	let resource = archive.fetch_mut("song")?;
-	let expected = fs::read("test_data/song.txt").unwrap();
-
-	assert_eq!(expected.as_slice(), resource.data.as_ref());
-	assert!(!resource.flags.contains(Flags::COMPRESSED_FLAG));
-
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "builder", feature = "crypto"))]
-fn builder_with_signature() -> InternalResult {
-	let mut build_config = BuilderConfig::default().flags(Flags::from_bits(CUSTOM_FLAG_4));
-	build_config.load_keypair(KEYPAIR.as_slice())?;
-
-	let mut leaves = [
-		Leaf::new(b"Hello, Cassandra!".as_slice(), "not_signed"),
-		Leaf::new(b"Don't forget to recite your beatitudes!".as_slice(), "signed").sign(true),
-		Leaf::new([123u8, 234, 156, 56, 67, 5, 76, 51, 67, 2, 4, 24].as_slice(), "bytez").sign(true),
-	];
-
-	let mut target = File::create(SIGNED_TARGET)?;
-	let written = dump(&mut target, leaves.as_mut_slice(), Some(build_config), None)?;
-
-	assert_eq!(target.metadata().unwrap().len(), written);
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "archive", feature = "crypto", feature = "compression"))]
-fn fetch_with_signature() -> InternalResult {
-	use crate::crypto_utils::read_verifying_key;
-
-	// Load keypair
-	let keypair = &KEYPAIR[crate::SECRET_KEY_LENGTH..];
-	let vk = read_verifying_key(keypair)?;
-
-	// open archive
-	let target = File::open(SIGNED_TARGET)?;
-	let mut archive = Archive::with_key(target, &vk)?;
-
-	assert!(archive.flags().contains(CUSTOM_FLAG_4), "Flags did not complete roundtrip");
-
-	let resource = archive.fetch_mut("bytez")?;
-	assert_eq!(resource.data.len(), 12);
-
-	// The adjacent resource was flagged to not be signed
-	let not_signed_resource = archive.fetch_mut("not_signed")?;
-	assert!(!not_signed_resource.flags.contains(Flags::SIGNED_FLAG));
-	assert!(!not_signed_resource.verified);
-
-	let resource = archive.fetch_mut("signed")?;
-	assert!(resource.verified);
-	assert!(resource.flags.contains(Flags::SIGNED_FLAG));
-
-	Ok(())
-}
-
-#[test]
-#[cfg(feature = "crypto")]
-fn decryptor_test() -> InternalResult {
-	use crate::crypto_utils::gen_keypair;
-
-	let vk = gen_keypair().verifying_key();
-
-	let crypt = Encryptor::new(&vk);
-	let data = vec![12, 12, 12, 12];
-
-	let (ciphertext, nonce) = crypt.encrypt(&data)?;
-	let plaintext = crypt.decrypt(&ciphertext, &nonce)?;
-
-	assert_ne!(&plaintext, &ciphertext);
-	assert_eq!(&plaintext, &data);
-
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "compression", feature = "builder", feature = "crypto"))]
-fn builder_with_encryption() -> InternalResult {
-	use crate::crypto_utils::read_keypair;
-
-	let build_config = BuilderConfig::default().keypair(read_keypair(KEYPAIR.as_slice())?);
-
-	let template = Leaf::default().encrypt(true).compress(CompressMode::Never).sign(true);
-	let mut leaves = leaves_from_dir("test_data", Some(&template))?;
-
-	leaves.push(
-		Leaf::new(File::open("test_data/poem.txt").unwrap(), "stitches.snitches")
-			.sign(false)
-			.encrypt(true)
-			.compression_algo(CompressionAlgorithm::Brotli(11))
-			.compress(CompressMode::Always),
-	);
-
-	let mut target = File::create(ENCRYPTED_TARGET)?;
-	let written = dump(&mut target, leaves.as_mut_slice(), Some(build_config), None)?;
-
-	assert_eq!(target.metadata().unwrap().len(), written);
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "archive", feature = "crypto", feature = "compression"))]
-fn fetch_from_encrypted() -> InternalResult {
-	use crate::crypto_utils::read_verifying_key;
-
-	let target = File::open(ENCRYPTED_TARGET)?;
-
-	// Load keypair
-	let public_key = &KEYPAIR[crate::SECRET_KEY_LENGTH..];
-	let vk = read_verifying_key(public_key)?;
-
-	let mut archive = Archive::with_key(target, &vk)?;
-
-	// read data
-	let not_signed = archive.fetch_mut("stitches.snitches")?;
-	println!("Verifying Resource: {}", not_signed);
-
-	assert!(!not_signed.verified);
-	assert!(not_signed.flags.contains(Flags::COMPRESSED_FLAG));
-	assert!(not_signed.flags.contains(Flags::ENCRYPTED_FLAG));
-
-	let data = std::fs::read("test_data/poem.txt").unwrap();
-	assert_eq!(data.as_slice(), not_signed.data.as_ref());
-
-	let signed = archive.fetch_mut("quicksort.wasm")?;
-	println!("Verifying Resource: {}", signed);
-
-	assert_eq!(signed.data.len(), 106537);
-	assert!(signed.verified);
-	assert!(!signed.flags.contains(Flags::COMPRESSED_FLAG));
-	assert!(signed.flags.contains(Flags::ENCRYPTED_FLAG));
-
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
-fn consolidated_test() -> InternalResult {
-	use crate::crypto_utils::{gen_keypair, read_keypair, read_secret_key};
-	use std::{io::Cursor, time::Instant};
-
-	let mut target = Cursor::new(vec![]);
-
-	// Data to be written
-	let data_1 = b"Around The World, Fatter wetter stronker" as &[u8];
-	let data_2 = b"Imago" as &[u8];
-	let data_3 = b"Fast-Acting Long-Lasting, *Bathroom Reader*" as &[u8];
-
-	// Builder definition
-	let keypair = gen_keypair();
-	let keypair_bytes = keypair.to_keypair_bytes();
-
-	let mut config = BuilderConfig::default();
-	config.signing_key = read_secret_key(&keypair_bytes[..ed25519_dalek::SECRET_KEY_LENGTH]).ok();
-
-	// Add data
-	let template = Leaf::<&'static [u8]>::default().encrypt(true).sign(true);
-	let mut leaves = [
-		Leaf::new(data_1, "d1").template(&template),
-		Leaf::new(data_2, "d2").template(&template),
-		Leaf::new(data_3, "d3").template(&template),
-	];
-
-	// Dump data
-	let then = Instant::now();
-	let written = dump(&mut target, &mut leaves, Some(config), None)?;
-
-	// Just because
-	println!("Building took: {:?}", then.elapsed());
-
-	// parse verifying key
-	let sk = read_keypair(keypair_bytes.as_slice())?;
-	let vk = sk.verifying_key();
-
-	// open archive
-	let then = Instant::now();
-	let mut archive = Archive::with_key(target, &vk)?;
-
-	println!("Archive initialization took: {:?}", then.elapsed());
-
-	// Quick assertions
-	let then = Instant::now();
-
-	assert_eq!(archive.fetch_mut("d1").unwrap().data.as_ref(), data_1);
-	assert_eq!(archive.fetch_mut("d2").unwrap().data.as_ref(), data_2);
-	assert_eq!(archive.fetch_mut("d3").unwrap().data.as_ref(), data_3);
-
-	println!("Fetching took: {:?} on average on {}", then.elapsed() / 3, archive);
-
-	// extract inner from archive
-	let inner = archive.into_inner().unwrap();
-	assert_eq!(inner.get_ref().len() as u64, written);
-
-	// All seems ok
-	Ok(())
-}
-
-#[test]
-fn null_roundtrip() -> InternalResult {
-	let mut target = std::io::Cursor::new(vec![]);
-	dump::<_, &[u8]>(&mut target, &mut [], None, None)?;
-
-	let archive = Archive::new(target)?;
-	assert_eq!(archive.entries().len(), 0, "Archive should have zero entries");
-
-	Ok(())
-}
-
-#[test]
-#[cfg(all(feature = "compression", feature = "builder"))]
-fn test_compressors() -> InternalResult {
-	use std::io::Cursor;
-	const INPUT_LEN: usize = 4096;
-
-	let input = [12u8; INPUT_LEN];
-	let mut target = Cursor::new(vec![]);
-
-	dump(
-		&mut target,
-		&mut [
-			Leaf::new(input.as_slice(), "LZ4").compression_algo(CompressionAlgorithm::LZ4).compress(CompressMode::Always),
-			Leaf::new(input.as_slice(), "BROTLI").compression_algo(CompressionAlgorithm::Brotli(9)).compress(CompressMode::Always),
-			Leaf::new(input.as_slice(), "SNAPPY").compression_algo(CompressionAlgorithm::Snappy).compress(CompressMode::Always),
-		],
-		None,
-		None,
-	)?;
-
-	// parse archive
-	let mut archive = Archive::new(&mut target)?;
-
-	let d1 = archive.fetch_mut("LZ4")?;
-	let d2 = archive.fetch_mut("BROTLI")?;
-	let d3 = archive.fetch_mut("SNAPPY")?;
-
-	// Identity tests
-	assert_eq!(d1.data.len(), INPUT_LEN);
-	assert_eq!(d2.data.len(), INPUT_LEN);
-	assert_eq!(d3.data.len(), INPUT_LEN);
-
-	assert!(&d1.data[..] == &input);
-	assert!(&d2.data[..] == &input);
-	assert!(&d3.data[..] == &input);
-
-	// Compression tests
-	assert!(archive.fetch_entry("LZ4").unwrap().offset < INPUT_LEN as u64);
-	assert!(archive.fetch_entry("BROTLI").unwrap().offset < INPUT_LEN as u64);
-	assert!(archive.fetch_entry("SNAPPY").unwrap().offset < INPUT_LEN as u64);
-
-	// A simple test to show that these are somehow not the same data
-	assert!(archive.fetch_entry("SNAPPY").unwrap().offset != archive.fetch_entry("LZ4").unwrap().offset);
-	assert!(archive.fetch_entry("BROTLI").unwrap().offset != archive.fetch_entry("LZ4").unwrap().offset);
-	assert!(archive.fetch_entry("SNAPPY").unwrap().offset != archive.fetch_entry("BROTLI").unwrap().offset);
-
-	Ok(())
-}
-
-#[test]
-#[cfg(feature = "archive")]
-fn test_batch_fetching() -> InternalResult {
-	use rayon::prelude::*;
-	use std::{collections::HashMap, io::Cursor};
-
-	// Define input constants
-	const INPUT_LEN: usize = 8;
-	const INPUT: [u8; INPUT_LEN] = [69u8; INPUT_LEN];
-
-	let mut target = Cursor::new(vec![]);
-
-	// Define and queue data
-	let mut ids = (0..120).map(|i| format!("ID {}", i)).collect::<Vec<_>>();
-	let mut leaves = ids.iter().map(|i| Leaf::new(&INPUT[..], i)).collect::<Vec<_>>();
-
-	ids.push("ERRORS".to_string());
-
-	// Process data
-	let config = BuilderConfig::default().threads(4);
-	dump(&mut target, leaves.as_mut_slice(), Some(config), None)?;
-
-	let archive = Archive::new(target)?;
-	let mut resources = ids.as_slice().par_iter().map(|id| (id.as_str(), archive.fetch(&id))).collect::<HashMap<_, _>>();
-
-	// Tests and checks
-	assert!(resources.get("NON_EXISTENT").is_none());
-	assert!(resources.get("ERRORS").is_some());
-
-	match resources.remove("ERRORS").unwrap() {
-		Ok(_) => {
-			return Err(InternalError::OtherError("This should be an error".into()));
-		},
-		Err(err) => match err {
-			InternalError::MissingResourceError(_) => {
-				resources.remove("ERRORS");
-			},
-
-			specific => return Err(specific),
-		},
-	};
-
-	for (_, res) in resources {
-		assert_eq!(res?.data.as_ref(), &INPUT[..]);
-	}
-
-	Ok(())
-}
+// This is meant to mirror as closely as possible, how users should use the crate
+#![cfg(test)]
+
+use crate::prelude::*;
+use std::{fs::File, str};
+
+// Contains both the public key and secret key in the same file:
+// secret -> [u8; crate::SECRET_KEY_LENGTH], public -> [u8; crate::PUBLIC_KEY_LENGTH]
+#[cfg(feature = "crypto")]
+const KEYPAIR: &[u8; crate::SECRET_KEY_LENGTH + crate::PUBLIC_KEY_LENGTH] = include_bytes!("../test_data/pair.pub");
+#[cfg(feature = "crypto")]
+const ENCRYPTION_KEY: crate::crypto::EncryptionKey = *b"vach-test-suite-encryption-key!!";
+
+// The paths to the Archives, to be written|loaded
+const SIGNED_TARGET: &str = "test_data/signed.vach";
+const SIMPLE_TARGET: &str = "test_data/simple.vach";
+const ENCRYPTED_TARGET: &str = "test_data/encrypted.vach";
+const POSITIONAL_TARGET: &str = "test_data/positional.vach";
+
+// Custom bitflag tests
+const CUSTOM_FLAG_1: u32 = 0b0000_0000_0000_0000_0000_1000_0000_0000;
+const CUSTOM_FLAG_2: u32 = 0b0000_0000_0000_0000_0000_0100_0000_0000;
+const CUSTOM_FLAG_3: u32 = 0b0000_0000_0000_0000_0000_0000_1000_0000;
+const CUSTOM_FLAG_4: u32 = 0b0000_0000_0000_0000_0000_0000_0001_0000;
+
+fn leaves_from_dir(
+	path: impl AsRef<std::path::Path>,
+	template: Option<&Leaf<&'static [u8]>>,
+) -> InternalResult<Vec<Leaf<File>>> {
+	use std::fs;
+
+	let mut leaves = vec![];
+	let directory = fs::read_dir(path)?;
+
+	for file in directory {
+		let path = file?.path();
+
+		let v = path.iter().map(|u| u.to_string_lossy()).collect::<Vec<_>>();
+
+		if path.is_file() && path.extension().map(|s| s.to_str().unwrap()) != Some("vach") {
+			let file = fs::File::open(&path)?;
+			let id = v.last().unwrap();
+
+			let leaf = match template {
+				Some(t) => Leaf::new(file, id).template(t),
+				None => Leaf::new(file, id),
+			};
+
+			leaves.push(leaf);
+		}
+	}
+
+	Ok(leaves)
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn custom_bitflags() -> InternalResult {
+	let target = File::open(SIMPLE_TARGET)?;
+	let archive = Archive::new(target)?;
+
+	let entry = archive.fetch_entry("poem").unwrap();
+	let flags = entry.flags;
+
+	assert_eq!(flags.bits(), entry.flags.bits());
+	assert!(flags.contains(CUSTOM_FLAG_1 | CUSTOM_FLAG_2 | CUSTOM_FLAG_3 | CUSTOM_FLAG_4));
+
+	Ok(())
+}
+
+#[test]
+fn flag_restricted_access() {
+	let mut flag = Flags::from_bits(0b1111_1000_0000_0000);
+
+	// This should return an error
+	if let Err(error) = flag.set(Flags::COMPRESSED_FLAG, true) {
+		assert!(matches!(error, InternalError::RestrictedFlagAccessError));
+	} else {
+		panic!("Access to restricted flags has been allowed, this should not be feasible")
+	};
+}
+
+#[test]
+fn flags_set_intersects() {
+	let mut flag = Flags::new();
+
+	flag.force_set(Flags::COMPRESSED_FLAG, true);
+	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG);
+
+	flag.force_set(Flags::COMPRESSED_FLAG, true);
+	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG);
+
+	flag.force_set(Flags::SIGNED_FLAG, true);
+	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG);
+
+	flag.force_set(Flags::COMPRESSED_FLAG, false);
+	assert_eq!(flag.bits(), Flags::SIGNED_FLAG);
+
+	flag.force_set(Flags::COMPRESSED_FLAG, false);
+	assert_eq!(flag.bits(), Flags::SIGNED_FLAG);
+
+	flag.force_set(Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG, true);
+	assert_eq!(flag.bits(), Flags::COMPRESSED_FLAG | Flags::SIGNED_FLAG);
+}
+
+#[test]
+#[cfg(feature = "crypto")]
+fn registry_entry_roundtrip() {
+	let entries = [
+		RegistryEntry {
+			id: "brotato".into(),
+			flags: Flags { bits: Flags::COMPRESSED_FLAG },
+			location: 12,
+			offset: 360,
+			signature: None,
+			nonce: None,
+			codec: None,
+			block: None,
+			checksum: None,
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		},
+		RegistryEntry {
+			id: "potato".into(),
+			flags: Flags {
+				bits: Flags::ENCRYPTED_FLAG | Flags::SIGNED_FLAG,
+			},
+			location: 120,
+			offset: 3600,
+			signature: Some([12u8; crate::SIGNATURE_LENGTH].into()),
+			nonce: Some([34u8; crate::NONCE_LENGTH]),
+			codec: None,
+			block: None,
+			checksum: None,
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		},
+		RegistryEntry {
+			id: "tomato".into(),
+			flags: Flags { bits: Flags::SIGNED_FLAG },
+			location: 1200,
+			offset: 36000,
+			signature: Some([12u8; crate::SIGNATURE_LENGTH].into()),
+			nonce: None,
+			codec: None,
+			block: None,
+			checksum: None,
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		},
+		RegistryEntry {
+			id: "potahto".into(),
+			flags: Flags {
+				bits: Flags::COMPRESSED_FLAG | Flags::CODEC_FLAG | Flags::ENCRYPTED_FLAG,
+			},
+			location: 12000,
+			offset: 360000,
+			signature: None,
+			nonce: Some([56u8; crate::NONCE_LENGTH]),
+			codec: Some(0x0201),
+			block: None,
+			checksum: None,
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		},
+		RegistryEntry {
+			id: "tomahto".into(),
+			flags: Flags {
+				bits: Flags::COMPRESSED_FLAG | Flags::LZ4_COMPRESSED | Flags::SOLID_FLAG | Flags::CHECKSUM_FLAG,
+			},
+			location: 120000,
+			offset: 4096,
+			signature: None,
+			nonce: None,
+			codec: None,
+			block: Some(BlockRange { start: 1024, length: 96 }),
+			checksum: Some(0xDEAD_BEEF_CAFE_F00D),
+			alignment: None,
+			metadata: Metadata::new(),
+			content_version: None,
+		},
+		RegistryEntry {
+			id: "tamato".into(),
+			flags: Flags {
+				bits: Flags::CHECKSUM_FLAG | Flags::ALIGNED_FLAG | Flags::METADATA_FLAG,
+			},
+			location: 1200000,
+			offset: 40960,
+			signature: None,
+			nonce: None,
+			codec: None,
+			block: None,
+			checksum: Some(0xF00D),
+			alignment: Some(4096),
+			metadata: Metadata::new()
+				.with("mime", "text/plain")
+				.with("mtime", -1_700_000_000i64)
+				.with("size", 40960u64)
+				.with("scale", 0.5)
+				.with("hidden", false)
+				.with("hash", [7u8; 32].as_slice()),
+			content_version: None,
+		},
+	];
+
+	for entry in entries {
+		let data = entry.to_bytes().unwrap();
+		let mut cursor = std::io::Cursor::new(data);
+
+		let parsed = RegistryEntry::from_handle(&mut cursor).unwrap();
+		assert_eq!(entry, parsed);
+	}
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder"))]
+fn builder_no_signature() {
+	let mut poem_flags = Flags::default();
+	poem_flags.set(CUSTOM_FLAG_1 | CUSTOM_FLAG_2 | CUSTOM_FLAG_3 | CUSTOM_FLAG_4, true).unwrap();
+
+	let mut leaves = [
+		Leaf::new(File::open("test_data/song.txt").unwrap(), "song").compress(CompressMode::Never),
+		Leaf::new(File::open("test_data/lorem.txt").unwrap(), "lorem"),
+		Leaf::new(File::open("test_data/bee.script").unwrap(), "script"),
+		Leaf::new(File::open("test_data/quicksort.wasm").unwrap(), "wasm"),
+		Leaf::new(File::open("test_data/poem.txt").unwrap(), "poem").compress(CompressMode::Always).flags(poem_flags),
+	];
+
+	let mut target = File::create(SIMPLE_TARGET).unwrap();
+	let mut count = 0usize;
+	let written = dump(&mut target, &mut leaves, None, Some(&mut |_| count += 1)).unwrap().bytes_written;
+
+	assert_eq!(count, leaves.len(),);
+	assert_eq!(target.metadata().unwrap().len(), written);
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "archive"))]
+fn fetch_no_signature() -> InternalResult {
+	use std::fs;
+
+	let target = File::open(SIMPLE_TARGET)?;
+
+	let mut archive = Archive::new(target)?;
+	let resource = archive.fetch_mut("wasm")?;
+
+	assert_eq!(resource.data.len(), 106537);
+	assert!(!resource.verified);
+	assert!(!resource.flags.contains(Flags::COMPRESSED_FLAG));
+
+	let resource = archive.fetch_mut("song")?;
+	let expected = fs::read("test_data/song.txt").unwrap();
+
+	assert_eq!(expected.as_slice(), resource.data.as_ref());
+	assert!(!resource.flags.contains(Flags::COMPRESSED_FLAG));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "crypto"))]
+fn builder_with_signature() -> InternalResult {
+	let mut build_config = BuilderConfig::default().flags(Flags::from_bits(CUSTOM_FLAG_4));
+	build_config.load_keypair(KEYPAIR.as_slice())?;
+
+	let mut leaves = [
+		Leaf::new(b"Hello, Cassandra!".as_slice(), "not_signed"),
+		Leaf::new(b"Don't forget to recite your beatitudes!".as_slice(), "signed").sign(true),
+		Leaf::new([123u8, 234, 156, 56, 67, 5, 76, 51, 67, 2, 4, 24].as_slice(), "bytez").sign(true),
+	];
+
+	let mut target = File::create(SIGNED_TARGET)?;
+	let written = dump(&mut target, leaves.as_mut_slice(), Some(build_config), None)?.bytes_written;
+
+	assert_eq!(target.metadata().unwrap().len(), written);
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "archive", feature = "crypto", feature = "compression"))]
+fn fetch_with_signature() -> InternalResult {
+	use crate::crypto_utils::read_verifying_key;
+
+	// Load keypair
+	let keypair = &KEYPAIR[crate::SECRET_KEY_LENGTH..];
+	let vk = read_verifying_key(keypair)?;
+
+	// open archive
+	let target = File::open(SIGNED_TARGET)?;
+	let mut archive = Archive::with_key(target, &vk)?;
+
+	assert!(archive.flags().contains(CUSTOM_FLAG_4), "Flags did not complete roundtrip");
+
+	let resource = archive.fetch_mut("bytez")?;
+	assert_eq!(resource.data.len(), 12);
+
+	// The adjacent resource was flagged to not be signed
+	let not_signed_resource = archive.fetch_mut("not_signed")?;
+	assert!(!not_signed_resource.flags.contains(Flags::SIGNED_FLAG));
+	assert!(!not_signed_resource.verified);
+
+	let resource = archive.fetch_mut("signed")?;
+	assert!(resource.verified);
+	assert!(resource.flags.contains(Flags::SIGNED_FLAG));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn manifest_tampering() -> InternalResult {
+	use crate::{crypto_utils::read_keypair, global::header::Header};
+	use std::io::Cursor;
+
+	let keypair = read_keypair(KEYPAIR.as_slice())?;
+	let vk = keypair.verifying_key();
+
+	let mut leaves = [Leaf::new(b"First".as_slice(), "first"), Leaf::new(b"Second".as_slice(), "second").sign(false)];
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(BuilderConfig::default().keypair(keypair)), None)?;
+
+	let source = target.into_inner();
+	assert!(Archive::with_key(Cursor::new(source.as_slice()), &vk).is_ok());
+
+	// flip a bit in the header flags, then in the first registry entry's flags
+	let registry_offset = Header::BASE_SIZE + Header::EXTENSION_COUNT_SIZE + crate::SIGNATURE_LENGTH;
+	for position in [crate::MAGIC_LENGTH, registry_offset] {
+		let mut tampered = source.clone();
+		tampered[position] ^= 0b0100;
+
+		match Archive::with_key(Cursor::new(tampered.as_slice()), &vk) {
+			Err(InternalError::ManifestVerificationError) => (),
+			other => panic!("Expected a ManifestVerificationError, got {:?}", other.map(|_| ())),
+		}
+
+		// no key, no manifest check
+		assert!(Archive::new(Cursor::new(tampered.as_slice())).is_ok());
+	}
+
+	// archives without a manifest are rejected when a key is expected
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Unsigned".as_slice(), "unsigned")], None, None)?;
+
+	match Archive::with_key(target, &vk) {
+		Err(InternalError::ManifestVerificationError) => Ok(()),
+		other => panic!("Expected a ManifestVerificationError, got {:?}", other.map(|_| ())),
+	}
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn relocated_ciphertext() -> InternalResult {
+	use crate::global::header::Header;
+	use std::io::Cursor;
+
+	let config = BuilderConfig::default().encryption_key(ENCRYPTION_KEY);
+	let mut leaves = [Leaf::new(b"Launch codes".as_slice(), "alpha").encrypt(true)];
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(config), None)?;
+
+	let source = target.into_inner();
+	let config = ArchiveConfig::default().decryption_key(ENCRYPTION_KEY);
+	assert_eq!(Archive::from_source_with_config(source.as_slice(), config.clone())?.fetch("alpha")?.data.as_ref(), b"Launch codes");
+
+	// move the ciphertext under another ID
+	let mut renamed = source.clone();
+	let position = renamed.windows(5).position(|w| w == b"alpha").unwrap();
+	renamed[position..position + 5].copy_from_slice(b"gamma");
+
+	let archive = Archive::from_source_with_config(renamed.as_slice(), config.clone())?;
+	assert!(matches!(archive.fetch("gamma"), Err(InternalError::EntryAuthenticationError(_))));
+
+	// mark the entry as compressed
+	let mut reflagged = source;
+	reflagged[Header::BASE_SIZE + Header::EXTENSION_COUNT_SIZE + 3] |= 0b1100_0000;
+
+	let archive = Archive::from_source_with_config(reflagged.as_slice(), config)?;
+	assert!(matches!(archive.fetch("alpha"), Err(InternalError::EntryAuthenticationError(_))));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn passphrase_encryption() -> InternalResult {
+	use std::io::Cursor;
+
+	const DATA: &[u8] = b"Keyfiles are for people with filing cabinets";
+
+	// cheap parameters, to keep the test quick
+	let params = KdfParams {
+		memory: 64,
+		iterations: 1,
+		parallelism: 1,
+	};
+
+	let config = BuilderConfig::default().passphrase("correct horse battery staple").kdf_params(params);
+	let mut leaves = [Leaf::new(DATA, "secret").encrypt(true), Leaf::new(DATA, "public")];
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(config), None)?;
+
+	let source = target.into_inner();
+	let archive = Archive::with_passphrase(Cursor::new(source.as_slice()), "correct horse battery staple")?;
+	assert_eq!(archive.fetch("secret")?.data.as_ref(), DATA);
+
+	// the wrong passphrase derives the wrong key
+	let archive = Archive::from_source_with_passphrase(source.as_slice(), "incorrect horse")?;
+	assert!(matches!(archive.fetch("secret"), Err(InternalError::EntryAuthenticationError(_))));
+	assert_eq!(archive.fetch("public")?.data.as_ref(), DATA);
+
+	// invalid parameters are caught while building
+	let config = BuilderConfig::default().passphrase("hunter2").kdf_params(KdfParams { memory: 0, ..params });
+	let result = dump(Cursor::new(vec![]), &mut [Leaf::new(DATA, "secret").encrypt(true)], Some(config), None);
+	assert!(matches!(result, Err(InternalError::KeyDerivationError(_))));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn multiple_recipients() -> InternalResult {
+	use crate::crypto_utils::{gen_keypair, gen_recipient_keypair};
+	use std::io::Cursor;
+
+	const DATA: &[u8] = b"For your eyes, and a few others', only";
+
+	let (alice, alice_pk) = gen_recipient_keypair();
+	let (bob, bob_pk) = gen_recipient_keypair();
+	let (carol, carol_pk) = gen_recipient_keypair();
+
+	let keypair = gen_keypair();
+	let config = BuilderConfig::default().keypair(keypair.clone()).recipient(alice_pk).recipient(bob_pk);
+	let mut leaves = [Leaf::new(DATA, "secret").encrypt(true).sign(true)];
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(config), None)?;
+
+	// any recipient can open the archive
+	for secret in [&alice, &bob] {
+		let config = ArchiveConfig::default().key(keypair.verifying_key()).recipient_key(secret.clone());
+		let archive = Archive::from_source_with_config(target.get_ref().as_slice(), config)?;
+		assert_eq!(archive.fetch("secret")?.data.as_ref(), DATA);
+	}
+
+	let config = ArchiveConfig::default().recipient_key(carol.clone());
+	assert!(matches!(Archive::from_source_with_config(target.get_ref().as_slice(), config), Err(InternalError::NoKeypairError)));
+
+	// swap bob for carol, without touching the leaves or the manifest
+	let leaf_data = target.get_ref()[..target.get_ref().len() - 4 - 2 * crate::crypto::WrappedKey::SIZE].to_vec();
+	rewrite_recipients(&mut target, &alice, &[alice_pk, carol_pk])?;
+	assert!(target.get_ref().starts_with(&leaf_data));
+
+	let config = ArchiveConfig::default().key(keypair.verifying_key()).recipient_key(carol);
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), config)?;
+	assert_eq!(archive.fetch("secret")?.data.as_ref(), DATA);
+
+	let config = ArchiveConfig::default().recipient_key(bob);
+	assert!(matches!(Archive::from_source_with_config(target.get_ref().as_slice(), config), Err(InternalError::NoKeypairError)));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "crypto")]
+fn decryptor_test() -> InternalResult {
+	use crate::crypto_utils::gen_encryption_key;
+
+	let crypt = Encryptor::new(&gen_encryption_key());
+	let data = vec![12, 12, 12, 12];
+
+	let (ciphertext, nonce) = crypt.encrypt(&data, b"aad")?;
+	let plaintext = crypt.decrypt(&ciphertext, &nonce, b"aad")?;
+	assert!(crypt.decrypt(&ciphertext, &nonce, b"other").is_err());
+
+	assert_ne!(&plaintext, &ciphertext);
+	assert_eq!(&plaintext, &data);
+
+	// dropping the final chunk of a chunked ciphertext is caught
+	let data = vec![12; crate::crypto::CHUNK_SIZE * 2];
+	let mut encryptor = crate::crypto::ChunkEncryptor::new(&crypt, b"aad".to_vec());
+	let mut ciphertext = Vec::new();
+	encryptor.update(&data, &mut ciphertext)?;
+	let nonce = encryptor.finish(&mut ciphertext)?;
+
+	assert_eq!(crypt.decrypt_chunks(&ciphertext, &nonce, b"aad")?, data);
+	assert!(crypt.decrypt_chunks(&ciphertext[..ciphertext.len() - crate::crypto::TAG_LENGTH], &nonce, b"aad").is_err());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "crypto"))]
+fn builder_with_encryption() -> InternalResult {
+	use crate::crypto_utils::read_keypair;
+
+	let build_config = BuilderConfig::default().keypair(read_keypair(KEYPAIR.as_slice())?).encryption_key(ENCRYPTION_KEY);
+
+	let template = Leaf::default().encrypt(true).compress(CompressMode::Never).sign(true);
+	let mut leaves = leaves_from_dir("test_data", Some(&template))?;
+
+	leaves.push(
+		Leaf::new(File::open("test_data/poem.txt").unwrap(), "stitches.snitches")
+			.sign(false)
+			.encrypt(true)
+			.compression_algo(CompressionAlgorithm::Brotli(11))
+			.compress(CompressMode::Always),
+	);
+
+	let mut target = File::create(ENCRYPTED_TARGET)?;
+	let written = dump(&mut target, leaves.as_mut_slice(), Some(build_config), None)?.bytes_written;
+
+	assert_eq!(target.metadata().unwrap().len(), written);
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "archive", feature = "crypto", feature = "compression"))]
+fn fetch_from_encrypted() -> InternalResult {
+	use crate::crypto_utils::read_verifying_key;
+
+	// Load keypair
+	let public_key = &KEYPAIR[crate::SECRET_KEY_LENGTH..];
+	let vk = read_verifying_key(public_key)?;
+
+	// the verifying key alone can't decrypt anything
+	let archive = Archive::with_key(File::open(ENCRYPTED_TARGET)?, &vk)?;
+	assert!(matches!(archive.fetch("stitches.snitches"), Err(InternalError::NoKeypairError)));
+
+	let target = File::open(ENCRYPTED_TARGET)?;
+	let config = ArchiveConfig::default().key(vk).decryption_key(ENCRYPTION_KEY);
+	let mut archive = Archive::with_config(target, config)?;
+
+	// read data
+	let not_signed = archive.fetch_mut("stitches.snitches")?;
+	println!("Verifying Resource: {}", not_signed);
+
+	assert!(!not_signed.verified);
+	assert!(not_signed.flags.contains(Flags::COMPRESSED_FLAG));
+	assert!(not_signed.flags.contains(Flags::ENCRYPTED_FLAG));
+
+	let data = std::fs::read("test_data/poem.txt").unwrap();
+	assert_eq!(data.as_slice(), not_signed.data.as_ref());
+
+	let signed = archive.fetch_mut("quicksort.wasm")?;
+	println!("Verifying Resource: {}", signed);
+
+	assert_eq!(signed.data.len(), 106537);
+	assert!(signed.verified);
+	assert!(!signed.flags.contains(Flags::COMPRESSED_FLAG));
+	assert!(signed.flags.contains(Flags::ENCRYPTED_FLAG));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn dedicated_encryption_key() -> InternalResult {
+	use crate::crypto_utils::{gen_encryption_key, read_keypair};
+	use std::io::Cursor;
+
+	let data = b"Encrypted without any keypair";
+
+	// no keypair involved, the encryption key alone decrypts the archive
+	let mut target = Cursor::new(vec![]);
+	let config = BuilderConfig::default().encryption_key(ENCRYPTION_KEY);
+	dump(&mut target, &mut [Leaf::new(data.as_slice(), "secret").encrypt(true)], Some(config), None)?;
+
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().decryption_key(ENCRYPTION_KEY))?;
+	let resource = archive.fetch("secret")?;
+	assert!(resource.flags.contains(Flags::ENCRYPTED_FLAG));
+	assert_eq!(resource.data.as_ref(), data);
+
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().decryption_key(gen_encryption_key()))?;
+	assert!(matches!(archive.fetch("secret"), Err(InternalError::EntryAuthenticationError(_))));
+
+	// signed archives are no longer decrypted using their verifying key
+	let keypair = read_keypair(KEYPAIR.as_slice())?;
+	let vk = keypair.verifying_key();
+
+	let mut target = Cursor::new(vec![]);
+	let config = BuilderConfig::default().keypair(keypair).encryption_key(ENCRYPTION_KEY);
+	dump(&mut target, &mut [Leaf::new(data.as_slice(), "secret").encrypt(true).sign(true)], Some(config), None)?;
+
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().key(vk))?;
+	assert!(matches!(archive.fetch("secret"), Err(InternalError::NoKeypairError)));
+
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().key(vk).decryption_key(ENCRYPTION_KEY))?;
+	let resource = archive.fetch("secret")?;
+	assert!(resource.verified);
+	assert_eq!(resource.data.as_ref(), data);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn consolidated_test() -> InternalResult {
+	use crate::crypto_utils::{gen_encryption_key, gen_keypair, read_keypair, read_secret_key};
+	use std::{io::Cursor, time::Instant};
+
+	let mut target = Cursor::new(vec![]);
+
+	// Data to be written
+	let data_1 = b"Around The World, Fatter wetter stronker" as &[u8];
+	let data_2 = b"Imago" as &[u8];
+	let data_3 = b"Fast-Acting Long-Lasting, *Bathroom Reader*" as &[u8];
+
+	// Builder definition
+	let keypair = gen_keypair();
+	let keypair_bytes = keypair.to_keypair_bytes();
+
+	let encryption_key = gen_encryption_key();
+	let config = BuilderConfig {
+		signing_key: read_secret_key(&keypair_bytes[..ed25519_dalek::SECRET_KEY_LENGTH]).ok(),
+		encryption_key: Some(encryption_key),
+		..Default::default()
+	};
+
+	// Add data
+	let template = Leaf::<&'static [u8]>::default().encrypt(true).sign(true);
+	let mut leaves = [
+		Leaf::new(data_1, "d1").template(&template),
+		Leaf::new(data_2, "d2").template(&template),
+		Leaf::new(data_3, "d3").template(&template),
+	];
+
+	// Dump data
+	let then = Instant::now();
+	let written = dump(&mut target, &mut leaves, Some(config), None)?.bytes_written;
+
+	// Just because
+	println!("Building took: {:?}", then.elapsed());
+
+	// parse verifying key
+	let sk = read_keypair(keypair_bytes.as_slice())?;
+	let vk = sk.verifying_key();
+
+	// open archive
+	let then = Instant::now();
+	let config = ArchiveConfig {
+		verifying_key: Some(vk),
+		decryption_key: Some(encryption_key),
+		..Default::default()
+	};
+	let mut archive = Archive::with_config(target, config)?;
+
+	println!("Archive initialization took: {:?}", then.elapsed());
+
+	// Quick assertions
+	let then = Instant::now();
+
+	assert_eq!(archive.fetch_mut("d1").unwrap().data.as_ref(), data_1);
+	assert_eq!(archive.fetch_mut("d2").unwrap().data.as_ref(), data_2);
+	assert_eq!(archive.fetch_mut("d3").unwrap().data.as_ref(), data_3);
+
+	println!("Fetching took: {:?} on average on {}", then.elapsed() / 3, archive);
+
+	// extract inner from archive
+	let inner = archive.into_inner().unwrap();
+	assert_eq!(inner.get_ref().len() as u64, written);
+
+	// All seems ok
+	Ok(())
+}
+
+#[test]
+fn null_roundtrip() -> InternalResult {
+	let mut target = std::io::Cursor::new(vec![]);
+	dump::<_, &[u8]>(&mut target, &mut [], None, None)?;
+
+	let archive = Archive::new(target)?;
+	assert_eq!(archive.entries().len(), 0, "Archive should have zero entries");
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn beyond_u16_capacity() -> InternalResult {
+	use std::io::Cursor;
+
+	// more entries than a v40 header could hold
+	let count = u16::MAX as usize + 10;
+	let ids = (0..count).map(|i| i.to_string()).collect::<Vec<_>>();
+	let mut leaves = ids.iter().map(|id| Leaf::new(id.as_bytes(), id)).collect::<Vec<_>>();
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, leaves.as_mut_slice(), None, None)?;
+
+	let archive = Archive::new(target)?;
+	assert_eq!(archive.entries().len(), count);
+
+	let last = ids.last().unwrap();
+	assert_eq!(archive.fetch(last)?.data.as_ref(), last.as_bytes());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn load_v40_archive() -> InternalResult {
+	use crate::global::{header::Header, reg_entry::RegistryEntry};
+
+	const DATA: &[u8] = b"Hello from the previous spec";
+
+	// v40 headers end with a u16 capacity
+	let mut source = vec![];
+	source.extend_from_slice(&crate::MAGIC);
+	source.extend_from_slice(&0u32.to_le_bytes());
+	source.extend_from_slice(&40u16.to_le_bytes());
+	source.extend_from_slice(&1u16.to_le_bytes());
+	assert_eq!(source.len(), Header::BASE_SIZE - 2);
+
+	let mut entry = RegistryEntry::empty();
+	entry.id = "legacy".into();
+	entry.offset = DATA.len() as u64;
+	entry.location = (source.len() + RegistryEntry::CONSTANT + entry.id.len()) as u64;
+
+	source.extend_from_slice(&entry.to_bytes()?);
+	source.extend_from_slice(DATA);
+
+	let archive = Archive::new(std::io::Cursor::new(source))?;
+	assert_eq!(archive.version(), 40);
+	assert_eq!(archive.entries().len(), 1);
+	assert_eq!(archive.fetch("legacy")?.data.as_ref(), DATA);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "archive", feature = "crypto"))]
+fn load_v30_archive() -> InternalResult {
+	use crate::{
+		crypto_utils::read_keypair,
+		global::{header::Header, reg_entry::RegistryEntry},
+	};
+	use aes_gcm::{aead::Aead, Aes256Gcm, KeyInit};
+	use ed25519_dalek::Signer;
+
+	const PLAIN: &[u8] = b"Older than the nonce";
+	const SECRET: &[u8] = b"Encrypted with the fixed v30 nonce";
+
+	let keypair = read_keypair(KEYPAIR.as_slice())?;
+	let cipher = Aes256Gcm::new_from_slice(keypair.verifying_key().as_bytes()).unwrap();
+	let encrypted = cipher.encrypt(RegistryEntry::NONCE_V30.as_slice().into(), SECRET).unwrap();
+
+	let mut plain = RegistryEntry::empty();
+	plain.id = "plain".into();
+	plain.content_version = Some(7);
+	plain.offset = PLAIN.len() as u64;
+
+	let mut secret = RegistryEntry::empty();
+	secret.id = "secret".into();
+	secret.content_version = Some(2);
+	secret.flags = Flags::from_bits(Flags::ENCRYPTED_FLAG | Flags::SIGNED_FLAG);
+	secret.offset = encrypted.len() as u64;
+
+	// v30 registry entries sit behind a 13 byte header
+	let registry_size = 2 * RegistryEntry::CONSTANT_V30 + crate::SIGNATURE_LENGTH + plain.id.len() + secret.id.len();
+	plain.location = (Header::BASE_SIZE - 2 + registry_size) as u64;
+	secret.location = plain.location + plain.offset;
+
+	let mut source = vec![];
+	source.extend_from_slice(&crate::MAGIC);
+	source.extend_from_slice(&0u32.to_le_bytes());
+	source.extend_from_slice(&30u16.to_le_bytes());
+	source.extend_from_slice(&2u16.to_le_bytes());
+	source.extend_from_slice(&plain.signed_suffix());
+
+	// v30 signatures cover the data and the serialized entry, and sit right after the fixed-size part of the entry
+	let suffix = secret.signed_suffix().into_owned();
+	let signature = keypair.sign(&[encrypted.as_slice(), &suffix].concat());
+	source.extend_from_slice(&suffix[..RegistryEntry::CONSTANT_V30]);
+	source.extend_from_slice(&signature.to_bytes());
+	source.extend_from_slice(&suffix[RegistryEntry::CONSTANT_V30..]);
+
+	source.extend_from_slice(PLAIN);
+	source.extend_from_slice(&encrypted);
+
+	let archive = Archive::with_key(std::io::Cursor::new(source), &keypair.verifying_key())?;
+	assert_eq!(archive.version(), 30);
+	assert_eq!(archive.entries().len(), 2);
+
+	assert_eq!(archive.fetch("plain")?.data.as_ref(), PLAIN);
+
+	let resource = archive.fetch("secret")?;
+	assert_eq!(resource.data.as_ref(), SECRET);
+	assert!(resource.verified);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn unsupported_versions() {
+	for version in [crate::MIN_VERSION - 10, crate::VERSION + 10, crate::VERSION - 5] {
+		let mut source = vec![];
+		source.extend_from_slice(&crate::MAGIC);
+		source.extend_from_slice(&0u32.to_le_bytes());
+		source.extend_from_slice(&version.to_le_bytes());
+		source.extend_from_slice(&0u32.to_le_bytes());
+
+		match Archive::new(std::io::Cursor::new(source)) {
+			Err(InternalError::IncompatibleArchiveVersionError(v)) => assert_eq!(v, version),
+			other => panic!("Expected an IncompatibleArchiveVersionError, got {:?}", other.map(|_| ())),
+		}
+	}
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder"))]
+fn test_compressors() -> InternalResult {
+	use std::io::Cursor;
+	const INPUT_LEN: usize = 4096;
+
+	let input = [12u8; INPUT_LEN];
+	let mut target = Cursor::new(vec![]);
+
+	dump(
+		&mut target,
+		&mut [
+			Leaf::new(input.as_slice(), "LZ4").compression_algo(CompressionAlgorithm::LZ4).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "BROTLI").compression_algo(CompressionAlgorithm::Brotli(9)).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "SNAPPY").compression_algo(CompressionAlgorithm::Snappy).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "ZSTD").compression_algo(CompressionAlgorithm::Zstd(19)).compress(CompressMode::Always),
+		],
+		None,
+		None,
+	)?;
+
+	// parse archive
+	let mut archive = Archive::new(&mut target)?;
+
+	let d1 = archive.fetch_mut("LZ4")?;
+	let d2 = archive.fetch_mut("BROTLI")?;
+	let d3 = archive.fetch_mut("SNAPPY")?;
+	let d4 = archive.fetch_mut("ZSTD")?;
+
+	// Identity tests
+	assert_eq!(d1.data.len(), INPUT_LEN);
+	assert_eq!(d2.data.len(), INPUT_LEN);
+	assert_eq!(d3.data.len(), INPUT_LEN);
+	assert_eq!(d4.data.len(), INPUT_LEN);
+
+	assert!(d1.data[..] == input);
+	assert!(d2.data[..] == input);
+	assert!(d3.data[..] == input);
+	assert!(d4.data[..] == input);
+
+	// Compression tests
+	assert!(archive.fetch_entry("LZ4").unwrap().offset < INPUT_LEN as u64);
+	assert!(archive.fetch_entry("BROTLI").unwrap().offset < INPUT_LEN as u64);
+	assert!(archive.fetch_entry("SNAPPY").unwrap().offset < INPUT_LEN as u64);
+	assert!(archive.fetch_entry("ZSTD").unwrap().offset < INPUT_LEN as u64);
+	assert!(archive.fetch_entry("ZSTD").unwrap().flags.contains(Flags::ZSTD_COMPRESSED));
+
+	// A simple test to show that these are somehow not the same data
+	assert!(archive.fetch_entry("SNAPPY").unwrap().offset != archive.fetch_entry("LZ4").unwrap().offset);
+	assert!(archive.fetch_entry("BROTLI").unwrap().offset != archive.fetch_entry("LZ4").unwrap().offset);
+	assert!(archive.fetch_entry("SNAPPY").unwrap().offset != archive.fetch_entry("BROTLI").unwrap().offset);
+
+	// levels outside zstd's range are rejected
+	let leaf = Leaf::new(input.as_slice(), "ZSTD").compression_algo(CompressionAlgorithm::Zstd(23)).compress(CompressMode::Always);
+	assert!(dump(Cursor::new(vec![]), &mut [leaf], None, None).is_err());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn fetch_reader_streaming() -> InternalResult {
+	use std::io::{Cursor, Read};
+
+	// large enough to span several decoder and source buffers
+	let input = (0..1_048_576u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+	let mut target = Cursor::new(vec![]);
+
+	dump(
+		&mut target,
+		&mut [
+			Leaf::new(input.as_slice(), "RAW"),
+			Leaf::new(input.as_slice(), "LZ4").compression_algo(CompressionAlgorithm::LZ4).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "BROTLI").compression_algo(CompressionAlgorithm::Brotli(4)).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "SNAPPY").compression_algo(CompressionAlgorithm::Snappy).compress(CompressMode::Always),
+			Leaf::new(input.as_slice(), "ZSTD").compression_algo(CompressionAlgorithm::Zstd(3)).compress(CompressMode::Always),
+		],
+		None,
+		None,
+	)?;
+
+	let archive = Archive::new(target)?;
+
+	for id in ["RAW", "LZ4", "BROTLI", "SNAPPY", "ZSTD"] {
+		let mut reader = archive.fetch_reader(id)?;
+		assert_eq!(reader.flags, archive.fetch_entry(id).unwrap().flags);
+
+		// pull bytes in small, uneven chunks
+		let mut output = Vec::with_capacity(input.len());
+		let mut chunk = [0u8; 1021];
+
+		loop {
+			match reader.read(&mut chunk)? {
+				0 => break,
+				n => output.extend_from_slice(&chunk[..n]),
+			}
+		}
+
+		assert_eq!(output, input, "Streamed data for {} did not match the input", id);
+	}
+
+	assert!(matches!(archive.fetch_reader("NON_EXISTENT"), Err(InternalError::MissingResourceError(_))));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn trained_dictionary() -> InternalResult {
+	use std::io::{Cursor, Read};
+
+	// many tiny, similar documents that compress badly on their own
+	let documents = (0..600u32)
+		.map(|i| format!(r#"{{"id":{i},"name":"item_{}","kind":"weapon","rarity":"{}","stats":{{"damage":{},"speed":{}}}}}"#, i * 7, ["common", "rare", "epic"][i as usize % 3], i % 97, i % 13))
+		.collect::<Vec<_>>();
+	let large = "lorem ipsum dolor sit amet ".repeat(4096).into_bytes();
+
+	let build = |config: BuilderConfig| -> InternalResult<Vec<u8>> {
+		let template = Leaf::<&[u8]>::default().compression_algo(CompressionAlgorithm::Zstd(3)).compress(CompressMode::Detect);
+
+		let mut leaves = documents.iter().enumerate().map(|(i, d)| Leaf::new(d.as_bytes(), format!("doc_{i}")).template(&template)).collect::<Vec<_>>();
+		leaves.push(Leaf::new(large.as_slice(), "large").template(&template));
+		leaves.push(Leaf::new(large.as_slice(), "hinted").template(&template).size_hint(large.len() as u64));
+
+		let mut target = Cursor::new(vec![]);
+		dump(&mut target, &mut leaves, Some(config), None)?;
+		Ok(target.into_inner())
+	};
+
+	let plain = build(BuilderConfig::default())?;
+
+	for threads in [1, 4] {
+		let trained = build(BuilderConfig::default().dictionary_size(4096).threads(threads))?;
+		assert!(trained.len() < plain.len(), "{} >= {}", trained.len(), plain.len());
+
+		let archive = Archive::new(Cursor::new(trained))?;
+		for (i, document) in documents.iter().enumerate() {
+			let id = format!("doc_{i}");
+			assert!(archive.fetch_entry(&id).unwrap().flags.contains(Flags::DICTIONARY_COMPRESSED));
+			assert_eq!(archive.fetch(&id)?.data.as_ref(), document.as_bytes());
+
+			let mut streamed = Vec::new();
+			archive.fetch_reader(&id)?.read_to_end(&mut streamed)?;
+			assert_eq!(streamed, document.as_bytes());
+		}
+
+		// large leaves are compressed on their own
+		let entry = archive.fetch_entry("large").unwrap();
+		assert!(entry.flags.contains(Flags::ZSTD_COMPRESSED) && !entry.flags.contains(Flags::DICTIONARY_COMPRESSED));
+		assert_eq!(archive.fetch("large")?.data.as_ref(), large.as_slice());
+	}
+
+	// too little data to train on, no dictionary is stored
+	let mut target = Cursor::new(vec![]);
+	let mut leaves = [Leaf::new(documents[0].as_bytes(), "only").compression_algo(CompressionAlgorithm::Zstd(3)).compress(CompressMode::Always)];
+	dump(&mut target, &mut leaves, Some(BuilderConfig::default().dictionary_size(4096)), None)?;
+
+	let archive = Archive::new(target)?;
+	assert!(!archive.fetch_entry("only").unwrap().flags.contains(Flags::DICTIONARY_COMPRESSED));
+	assert_eq!(archive.fetch("only")?.data.as_ref(), documents[0].as_bytes());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn best_compression() -> InternalResult {
+	use std::io::Cursor;
+
+	let text = include_bytes!("../test_data/bee.script");
+	let candidates = [CompressionAlgorithm::LZ4, CompressionAlgorithm::Snappy, CompressionAlgorithm::Brotli(9)];
+
+	// the smallest output of every candidate, compressed on it's own
+	let mut sizes = candidates
+		.iter()
+		.map(|algo| {
+			let mut output = vec![];
+			Compressor::new(text.as_slice()).compress(*algo, &mut output).map(|_| (output.len() as u64, u32::from(*algo)))
+		})
+		.collect::<InternalResult<Vec<_>>>()?;
+	sizes.sort();
+
+	let mut target = Cursor::new(vec![]);
+	let mut leaves = [
+		Leaf::new(text.as_slice(), "text").compress(CompressMode::Best),
+		Leaf::new(text.as_slice(), "picky").compress(CompressMode::Best),
+	];
+
+	let config = BuilderConfig::default().compression_candidates(candidates.to_vec());
+	dump(&mut target, &mut leaves[..1], Some(config.clone()), None)?;
+
+	let archive = Archive::new(target)?;
+	let entry = archive.fetch_entry("text").unwrap();
+	assert!(entry.flags.contains(Flags::COMPRESSED_FLAG | sizes[0].1));
+	assert_eq!(entry.offset, sizes[0].0);
+	assert_eq!(archive.fetch("text")?.data.as_ref(), text.as_slice());
+
+	// compression that doesn't save enough isn't used
+	let savings = 1.0 - (sizes[0].0 as f32 / text.len() as f32);
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves[1..], Some(config.min_savings(savings + 0.01)), None)?;
+
+	let archive = Archive::new(target)?;
+	assert!(!archive.fetch_entry("picky").unwrap().flags.contains(Flags::COMPRESSED_FLAG));
+	assert_eq!(archive.fetch("picky")?.data.as_ref(), text.as_slice());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn custom_codecs() -> InternalResult {
+	use std::io::{self, Cursor, Read, Write};
+
+	// run-length encodes bytes as (count, byte) pairs
+	struct RunLength;
+
+	impl Codec for RunLength {
+		fn compress(
+			&self,
+			data: &mut dyn Read,
+			output: &mut dyn Write,
+		) -> io::Result<()> {
+			let mut input = Vec::new();
+			data.read_to_end(&mut input)?;
+
+			for run in input.chunk_by(|a, b| a == b) {
+				for chunk in run.chunks(u8::MAX as usize) {
+					output.write_all(&[chunk.len() as u8, chunk[0]])?;
+				}
+			}
+
+			Ok(())
+		}
+
+		fn decompress<'a>(
+			&self,
+			mut data: Box<dyn Read + 'a>,
+		) -> io::Result<Box<dyn Read + 'a>> {
+			let mut input = Vec::new();
+			data.read_to_end(&mut input)?;
+
+			let output = input.chunks(2).flat_map(|pair| std::iter::repeat_n(pair[1], pair[0] as usize)).collect::<Vec<_>>();
+			Ok(Box::new(Cursor::new(output)))
+		}
+	}
+
+	let runs = [0u8, 1, 2, 3].iter().flat_map(|b| std::iter::repeat_n(*b, 1000)).collect::<Vec<_>>();
+	let noise = (0..1000u32).map(|i| (i * 7919 % 251) as u8).collect::<Vec<_>>();
+
+	let mut target = Cursor::new(vec![]);
+	let mut leaves = [
+		Leaf::new(runs.as_slice(), "runs").codec(7).compress(CompressMode::Always),
+		Leaf::new(noise.as_slice(), "noise").codec(7).compress(CompressMode::Detect),
+		Leaf::new(runs.as_slice(), "never").codec(7),
+	];
+	dump(&mut target, &mut leaves, Some(BuilderConfig::default().codec(7, RunLength)), None)?;
+
+	let archive = Archive::with_config(Cursor::new(target.get_ref()), ArchiveConfig::default().codec(7, RunLength))?;
+
+	let entry = archive.fetch_entry("runs").unwrap();
+	assert_eq!(entry.codec, Some(7));
+	assert!(entry.flags.contains(Flags::COMPRESSED_FLAG | Flags::CODEC_FLAG) && !entry.flags.contains(Flags::LZ4_COMPRESSED));
+	assert!(entry.offset < 100);
+
+	// incompressible data is kept as is, codecs aren't used without compression
+	assert!(!archive.fetch_entry("noise").unwrap().flags.contains(Flags::COMPRESSED_FLAG));
+	assert_eq!(archive.fetch_entry("never").unwrap().codec, None);
+
+	for (id, data) in [("runs", &runs), ("noise", &noise), ("never", &runs)] {
+		assert_eq!(archive.fetch(id)?.data.as_ref(), data.as_slice());
+
+		let mut streamed = Vec::new();
+		archive.fetch_reader(id)?.read_to_end(&mut streamed)?;
+		assert_eq!(&streamed, data);
+	}
+
+	// the codec has to be registered on both ends
+	let archive = Archive::new(Cursor::new(target.get_ref()))?;
+	assert!(matches!(archive.fetch("runs"), Err(InternalError::UnknownCodecError(7))));
+
+	let mut leaves = [Leaf::new(runs.as_slice(), "runs").codec(8).compress(CompressMode::Always)];
+	let result = dump(Cursor::new(vec![]), &mut leaves, Some(BuilderConfig::default().codec(7, RunLength)), None);
+	assert!(matches!(result, Err(InternalError::UnknownCodecError(8))));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn solid_blocks() -> InternalResult {
+	use std::io::{Cursor, Read};
+
+	let documents = (0..400u32)
+		.map(|i| format!("[config_{i}]\nname = \"entry_{}\"\nenabled = {}\nweight = {}\n", i * 3, i % 2 == 0, i % 17))
+		.collect::<Vec<_>>();
+	let large = "lorem ipsum dolor sit amet ".repeat(4096).into_bytes();
+
+	let build = |config: BuilderConfig| -> InternalResult<Vec<u8>> {
+		let template = Leaf::<&[u8]>::default().compression_algo(CompressionAlgorithm::LZ4).compress(CompressMode::Always);
+
+		let mut leaves = documents.iter().enumerate().map(|(i, d)| Leaf::new(d.as_bytes(), format!("doc_{i}")).template(&template)).collect::<Vec<_>>();
+		leaves.push(Leaf::new(large.as_slice(), "large").template(&template));
+		leaves.push(Leaf::new(large.as_slice(), "hinted").template(&template).size_hint(large.len() as u64));
+
+		let mut target = Cursor::new(vec![]);
+		dump(&mut target, &mut leaves, Some(config), None)?;
+		Ok(target.into_inner())
+	};
+
+	let plain = build(BuilderConfig::default())?;
+
+	for threads in [1, 4] {
+		let solid = build(BuilderConfig::default().solid_block_size(4096).threads(threads).deduplicate(threads == 1))?;
+		assert!(solid.len() < plain.len(), "{} >= {}", solid.len(), plain.len());
+
+		let mut archive = Archive::new(Cursor::new(solid))?;
+		for (i, document) in documents.iter().enumerate() {
+			let id = format!("doc_{i}");
+
+			let entry = archive.fetch_entry(&id).unwrap();
+			assert!(entry.flags.contains(Flags::SOLID_FLAG));
+			assert_eq!(entry.block.unwrap().length, document.len() as u64);
+
+			assert_eq!(archive.fetch(&id)?.data.as_ref(), document.as_bytes());
+			assert_eq!(archive.fetch_mut(&id)?.data.as_ref(), document.as_bytes());
+
+			let mut streamed = Vec::new();
+			archive.fetch_reader(&id)?.read_to_end(&mut streamed)?;
+			assert_eq!(streamed, document.as_bytes());
+		}
+
+		// large leaves are stored on their own, whether their size was hinted or found out by reading them
+		for id in ["large", "hinted"] {
+			let entry = archive.fetch_entry(id).unwrap();
+			assert!(entry.block.is_none() && !entry.flags.contains(Flags::SOLID_FLAG));
+			assert_eq!(archive.fetch(id)?.data.as_ref(), large.as_slice());
+		}
+	}
+
+	// blocks are still readable with caching disabled
+	let archive = Archive::with_config(Cursor::new(build(BuilderConfig::default().solid_block_size(4096))?), ArchiveConfig::default().block_cache(0))?;
+	assert_eq!(archive.fetch("doc_7")?.data.as_ref(), documents[7].as_bytes());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn solid_compress_modes() -> InternalResult {
+	use std::io::Cursor;
+
+	let documents = (0..200u32).map(|i| format!("[config_{i}]\nname = \"entry_{}\"\nenabled = {}\n", i * 3, i % 2 == 0)).collect::<Vec<_>>();
+
+	let build = |template: Leaf, config: BuilderConfig| -> InternalResult<Archive<Cursor<Vec<u8>>>> {
+		let mut leaves = documents.iter().enumerate().map(|(i, d)| Leaf::new(d.as_bytes(), format!("doc_{i}")).template(&template)).collect::<Vec<_>>();
+
+		let mut target = Cursor::new(vec![]);
+		dump(&mut target, &mut leaves, Some(config), None)?;
+		Archive::new(target)
+	};
+
+	let check = |archive: &Archive<Cursor<Vec<u8>>>, condition: &dyn Fn(&RegistryEntry) -> bool| -> InternalResult {
+		for (i, document) in documents.iter().enumerate() {
+			let id = format!("doc_{i}");
+			assert!(condition(&archive.fetch_entry(&id).unwrap()), "{id}");
+			assert_eq!(archive.fetch(&id)?.data.as_ref(), document.as_bytes());
+		}
+
+		Ok(())
+	};
+
+	let solid = || BuilderConfig::default().solid_block_size(4096);
+
+	// `Best` leaves each keep their own smallest algorithm, rather than their block's
+	let best = Leaf::default().compress(CompressMode::Best);
+	let plain = build(best.clone(), BuilderConfig::default())?;
+	let archive = build(best, solid())?;
+	check(&archive, &|entry| entry.block.is_none() && entry.flags == plain.fetch_entry(&entry.id).unwrap().flags)?;
+
+	// blocks of `Detect` leaves are only compressed if they save `min_savings`
+	let detect = Leaf::default().compression_algo(CompressionAlgorithm::LZ4).compress(CompressMode::Detect);
+	let archive = build(detect.clone(), solid())?;
+	check(&archive, &|entry| entry.block.is_some() && entry.flags.contains(Flags::COMPRESSED_FLAG))?;
+
+	let archive = build(detect, solid().min_savings(0.99))?;
+	check(&archive, &|entry| entry.block.is_some() && !entry.flags.contains(Flags::COMPRESSED_FLAG))?;
+
+	// Zstd blocks are compressed against the dictionary
+	let zstd = Leaf::default().compression_algo(CompressionAlgorithm::Zstd(3)).compress(CompressMode::Always);
+	let archive = build(zstd, solid().dictionary_size(4096))?;
+	check(&archive, &|entry| entry.block.is_some() && entry.flags.contains(Flags::DICTIONARY_COMPRESSED))?;
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn deduplicate_leaves() -> InternalResult {
+	use std::io::Cursor;
+
+	let placeholder = [0xAB_u8; 4096];
+	let unique = b"Not like the others".as_slice();
+
+	let build = |config: BuilderConfig| -> InternalResult<(Vec<u8>, DumpStats)> {
+		let mut leaves = (0..8).map(|i| Leaf::new(placeholder.as_slice(), format!("placeholder_{i}"))).collect::<Vec<_>>();
+		leaves.push(Leaf::new(unique, "unique"));
+
+		let mut target = Cursor::new(vec![]);
+		let stats = dump(&mut target, &mut leaves, Some(config), None)?;
+		Ok((target.into_inner(), stats))
+	};
+
+	let (plain, stats) = build(BuilderConfig::default())?;
+	assert_eq!(stats.bytes_deduplicated, 0);
+
+	for threads in [1, 4] {
+		let (deduplicated, stats) = build(BuilderConfig::default().deduplicate(true).threads(threads))?;
+		assert_eq!(stats.bytes_written, deduplicated.len() as u64);
+		assert_eq!(stats.bytes_deduplicated, 7 * placeholder.len() as u64);
+		assert_eq!(plain.len() - deduplicated.len(), 7 * placeholder.len());
+
+		let archive = Archive::new(Cursor::new(deduplicated))?;
+		let first = archive.fetch_entry("placeholder_0").unwrap();
+
+		for i in 0..8 {
+			let id = format!("placeholder_{i}");
+			let entry = archive.fetch_entry(&id).unwrap();
+			assert_eq!((entry.location, entry.offset), (first.location, first.offset));
+			assert_eq!(archive.fetch(&id)?.data.as_ref(), placeholder.as_slice());
+		}
+
+		assert_ne!(archive.fetch_entry("unique").unwrap().location, first.location);
+		assert_eq!(archive.fetch("unique")?.data.as_ref(), unique);
+	}
+
+	// leaves are only merged if their bytes match, not just their fingerprints
+	{
+		use crate::writer::{dedup::{Deduplicator, Fingerprinter}, spill::Spill};
+		use std::io::Write;
+
+		let mut dedup = Deduplicator::default();
+		let spill = |data: &[u8]| -> InternalResult<Spill> {
+			let mut spill = Spill::default();
+			spill.write_all(data)?;
+			Ok(spill)
+		};
+
+		let mut fingerprinter = Fingerprinter::new(std::io::sink(), &dedup.keys);
+		fingerprinter.write_all(&placeholder)?;
+		let fingerprint = fingerprinter.finish();
+
+		let mut entry = RegistryEntry::empty();
+		entry.offset = placeholder.len() as u64;
+		dedup.insert(fingerprint, &entry, &mut spill(&placeholder)?)?;
+
+		// same length and fingerprint, different bytes
+		let mut collision = placeholder;
+		collision[2048] = 0xCD;
+
+		assert_eq!(dedup.find(fingerprint, &entry, &mut spill(&collision)?)?, None);
+		assert_eq!(dedup.find(fingerprint, &entry, &mut spill(&placeholder)?)?, Some(entry.location));
+		assert_eq!(dedup.saved, placeholder.len() as u64);
+	}
+
+	// encrypted leaves are written on their own, each with it's own nonce
+	#[cfg(feature = "crypto")]
+	{
+		let config = BuilderConfig::default().deduplicate(true).encryption_key(crate::crypto_utils::gen_encryption_key());
+		let key = config.encryption_key.unwrap();
+
+		let mut leaves = [Leaf::new(placeholder.as_slice(), "a").encrypt(true), Leaf::new(placeholder.as_slice(), "b").encrypt(true)];
+		let mut target = Cursor::new(vec![]);
+		assert_eq!(dump(&mut target, &mut leaves, Some(config), None)?.bytes_deduplicated, 0);
+
+		let archive = Archive::with_config(target, ArchiveConfig::default().decryption_key(key))?;
+		let (a, b) = (archive.fetch_entry("a").unwrap(), archive.fetch_entry("b").unwrap());
+		assert_ne!(a.location, b.location);
+		assert_ne!(a.nonce, b.nonce);
+		assert_eq!(archive.fetch("b")?.data.as_ref(), placeholder.as_slice());
+	}
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn entry_checksums() -> InternalResult {
+	use std::io::{Cursor, Read};
+
+	let data = include_bytes!("../test_data/song.txt");
+
+	let mut target = Cursor::new(vec![]);
+	let mut leaves = [Leaf::new(data.as_slice(), "song"), Leaf::new(b"Untouched".as_slice(), "other")];
+	dump(&mut target, &mut leaves, Some(BuilderConfig::default().checksums(true)), None)?;
+
+	let mut bytes = target.into_inner();
+	let archive = Archive::from_source(bytes.as_slice())?;
+
+	let entry = archive.fetch_entry("song").unwrap();
+	assert!(entry.flags.contains(Flags::CHECKSUM_FLAG) && entry.checksum.is_some());
+	assert_eq!(archive.fetch("song")?.data.as_ref(), data.as_slice());
+	assert_eq!(archive.fetch_borrowed("song")?.data.as_ref(), data.as_slice());
+
+	// flip a bit in the middle of the entry
+	bytes[(entry.location + entry.offset / 2) as usize] ^= 0b0001_0000;
+	let archive = Archive::from_source(bytes.as_slice())?;
+
+	assert!(matches!(archive.fetch("song"), Err(InternalError::ChecksumMismatchError(id)) if id == "song"));
+	assert!(matches!(archive.fetch_borrowed("song"), Err(InternalError::ChecksumMismatchError(_))));
+
+	let error = archive.fetch_reader("song")?.read_to_end(&mut vec![]).unwrap_err();
+	assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+	// other entries are unaffected
+	assert_eq!(archive.fetch("other")?.data.as_ref(), b"Untouched");
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn entry_metadata() -> InternalResult {
+	use std::io::Cursor;
+
+	let data = include_bytes!("../test_data/song.txt");
+
+	let template = Leaf::<&[u8]>::default().meta("mime", "text/plain");
+	let leaves = |big: Metadata| {
+		[
+			Leaf::new(data.as_slice(), "song").template(&template).meta("mtime", 1_700_000_000i64).meta("hash", [1u8, 2, 3].as_slice()),
+			Leaf::new(b"Hello".as_slice(), "hello").template(&template),
+			Leaf::new(b"Bare".as_slice(), "bare"),
+			Leaf::new(b"Big".as_slice(), "big").metadata(big),
+		]
+	};
+
+	// keys must fit in a byte
+	let overflowing = Metadata::new().with("k".repeat(Metadata::MAX_KEY_LENGTH + 1), true);
+	assert!(matches!(dump(Cursor::new(vec![]), &mut leaves(overflowing), None, None), Err(InternalError::MetadataError(_))));
+
+	for threads in [1, 4] {
+		let mut target = Cursor::new(vec![]);
+		let config = BuilderConfig::default().threads(threads);
+		dump(&mut target, &mut leaves(Metadata::new().with("scale", 0.25).with("hidden", true)), Some(config), None)?;
+
+		let archive = Archive::new(target)?;
+
+		let song = archive.fetch_metadata("song").unwrap();
+		assert_eq!(song.len(), 3);
+		assert_eq!(song.get("mime").and_then(MetaValue::as_str), Some("text/plain"));
+		assert_eq!(song.get("mtime").and_then(MetaValue::as_int), Some(1_700_000_000));
+		assert_eq!(song.get("hash").and_then(MetaValue::as_bytes), Some([1u8, 2, 3].as_slice()));
+
+		let big = archive.fetch_entry("big").unwrap();
+		assert!(big.flags.contains(Flags::METADATA_FLAG));
+		assert_eq!(big.metadata.get("scale"), Some(&MetaValue::Float(0.25)));
+
+		// entries without metadata don't carry the section
+		let bare = archive.fetch_entry("bare").unwrap();
+		assert!(!bare.flags.contains(Flags::METADATA_FLAG) && bare.metadata.is_empty());
+
+		assert_eq!(archive.fetch_metadata("hello").unwrap().get("mime"), Some(&MetaValue::from("text/plain")));
+		assert_eq!(archive.fetch("song")?.data.as_ref(), data.as_slice());
+	}
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn aligned_leaves() -> InternalResult {
+	use std::io::Cursor;
+
+	let data = include_bytes!("../test_data/song.txt");
+	let leaves = || {
+		[
+			Leaf::new(b"odd".as_slice(), "odd"),
+			Leaf::new(data.as_slice(), "page").alignment(4096),
+			Leaf::new(b"packed".as_slice(), "packed").alignment(1),
+			Leaf::new(data.as_slice(), "copy"),
+		]
+	};
+
+	assert!(matches!(dump(Cursor::new(vec![]), &mut leaves(), Some(BuilderConfig::default().alignment(24)), None), Err(InternalError::AlignmentError(_))));
+
+	for threads in [1, 3] {
+		// "copy" is identical to "page", but deduplicated data must satisfy it's own alignment too
+		let config = BuilderConfig::default().alignment(64).threads(threads).deduplicate(threads == 1);
+
+		let mut target = Cursor::new(vec![]);
+		dump(&mut target, &mut leaves(), Some(config), None)?;
+
+		let archive = Archive::new(target)?;
+		let alignment = |id: &str| {
+			let entry = archive.fetch_entry(id).unwrap();
+			assert_eq!(entry.flags.contains(Flags::ALIGNED_FLAG), entry.alignment.is_some());
+			assert_eq!(entry.location % entry.alignment.unwrap_or(1), 0);
+			entry.alignment
+		};
+
+		assert_eq!(alignment("odd"), Some(64));
+		assert_eq!(alignment("page"), Some(4096));
+		assert_eq!(alignment("packed"), None);
+		assert_eq!(alignment("copy"), Some(64));
+
+		assert_eq!(archive.fetch("page")?.data.as_ref(), data.as_slice());
+		assert_eq!(archive.fetch("packed")?.data.as_ref(), b"packed");
+	}
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn directory_tree() -> InternalResult {
+	use std::io::Cursor;
+
+	let ids = [
+		"readme",
+		"sfx/jump.wav",
+		"sfx/ui/click.wav",
+		"sfx/ui/click.ogg",
+		"sfx/ui/deep/hover.wav",
+		"textures/ui/button.png",
+		"textures/ui",
+	];
+
+	let mut leaves = ids.map(|id| Leaf::new(id.as_bytes(), id));
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, None, None)?;
+
+	let archive = Archive::from_source(target.get_ref().as_slice())?;
+	let ids_of = |entries: Vec<&RegistryEntry>| entries.into_iter().map(|entry| entry.id.to_string()).collect::<Vec<_>>();
+
+	// directories come first, an ID may name both an entry and a directory
+	let root = archive.read_dir("").unwrap().iter().map(|child| (child.name(), child.is_dir())).collect::<Vec<_>>();
+	assert_eq!(root, [("sfx", true), ("textures", true), ("readme", false)]);
+
+	let textures = archive.read_dir("textures/").unwrap().iter().map(DirEntry::name).collect::<Vec<_>>();
+	assert_eq!(textures, ["ui", "ui"]);
+	assert!(archive.read_dir("sfx/missing").is_none());
+
+	assert_eq!(ids_of(archive.walk("sfx/ui")), ["sfx/ui/click.ogg", "sfx/ui/click.wav", "sfx/ui/deep/hover.wav"]);
+	assert_eq!(archive.walk("").len(), ids.len());
+	assert!(archive.walk("readme").is_empty());
+
+	assert_eq!(ids_of(archive.glob("sfx/**/*.wav")), ["sfx/jump.wav", "sfx/ui/click.wav", "sfx/ui/deep/hover.wav"]);
+	assert_eq!(ids_of(archive.glob("sfx/ui/click.???")), ["sfx/ui/click.ogg", "sfx/ui/click.wav"]);
+	assert_eq!(ids_of(archive.glob("*/ui/*")), ["sfx/ui/click.ogg", "sfx/ui/click.wav", "textures/ui/button.png"]);
+	assert_eq!(ids_of(archive.glob("**/ui")), ["textures/ui"]);
+	assert_eq!(archive.glob("**").len(), ids.len());
+
+	// the separator is configurable
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().separator('.'))?;
+	assert_eq!(ids_of(archive.glob("**.wav")), ["sfx/jump.wav", "sfx/ui/click.wav", "sfx/ui/deep/hover.wav"]);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn append_leaves() -> InternalResult {
+	use std::io::Cursor;
+
+	let data = include_bytes!("../test_data/song.txt");
+
+	// appending to an empty archive pads past the grown header
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [] as &mut [Leaf<&[u8]>], None, None)?;
+	append(&mut target, &mut [Leaf::new(b"first".as_slice(), "first")], None, None)?;
+
+	let config = BuilderConfig::default().alignment(16);
+	append(&mut target, &mut [Leaf::new(data.as_slice(), "song"), Leaf::new(b"second".as_slice(), "second")], Some(config), None)?;
+
+	let duplicate = append(&mut target, &mut [Leaf::new(b"again".as_slice(), "first")], None, None);
+	assert!(matches!(duplicate, Err(InternalError::DuplicateLeafID(_))));
+
+	// the latest registry is read transparently
+	let archive = Archive::from_source(target.get_ref().as_slice())?;
+	assert_eq!(archive.entries().len(), 3);
+	assert_eq!(archive.fetch("first")?.data.as_ref(), b"first");
+	assert_eq!(archive.fetch("second")?.data.as_ref(), b"second");
+	assert_eq!(archive.fetch("song")?.data.as_ref(), data.as_slice());
+	assert_eq!(archive.fetch_entry("song").unwrap().location % 16, 0);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn append_signed_leaves() -> InternalResult {
+	use crate::crypto_utils::read_keypair;
+	use std::io::Cursor;
+
+	let keypair = read_keypair(KEYPAIR.as_slice())?;
+	let vk = keypair.verifying_key();
+	let config = || BuilderConfig::default().keypair(read_keypair(KEYPAIR.as_slice()).unwrap()).encryption_key(ENCRYPTION_KEY);
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Old secret".as_slice(), "old").encrypt(true).sign(true)], Some(config()), None)?;
+
+	// signed archives can't be appended to without their key
+	let unsigned = append(&mut target, &mut [Leaf::new(b"Forged".as_slice(), "forged")], None, None);
+	assert!(matches!(unsigned, Err(InternalError::NoKeypairError)));
+
+	append(&mut target, &mut [Leaf::new(b"New secret".as_slice(), "new").encrypt(true).sign(true)], Some(config()), None)?;
+
+	let source = target.into_inner();
+	let archive_config = ArchiveConfig::default().key(vk).decryption_key(ENCRYPTION_KEY);
+	let archive = Archive::from_source_with_config(source.as_slice(), archive_config.clone())?;
+
+	for (id, data) in [("old", b"Old secret"), ("new", b"New secret")] {
+		let resource = archive.fetch(id)?;
+		assert!(resource.verified);
+		assert_eq!(resource.data.as_ref(), data);
+	}
+
+	// the manifest covers the appended registry
+	let mut tampered = source.clone();
+	*tampered.last_mut().unwrap() ^= 1;
+	assert!(matches!(
+		Archive::from_source_with_config(tampered.as_slice(), archive_config),
+		Err(InternalError::ManifestVerificationError)
+	));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn append_rejections() -> InternalResult {
+	use crate::crypto_utils::{gen_encryption_key, gen_keypair, gen_recipient_keypair};
+	use std::io::Cursor;
+
+	let keypair = gen_keypair();
+	let leaf = |encrypt| [Leaf::new(b"Appended".as_slice(), "appended").encrypt(encrypt)];
+
+	// nothing is written when appending is refused
+	let rejected = |source: &Vec<u8>, config: Option<BuilderConfig>, encrypt: bool| -> InternalError {
+		let mut target = Cursor::new(source.clone());
+		let error = append(&mut target, &mut leaf(encrypt), config, None).unwrap_err();
+
+		assert_eq!(target.get_ref(), source);
+		error
+	};
+
+	// signed archives need their own key
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Signed".as_slice(), "signed").sign(true)], Some(BuilderConfig::default().keypair(keypair.clone())), None)?;
+	let signed = target.into_inner();
+
+	assert!(matches!(rejected(&signed, None, false), InternalError::NoKeypairError));
+	assert!(matches!(rejected(&signed, Some(BuilderConfig::default().keypair(gen_keypair())), false), InternalError::ManifestVerificationError));
+
+	// recipients can't be changed, and passphrases only work for archives built with one
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Secret".as_slice(), "secret").encrypt(true)], Some(BuilderConfig::default().encryption_key(ENCRYPTION_KEY)), None)?;
+	let encrypted = target.into_inner();
+
+	let (_, recipient) = gen_recipient_keypair();
+	assert!(matches!(rejected(&encrypted, Some(BuilderConfig::default().recipient(recipient)), false), InternalError::OtherError(_)));
+	assert!(matches!(rejected(&encrypted, Some(BuilderConfig::default().passphrase("hunter2")), false), InternalError::OtherError(_)));
+
+	// new leaves must be encrypted with the archive's key, whether given directly or derived from a passphrase
+	let wrong_key = Some(BuilderConfig::default().encryption_key(gen_encryption_key()));
+	assert!(matches!(rejected(&encrypted, wrong_key, true), InternalError::EntryAuthenticationError(_)));
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Secret".as_slice(), "secret").encrypt(true)], Some(BuilderConfig::default().passphrase("hunter2")), None)?;
+	let protected = target.into_inner();
+
+	let wrong_passphrase = Some(BuilderConfig::default().passphrase("hunter3"));
+	assert!(matches!(rejected(&protected, wrong_passphrase, true), InternalError::EntryAuthenticationError(_)));
+	append(Cursor::new(protected), &mut leaf(true), Some(BuilderConfig::default().passphrase("hunter2")), None)?;
+
+	// signing a tiny archive grows its header past the registry, into the leaf data
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut [Leaf::new(b"Tiny".as_slice(), "t")], None, None)?;
+	let tiny = target.into_inner();
+
+	assert!(matches!(rejected(&tiny, Some(BuilderConfig::default().keypair(keypair)), false), InternalError::OtherError(_)));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn append_then_rewrite_recipients() -> InternalResult {
+	use crate::crypto_utils::{gen_keypair, gen_recipient_keypair};
+	use std::io::Cursor;
+
+	let (alice, alice_pk) = gen_recipient_keypair();
+	let (bob, bob_pk) = gen_recipient_keypair();
+
+	let config = || BuilderConfig::default().encryption_key(ENCRYPTION_KEY);
+	let leaves = || [Leaf::new(b"Old secret".as_slice(), "old").encrypt(true)];
+	let appended = || [Leaf::new(b"New secret".as_slice(), "new").encrypt(true)];
+
+	// the grown section no longer fits before the appended leaves, so it's moved to the end
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves(), Some(config().recipient(alice_pk)), None)?;
+	append(&mut target, &mut appended(), Some(config()), None)?;
+
+	let length = target.get_ref().len();
+	rewrite_recipients(&mut target, &alice, &[alice_pk, bob_pk])?;
+	assert!(target.get_ref().len() > length);
+
+	for secret in [alice, bob] {
+		let archive = Archive::from_source_with_config(target.get_ref().as_slice(), ArchiveConfig::default().recipient_key(secret))?;
+		assert_eq!(archive.fetch("old")?.data.as_ref(), b"Old secret");
+		assert_eq!(archive.fetch("new")?.data.as_ref(), b"New secret");
+	}
+
+	// moving it would invalidate the manifest of signed archives
+	let (carol, carol_pk) = gen_recipient_keypair();
+	let keypair = gen_keypair();
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves(), Some(config().keypair(keypair.clone()).recipient(carol_pk)), None)?;
+	append(&mut target, &mut appended(), Some(config().keypair(keypair.clone())), None)?;
+
+	let source = target.get_ref().clone();
+	assert!(matches!(rewrite_recipients(&mut target, &carol, &[carol_pk, bob_pk]), Err(InternalError::OtherError(_))));
+	assert_eq!(target.get_ref(), &source);
+
+	let archive_config = ArchiveConfig::default().key(keypair.verifying_key()).recipient_key(carol);
+	let archive = Archive::from_source_with_config(target.get_ref().as_slice(), archive_config)?;
+	assert_eq!(archive.fetch("new")?.data.as_ref(), b"New secret");
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto", feature = "compression"))]
+fn verify_all_entries() -> InternalResult {
+	use std::io::Cursor;
+
+	let text = include_bytes!("../test_data/poem.txt");
+	let keypair = crate::crypto_utils::gen_keypair();
+
+	let template = Leaf::<&[u8]>::default().sign(false);
+	let mut leaves = [
+		Leaf::new(text.as_slice(), "signed").sign(true),
+		Leaf::new(text.as_slice(), "compressed").template(&template).compress(CompressMode::Always),
+		Leaf::new(text.as_slice(), "encrypted").template(&template).encrypt(true),
+		Leaf::new(text.as_slice(), "plain").template(&template),
+		Leaf::new(b"tiny".as_slice(), "solid_a").template(&template).compress(CompressMode::Always),
+		Leaf::new(b"tinier".as_slice(), "solid_b").template(&template).compress(CompressMode::Always),
+	];
+
+	let config = BuilderConfig::default().keypair(keypair.clone()).encryption_key(ENCRYPTION_KEY).solid_block_size(1024);
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(config), None)?;
+
+	let mut source = target.into_inner();
+	let config = ArchiveConfig::default().key(keypair.verifying_key()).decryption_key(ENCRYPTION_KEY);
+
+	for threads in [1, 4] {
+		let archive = Archive::from_source_with_config(source.as_slice(), config.clone())?;
+		let reports = archive.verify_all(threads);
+
+		assert_eq!(reports.len(), 6);
+		assert!(reports.windows(2).all(|pair| pair[0].id < pair[1].id));
+		assert!(reports.iter().all(|report| matches!(report.status, EntryStatus::Ok) == (report.id.as_ref() == "signed")));
+		assert!(reports.iter().all(|report| report.status.is_intact()));
+	}
+
+	// corrupt each entry in a different way
+	let (positions, plain, last) = {
+		let archive = Archive::from_source_with_config(source.as_slice(), config.clone())?;
+		let middle = |id: &str| {
+			let entry = archive.fetch_entry(id).unwrap();
+			(entry.location + entry.offset / 2) as usize
+		};
+
+		let positions = [middle("signed"), middle("encrypted")];
+		let plain = archive.fetch_entry("plain").unwrap();
+		let last = archive.entries().values().max_by_key(|entry| entry.location).unwrap().clone();
+
+		(positions, plain, last)
+	};
+
+	for position in positions {
+		source[position] ^= 0xFF;
+	}
+
+	// cut the source off in the middle of the last entry
+	source.truncate((last.location + last.offset / 2) as usize);
+
+	let archive = Archive::from_source_with_config(source.as_slice(), config)?;
+	let reports = archive.verify_all(1);
+	let status = |id: &str| &reports.iter().find(|report| report.id.as_ref() == id).unwrap().status;
+
+	assert!(matches!(status("signed"), EntryStatus::BadSignature));
+	assert!(matches!(status("encrypted"), EntryStatus::DecryptFailure(_)));
+	assert!(matches!(status(&last.id), EntryStatus::OutOfBounds));
+
+	if plain.location != last.location {
+		assert!(matches!(status("plain"), EntryStatus::Unsigned));
+	}
+
+	// checksums catch corruption without any keys
+	let mut leaves = [Leaf::new(text.as_slice(), "checksummed").compress(CompressMode::Always)];
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(BuilderConfig::default().checksums(true)), None)?;
+
+	let mut source = target.into_inner();
+	let entry = Archive::from_source(source.as_slice())?.fetch_entry("checksummed").unwrap();
+	source[(entry.location + entry.offset / 2) as usize] ^= 0xFF;
+
+	let reports = Archive::from_source(source.as_slice())?.verify_all(1);
+	assert!(matches!(reports[0].status, EntryStatus::ChecksumMismatch));
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive", feature = "crypto"))]
+fn chunked_encryption() -> InternalResult {
+	use crate::crypto::{CHUNK_SIZE, TAG_LENGTH};
+	use std::io::{Cursor, Read};
+
+	// one entry ending mid-chunk, another on a chunk boundary which gets an empty final chunk
+	let input = (0..CHUNK_SIZE as u32 * 3 + 1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+	let aligned = &input[..CHUNK_SIZE * 2];
+
+	let config = BuilderConfig::default().encryption_key(ENCRYPTION_KEY);
+	let mut leaves = [Leaf::new(input.as_slice(), "uneven").encrypt(true), Leaf::new(aligned, "aligned").encrypt(true)];
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, &mut leaves, Some(config), None)?;
+
+	let mut source = target.into_inner();
+	let config = ArchiveConfig::default().decryption_key(ENCRYPTION_KEY);
+	let archive = Archive::from_source_with_config(source.as_slice(), config.clone())?;
+
+	for (id, data) in [("uneven", input.as_slice()), ("aligned", aligned)] {
+		assert_eq!(archive.fetch_entry(id).unwrap().offset as usize, data.len() + (data.len() / CHUNK_SIZE + 1) * TAG_LENGTH);
+		assert_eq!(archive.fetch(id)?.data.as_ref(), data);
+
+		let mut streamed = Vec::new();
+		archive.fetch_reader(id)?.read_to_end(&mut streamed)?;
+		assert_eq!(streamed, data);
+	}
+
+	// swapping two chunks around breaks authentication, when fetched whole and when streamed
+	let location = archive.fetch_entry("uneven").unwrap().location as usize;
+	let chunk = CHUNK_SIZE + TAG_LENGTH;
+	let (first, second) = source[location..location + 2 * chunk].split_at_mut(chunk);
+	first.swap_with_slice(second);
+
+	let archive = Archive::from_source_with_config(source.as_slice(), config)?;
+	assert!(matches!(archive.fetch("uneven"), Err(InternalError::EntryAuthenticationError(_))));
+
+	let error = archive.fetch_reader("uneven")?.read_to_end(&mut Vec::new()).unwrap_err();
+	assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive", feature = "crypto"))]
+fn streaming_large_leaves() -> InternalResult {
+	use crate::crypto_utils::gen_keypair;
+	use std::io::Cursor;
+
+	// larger than a spill's in-memory threshold, one compressible and one not
+	let size = 4 * 1024 * 1024 + 4096;
+	let mut state = 0x2545_f491_u32;
+	let noise = (0..size)
+		.map(|_| {
+			state ^= state << 13;
+			state ^= state >> 17;
+			state ^= state << 5;
+			state as u8
+		})
+		.collect::<Vec<_>>();
+
+	let sevens = vec![7u8; size];
+	let keypair = gen_keypair();
+
+	for threads in [1, 4] {
+		let config = BuilderConfig::default().threads(threads).keypair(keypair.clone()).encryption_key(ENCRYPTION_KEY);
+		let template = Leaf::<&[u8]>::default().compress(CompressMode::Detect).encrypt(true).sign(true);
+
+		let mut leaves = [
+			Leaf::new(sevens.as_slice(), "sevens").template(&template),
+			Leaf::new(noise.as_slice(), "noise").template(&template),
+		];
+
+		let mut target = Cursor::new(vec![]);
+		dump(&mut target, &mut leaves, Some(config), None)?;
+
+		let config = ArchiveConfig::default().key(keypair.verifying_key()).decryption_key(ENCRYPTION_KEY);
+		let archive = Archive::with_config(target, config)?;
+
+		// compression is only kept where it pays off
+		assert!(archive.fetch_entry("sevens").unwrap().flags.contains(Flags::COMPRESSED_FLAG));
+		assert!(!archive.fetch_entry("noise").unwrap().flags.contains(Flags::COMPRESSED_FLAG));
+
+		let fetched = archive.fetch("sevens")?;
+		assert!(fetched.verified);
+		assert_eq!(fetched.data.as_ref(), sevens.as_slice());
+
+		let fetched = archive.fetch("noise")?;
+		assert!(fetched.verified);
+		assert_eq!(fetched.data.as_ref(), noise.as_slice());
+	}
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "compression", feature = "builder", feature = "archive"))]
+fn fetch_borrowed_zero_copy() -> InternalResult {
+	use std::{borrow::Cow, io::Cursor};
+
+	let input = [42u8; 2048];
+	let mut target = Cursor::new(vec![]);
+
+	dump(
+		&mut target,
+		&mut [
+			Leaf::new(input.as_slice(), "plain"),
+			Leaf::new(input.as_slice(), "compressed").compress(CompressMode::Always),
+		],
+		None,
+		None,
+	)?;
+
+	let source = target.into_inner();
+	let archive = Archive::new(Cursor::new(source.as_slice()))?;
+
+	// plain entries point straight into the source
+	let plain = archive.fetch_borrowed("plain")?;
+	let Cow::Borrowed(data) = plain.data else {
+		panic!("Plain entry was copied into an owned buffer")
+	};
+
+	let location = archive.fetch_entry("plain").unwrap().location as usize;
+	assert_eq!(data, input.as_slice());
+	assert_eq!(data.as_ptr(), source[location..].as_ptr());
+
+	// processed entries fall back to an owned buffer
+	let compressed = archive.fetch_borrowed("compressed")?;
+	assert!(matches!(compressed.data, Cow::Owned(_)));
+	assert_eq!(compressed.data.as_ref(), input.as_slice());
+
+	assert!(matches!(archive.fetch_borrowed("missing"), Err(InternalError::MissingResourceError(_))));
+
+	// positional sources over a slice borrow too
+	let archive = Archive::from_source(source.as_slice())?;
+	let plain = archive.fetch_borrowed("plain")?;
+
+	assert!(matches!(plain.data, Cow::Borrowed(_)));
+	assert_eq!(plain.data.as_ref(), input.as_slice());
+
+	Ok(())
+}
+
+#[test]
+#[cfg(all(feature = "builder", feature = "archive"))]
+fn positional_concurrent_fetching() -> InternalResult {
+	use rayon::prelude::*;
+	use std::io::Cursor;
+
+	let ids = (0..64).map(|i| format!("ID {}", i)).collect::<Vec<_>>();
+	let data = ids.iter().map(|id| id.repeat(32).into_bytes()).collect::<Vec<_>>();
+	let mut leaves = ids.iter().zip(data.iter()).map(|(id, d)| Leaf::new(d.as_slice(), id)).collect::<Vec<_>>();
+
+	let mut target = Cursor::new(vec![]);
+	dump(&mut target, leaves.as_mut_slice(), None, None)?;
+
+	// no Mutex is involved when fetching from a positional source
+	let source = target.into_inner();
+	std::fs::write(POSITIONAL_TARGET, &source)?;
+
+	let archive = Archive::from_source(source)?;
+	assert_eq!(archive.entries().len(), ids.len());
+
+	ids.par_iter().zip(data.par_iter()).try_for_each(|(id, expected)| {
+		let resource = archive.fetch(id)?;
+		assert_eq!(resource.data.as_ref(), expected.as_slice());
+
+		let mut streamed = vec![];
+		std::io::Read::read_to_end(&mut archive.fetch_reader(id)?, &mut streamed)?;
+		assert_eq!(&streamed, expected);
+
+		InternalResult::Ok(())
+	})?;
+
+	// files support positional reads too
+	let archive = Archive::from_source(File::open(POSITIONAL_TARGET)?)?;
+	ids.par_iter().zip(data.par_iter()).try_for_each(|(id, expected)| {
+		assert_eq!(archive.fetch(id)?.data.as_ref(), expected.as_slice());
+		InternalResult::Ok(())
+	})?;
+
+	Ok(())
+}
+
+#[test]
+#[cfg(feature = "archive")]
+fn test_batch_fetching() -> InternalResult {
+	use rayon::prelude::*;
+	use std::{collections::HashMap, io::Cursor};
+
+	// Define input constants
+	const INPUT_LEN: usize = 8;
+	const INPUT: [u8; INPUT_LEN] = [69u8; INPUT_LEN];
+
+	let mut target = Cursor::new(vec![]);
+
+	// Define and queue data
+	let mut ids = (0..120).map(|i| format!("ID {}", i)).collect::<Vec<_>>();
+	let mut leaves = ids.iter().map(|i| Leaf::new(&INPUT[..], i)).collect::<Vec<_>>();
+
+	ids.push("ERRORS".to_string());
+
+	// Process data
+	let config = BuilderConfig::default().threads(4);
+	dump(&mut target, leaves.as_mut_slice(), Some(config), None)?;
+
+	let archive = Archive::new(target)?;
+	let mut resources = ids.as_slice().par_iter().map(|id| (id.as_str(), archive.fetch(id))).collect::<HashMap<_, _>>();
+
+	// Tests and checks
+	assert!(!resources.contains_key("NON_EXISTENT"));
+	assert!(resources.contains_key("ERRORS"));
+
+	match resources.remove("ERRORS").unwrap() {
+		Ok(_) => {
+			return Err(InternalError::OtherError("This should be an error".into()));
+		},
+		Err(err) => match err {
+			InternalError::MissingResourceError(_) => {
+				resources.remove("ERRORS");
+			},
+
+			specific => return Err(specific),
+		},
+	};
+
+	for (_, res) in resources {
+		assert_eq!(res?.data.as_ref(), &INPUT[..]);
+	}
+
+	Ok(())
+}
@@ -1,67 +1,266 @@
-#[cfg(feature = "crypto")]
-use crate::crypto;
-use crate::global::flags::Flags;
-
-/// Settings for [`dump`](crate::writer::dump)
-#[derive(Debug, Clone)]
-pub struct BuilderConfig {
-	/// Number of threads to spawn during `Builder::dump`. Set to 1 (default) to disable multithreading.
-	pub num_threads: usize,
-	/// Singleton flags to be written into the `Header` section of the archive.
-	pub flags: Flags,
-	/// An optional private key. If one is provided, then the archive will have signatures.
-	#[cfg(feature = "crypto")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-	pub signing_key: Option<crypto::SigningKey>,
-}
-
-// Helper functions
-impl BuilderConfig {
-	/// Setter for the [`num_threads`](BuilderConfig::num_threads) field
-	pub fn threads(
-		mut self,
-		num_threads: usize,
-	) -> Self {
-		self.num_threads = num_threads;
-		self
-	}
-
-	/// Setter for the [`keypair`](BuilderConfig::keypair) field
-	#[cfg(feature = "crypto")]
-	pub fn keypair(
-		mut self,
-		keypair: crypto::SigningKey,
-	) -> Self {
-		self.signing_key = Some(keypair);
-		self
-	}
-
-	/// Setter for the [`flags`](BuilderConfig::flags) field
-	pub fn flags(
-		mut self,
-		flags: Flags,
-	) -> Self {
-		self.flags = flags;
-		self
-	}
-
-	/// Read and parse a keypair from a stream of bytes
-	#[cfg(feature = "crypto")]
-	pub fn load_keypair<T: std::io::Read>(
-		&mut self,
-		handle: T,
-	) -> crate::global::error::InternalResult {
-		crate::crypto_utils::read_keypair(handle).map(|kp| self.signing_key = Some(kp))
-	}
-}
-
-impl Default for BuilderConfig {
-	fn default() -> BuilderConfig {
-		BuilderConfig {
-			num_threads: 1,
-			flags: Flags::default(),
-			#[cfg(feature = "crypto")]
-			signing_key: None,
-		}
-	}
-}
+#[cfg(feature = "crypto")]
+use crate::crypto;
+use crate::global::flags::Flags;
+
+#[cfg(feature = "compression")]
+use crate::global::{
+	codec::{Codec, CodecRegistry},
+	compressor::CompressionAlgorithm,
+};
+
+/// Settings for [`dump`](crate::writer::dump)
+#[derive(Debug, Clone)]
+pub struct BuilderConfig {
+	/// Number of threads to spawn during `Builder::dump`. Set to 1 (default) to disable multithreading.
+	pub num_threads: usize,
+	/// Singleton flags to be written into the `Header` section of the archive.
+	pub flags: Flags,
+	/// An optional private key. If one is provided, then the archive will have signatures.
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub signing_key: Option<crypto::SigningKey>,
+	/// An optional symmetric key, required to encrypt [`Leaf`](crate::builder::Leaf)s. Loaders need the same key to decrypt them, see [`ArchiveConfig`](crate::archive::ArchiveConfig)
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub encryption_key: Option<crypto::EncryptionKey>,
+	/// An optional passphrase, from which the encryption key is derived. Takes precedence over [`encryption_key`](BuilderConfig::encryption_key)
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub passphrase: Option<String>,
+	/// Parameters used when deriving the encryption key from the [`passphrase`](BuilderConfig::passphrase), stored in the archive's header
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub kdf_params: crypto::KdfParams,
+	/// Public keys of the recipients able to open the archive. The encryption key is wrapped for each of them, and is randomly generated if no other key is given
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub recipients: Vec<crypto::RecipientKey>,
+	/// Maximum size of a Zstd dictionary to train from the leaves' data and store in the archive's header, [`None`] (default) to disable.
+	/// Leaves compressed with [`Zstd`](crate::builder::CompressionAlgorithm::Zstd) and no larger than [`SMALL_LEAF_SIZE`](crate::builder::SMALL_LEAF_SIZE) are compressed against it.
+	/// No dictionary is stored if there isn't enough sample data to train on
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub dictionary_size: Option<usize>,
+	/// User-defined codecs, selected per [`Leaf`](crate::builder::Leaf) by their ID through [`Leaf::codec`](crate::builder::Leaf::codec)
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub codecs: CodecRegistry,
+	/// Algorithms tried on leaves compressed with [`CompressMode::Best`](crate::builder::CompressMode::Best). Defaults to LZ4, Snappy, Brotli(9) and Zstd(3)
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub compression_candidates: Vec<CompressionAlgorithm>,
+	/// The fraction of a leaf's size compression has to save for [`Detect`](crate::builder::CompressMode::Detect) and [`Best`](crate::builder::CompressMode::Best) modes to use it, eg `0.1` for 10%.
+	/// [`None`] (default) uses compression whenever the output isn't larger than the original data
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub min_savings: Option<f32>,
+	/// Groups compressed leaves no larger than [`SMALL_LEAF_SIZE`](crate::builder::SMALL_LEAF_SIZE) into solid blocks of about this many bytes, compressed together.
+	/// [`None`] (default) compresses every leaf on it's own. Signed or encrypted leaves, and leaves using a [`codec`](crate::builder::Leaf::codec) or [`CompressMode::Best`](crate::builder::CompressMode::Best), are never grouped
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub solid_block_size: Option<usize>,
+	/// Writes the data of identical leaves once, pointing all their registry entries at it. Leaves are compared after processing, so only leaves with the same compression settings are merged.
+	/// A copy of each distinct leaf's data is kept to compare against, in memory and spilling into a temporary file once large.
+	/// Encrypted leaves and leaves grouped into solid blocks are always written on their own. Defaults to `false`
+	pub deduplicate: bool,
+	/// Stores a checksum of each leaf's data in it's registry entry, so corrupted entries are caught when fetched. Unlike signatures, this works without the `crypto` feature. Defaults to `false`
+	pub checksums: bool,
+	/// Pads the data section so every leaf's data starts at a multiple of this power of two, eg for memory-mapped leaves handed straight to SIMD code or GPU uploads.
+	/// Overridden per leaf by [`Leaf::alignment`](crate::builder::Leaf::alignment), solid blocks are aligned as a whole. [`None`] (default) packs leaves back to back
+	pub alignment: Option<u64>,
+}
+
+// Helper functions
+impl BuilderConfig {
+	/// Setter for the [`num_threads`](BuilderConfig::num_threads) field
+	pub fn threads(
+		mut self,
+		num_threads: usize,
+	) -> Self {
+		self.num_threads = num_threads;
+		self
+	}
+
+	/// Setter for the [`keypair`](BuilderConfig::keypair) field
+	#[cfg(feature = "crypto")]
+	pub fn keypair(
+		mut self,
+		keypair: crypto::SigningKey,
+	) -> Self {
+		self.signing_key = Some(keypair);
+		self
+	}
+
+	/// Setter for the [`encryption_key`](BuilderConfig::encryption_key) field
+	#[cfg(feature = "crypto")]
+	pub fn encryption_key(
+		mut self,
+		encryption_key: crypto::EncryptionKey,
+	) -> Self {
+		self.encryption_key = Some(encryption_key);
+		self
+	}
+
+	/// Setter for the [`passphrase`](BuilderConfig::passphrase) field
+	#[cfg(feature = "crypto")]
+	pub fn passphrase(
+		mut self,
+		passphrase: impl Into<String>,
+	) -> Self {
+		self.passphrase = Some(passphrase.into());
+		self
+	}
+
+	/// Setter for the [`kdf_params`](BuilderConfig::kdf_params) field
+	#[cfg(feature = "crypto")]
+	pub fn kdf_params(
+		mut self,
+		kdf_params: crypto::KdfParams,
+	) -> Self {
+		self.kdf_params = kdf_params;
+		self
+	}
+
+	/// Adds a public key to the [`recipients`](BuilderConfig::recipients) field
+	#[cfg(feature = "crypto")]
+	pub fn recipient(
+		mut self,
+		recipient: crypto::RecipientKey,
+	) -> Self {
+		self.recipients.push(recipient);
+		self
+	}
+
+	/// Setter for the [`dictionary_size`](BuilderConfig::dictionary_size) field
+	#[cfg(feature = "compression")]
+	pub fn dictionary_size(
+		mut self,
+		dictionary_size: usize,
+	) -> Self {
+		self.dictionary_size = Some(dictionary_size);
+		self
+	}
+
+	/// Registers a [`Codec`] under an ID in the [`codecs`](BuilderConfig::codecs) field
+	#[cfg(feature = "compression")]
+	pub fn codec(
+		mut self,
+		id: u16,
+		codec: impl Codec + 'static,
+	) -> Self {
+		self.codecs = self.codecs.register(id, codec);
+		self
+	}
+
+	/// Setter for the [`compression_candidates`](BuilderConfig::compression_candidates) field
+	#[cfg(feature = "compression")]
+	pub fn compression_candidates(
+		mut self,
+		compression_candidates: Vec<CompressionAlgorithm>,
+	) -> Self {
+		self.compression_candidates = compression_candidates;
+		self
+	}
+
+	/// Setter for the [`min_savings`](BuilderConfig::min_savings) field
+	#[cfg(feature = "compression")]
+	pub fn min_savings(
+		mut self,
+		min_savings: f32,
+	) -> Self {
+		self.min_savings = Some(min_savings);
+		self
+	}
+
+	/// Setter for the [`solid_block_size`](BuilderConfig::solid_block_size) field
+	#[cfg(feature = "compression")]
+	pub fn solid_block_size(
+		mut self,
+		solid_block_size: usize,
+	) -> Self {
+		self.solid_block_size = Some(solid_block_size);
+		self
+	}
+
+	/// Setter for the [`deduplicate`](BuilderConfig::deduplicate) field
+	pub fn deduplicate(
+		mut self,
+		deduplicate: bool,
+	) -> Self {
+		self.deduplicate = deduplicate;
+		self
+	}
+
+	/// Setter for the [`checksums`](BuilderConfig::checksums) field
+	pub fn checksums(
+		mut self,
+		checksums: bool,
+	) -> Self {
+		self.checksums = checksums;
+		self
+	}
+
+	/// Setter for the [`alignment`](BuilderConfig::alignment) field
+	pub fn alignment(
+		mut self,
+		alignment: u64,
+	) -> Self {
+		self.alignment = Some(alignment);
+		self
+	}
+
+	/// Setter for the [`flags`](BuilderConfig::flags) field
+	pub fn flags(
+		mut self,
+		flags: Flags,
+	) -> Self {
+		self.flags = flags;
+		self
+	}
+
+	/// Read and parse a keypair from a stream of bytes
+	#[cfg(feature = "crypto")]
+	pub fn load_keypair<T: std::io::Read>(
+		&mut self,
+		handle: T,
+	) -> crate::global::error::InternalResult {
+		crate::crypto_utils::read_keypair(handle).map(|kp| self.signing_key = Some(kp))
+	}
+}
+
+impl Default for BuilderConfig {
+	fn default() -> BuilderConfig {
+		BuilderConfig {
+			num_threads: 1,
+			flags: Flags::default(),
+			#[cfg(feature = "crypto")]
+			signing_key: None,
+			#[cfg(feature = "crypto")]
+			encryption_key: None,
+			#[cfg(feature = "crypto")]
+			passphrase: None,
+			#[cfg(feature = "crypto")]
+			kdf_params: crypto::KdfParams::default(),
+			#[cfg(feature = "crypto")]
+			recipients: Vec::new(),
+			#[cfg(feature = "compression")]
+			dictionary_size: None,
+			#[cfg(feature = "compression")]
+			codecs: CodecRegistry::default(),
+			#[cfg(feature = "compression")]
+			compression_candidates: vec![
+				CompressionAlgorithm::LZ4,
+				CompressionAlgorithm::Snappy,
+				CompressionAlgorithm::Brotli(9),
+				CompressionAlgorithm::Zstd(3),
+			],
+			#[cfg(feature = "compression")]
+			min_savings: None,
+			#[cfg(feature = "compression")]
+			solid_block_size: None,
+			deduplicate: false,
+			checksums: false,
+			alignment: None,
+		}
+	}
+}
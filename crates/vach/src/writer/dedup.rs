@@ -0,0 +1,151 @@
+use std::{
+	collections::{HashMap, hash_map::Entry},
+	hash::{BuildHasher, DefaultHasher, Hasher, RandomState},
+	io::{self, Read, Write},
+};
+
+use super::spill::Spill;
+use crate::global::{flags::Flags, reg_entry::RegistryEntry};
+
+/// Identifies a leaf's processed data, hashed twice using independent random keys so collisions can't be crafted ahead of time.
+/// Only narrows down candidates, leaves with matching fingerprints are compared byte for byte before being merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Fingerprint {
+	hashes: [u64; 2],
+	len: u64,
+}
+
+/// Hashes the data written through it into a [`Fingerprint`]
+pub(crate) struct Fingerprinter<W> {
+	inner: W,
+	hashers: [DefaultHasher; 2],
+	len: u64,
+}
+
+impl<W: Write> Fingerprinter<W> {
+	pub(crate) fn new(
+		inner: W,
+		keys: &[RandomState; 2],
+	) -> Fingerprinter<W> {
+		Fingerprinter {
+			inner,
+			hashers: [keys[0].build_hasher(), keys[1].build_hasher()],
+			len: 0,
+		}
+	}
+
+	pub(crate) fn finish(self) -> Fingerprint {
+		Fingerprint {
+			hashes: [self.hashers[0].finish(), self.hashers[1].finish()],
+			len: self.len,
+		}
+	}
+}
+
+impl<W: Write> Write for Fingerprinter<W> {
+	fn write(
+		&mut self,
+		buf: &[u8],
+	) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+
+		for hasher in self.hashers.iter_mut() {
+			hasher.write(&buf[..written]);
+		}
+
+		self.len += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Remembers where processed leaves were written, so identical ones can point at the same data
+#[derive(Debug, Default)]
+pub(crate) struct Deduplicator {
+	pub(crate) keys: [RandomState; 2],
+	/// Keyed by the flags and codec needed to decode the data, so identical bytes meant to be read differently aren't merged.
+	/// Maps to where the data was written in the target, and where it's copy starts in `store`
+	written: HashMap<(Fingerprint, u32, Option<u16>), (u64, u64)>,
+	/// Copies of the data written so far, since the target can't be read back to compare leaves against it
+	store: Spill,
+	/// Bytes not written, since they were identical to a previous leaf's
+	pub(crate) saved: u64,
+}
+
+impl Deduplicator {
+	/// Finds where data identical to an entry's `data` was written, counting it's bytes as saved. Encrypted entries are never matched, as each has it's own nonce.
+	/// Neither are entries whose alignment the earlier data doesn't satisfy
+	pub(crate) fn find(
+		&mut self,
+		fingerprint: Fingerprint,
+		entry: &RegistryEntry,
+		data: &mut Spill,
+	) -> io::Result<Option<u64>> {
+		if entry.flags.contains(Flags::ENCRYPTED_FLAG) {
+			return Ok(None);
+		}
+
+		let Some(&(location, stored)) = self.written.get(&(fingerprint, entry.flags.bits() & Flags::AUTHENTICATED_MASK, entry.codec)) else {
+			return Ok(None);
+		};
+
+		if entry.alignment.is_some_and(|alignment| location % alignment != 0) {
+			return Ok(None);
+		}
+
+		// fingerprints can collide, only the bytes themselves tell
+		if !identical(self.store.reader_at(stored)?, data.reader()?, fingerprint.len)? {
+			return Ok(None);
+		}
+
+		self.saved += entry.offset;
+
+		Ok(Some(location))
+	}
+
+	/// Records where an entry's data was written, keeping a copy of it to compare later entries against
+	pub(crate) fn insert(
+		&mut self,
+		fingerprint: Fingerprint,
+		entry: &RegistryEntry,
+		data: &mut Spill,
+	) -> io::Result<()> {
+		if entry.flags.contains(Flags::ENCRYPTED_FLAG) {
+			return Ok(());
+		}
+
+		if let Entry::Vacant(slot) = self.written.entry((fingerprint, entry.flags.bits() & Flags::AUTHENTICATED_MASK, entry.codec)) {
+			let stored = self.store.len();
+			io::copy(&mut data.reader()?, &mut self.store)?;
+			slot.insert((entry.location, stored));
+		}
+
+		Ok(())
+	}
+}
+
+/// Compares the next `len` bytes of two readers
+fn identical(
+	mut left: impl Read,
+	mut right: impl Read,
+	mut len: u64,
+) -> io::Result<bool> {
+	let (mut a, mut b) = ([0u8; 8 * 1024], [0u8; 8 * 1024]);
+
+	while len > 0 {
+		let chunk = len.min(a.len() as u64) as usize;
+		left.read_exact(&mut a[..chunk])?;
+		right.read_exact(&mut b[..chunk])?;
+
+		if a[..chunk] != b[..chunk] {
+			return Ok(false);
+		}
+
+		len -= chunk as u64;
+	}
+
+	Ok(true)
+}
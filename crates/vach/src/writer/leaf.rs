@@ -1,273 +1,706 @@
-use crate::global::error::InternalResult;
-use crate::global::{flags::Flags, reg_entry::RegistryEntry};
-
-#[cfg(feature = "compression")]
-use crate::global::compressor::{CompressionAlgorithm, Compressor};
-
-#[cfg(feature = "crypto")]
-use crate::crypto::Encryptor;
-
-#[cfg(not(feature = "crypto"))]
-type Encryptor = ();
-
-use std::{io::Read, sync::Arc};
-
-/// Configures how a [`Leaf`] should be compressed.
-#[derive(Debug, Clone, Copy, Default)]
-#[cfg(feature = "compression")]
-#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-pub enum CompressMode {
-	/// The data is never compressed and is embedded as is.
-	#[default]
-	Never,
-	/// The data will always be compressed
-	Always,
-	/// The compressed data is used, only if it is smaller than the original data.
-	Detect,
-}
-
-/// A named ([`ID`](Leaf::id)) wrapper around an [`io::Read`](Read) handle, tagged with extra metadata.
-#[derive(Debug, Default, Clone)]
-pub struct Leaf<R = &'static [u8]> {
-	/// source data
-	pub handle: R,
-
-	/// The `ID` under which the embedded data will be referenced
-	pub id: Arc<str>,
-	/// The flags that will go into the archive write target.
-	pub flags: Flags,
-
-	/// How a [`Leaf`] should be compressed
-	#[cfg(feature = "compression")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-	pub compress: CompressMode,
-	/// The specific compression algorithm to use
-	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-	#[cfg(feature = "compression")]
-	pub compression_algo: CompressionAlgorithm,
-
-	/// Use encryption when writing into the target.
-	#[cfg(feature = "crypto")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-	pub encrypt: bool,
-	/// Whether to include a signature with this [`Leaf`]
-	#[cfg(feature = "crypto")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
-	pub sign: bool,
-}
-
-impl<R: Read + Send + Sync> Leaf<R> {
-	/// Creates a new [`Leaf`] wrapping around the given [`Read`] handle, with an ID
-	pub fn new<S: AsRef<str>>(
-		handle: R,
-		id: S,
-	) -> Leaf<R> {
-		let default = Leaf::<&'static [u8]>::default();
-
-		Leaf {
-			handle,
-			id: Arc::from(id.as_ref()),
-
-			// copy from default implementation
-			flags: default.flags,
-
-			#[cfg(feature = "compression")]
-			compress: default.compress,
-			#[cfg(feature = "compression")]
-			compression_algo: default.compression_algo,
-			#[cfg(feature = "crypto")]
-			encrypt: default.encrypt,
-			#[cfg(feature = "crypto")]
-			sign: default.sign,
-		}
-	}
-
-	/// Copy all fields from another [`Leaf`], except for `handle` and `id`.
-	pub fn template<R2>(
-		self,
-		other: &Leaf<R2>,
-	) -> Self {
-		Leaf {
-			handle: self.handle,
-			id: self.id,
-
-			flags: other.flags,
-
-			#[cfg(feature = "compression")]
-			compress: other.compress,
-			#[cfg(feature = "compression")]
-			compression_algo: other.compression_algo,
-			#[cfg(feature = "crypto")]
-			encrypt: other.encrypt,
-			#[cfg(feature = "crypto")]
-			sign: other.sign,
-		}
-	}
-
-	/// Setter for the [`compress`](Leaf::compress) field
-	#[cfg(feature = "compression")]
-	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
-	pub fn compress(
-		mut self,
-		compress: CompressMode,
-	) -> Self {
-		self.compress = compress;
-		self
-	}
-
-	/// Setter for the [`flags`](crate::builder::Flags) field
-	pub fn flags(
-		mut self,
-		flags: Flags,
-	) -> Self {
-		self.flags = flags;
-		self
-	}
-
-	/// Setter for the [`encrypt`](Leaf::encrypt) field
-	#[cfg(feature = "crypto")]
-	pub fn encrypt(
-		mut self,
-		encrypt: bool,
-	) -> Self {
-		self.encrypt = encrypt;
-		self
-	}
-
-	/// Setter for the [`sign`](Leaf::sign) field
-	#[cfg(feature = "crypto")]
-	pub fn sign(
-		mut self,
-		sign: bool,
-	) -> Self {
-		self.sign = sign;
-		self
-	}
-
-	/// Setter for the [`compression_algo`](Leaf::compression_algo) field
-	#[cfg(feature = "compression")]
-	pub fn compression_algo(
-		mut self,
-		compression_algo: CompressionAlgorithm,
-	) -> Self {
-		self.compression_algo = compression_algo;
-		self
-	}
-
-	pub(crate) fn calculate_entry_bytes(
-		&self,
-		sign: bool,
-	) -> usize {
-		#[cfg(feature = "crypto")]
-		let sig_len = if sign && self.sign { crate::SIGNATURE_LENGTH } else { 0 };
-		#[cfg(not(feature = "crypto"))]
-		let sig_len = 0;
-
-		#[cfg(feature = "crypto")]
-		let nonce_len = if sign && self.encrypt { crate::NONCE_LENGTH } else { 0 };
-		#[cfg(not(feature = "crypto"))]
-		let nonce_len = 0;
-
-		self.id.len() + RegistryEntry::CONSTANT + sig_len + nonce_len
-	}
-}
-
-impl<R> From<&mut Leaf<R>> for RegistryEntry {
-	fn from(leaf: &mut Leaf<R>) -> Self {
-		RegistryEntry {
-			id: leaf.id.clone(),
-			flags: leaf.flags,
-			..RegistryEntry::empty()
-		}
-	}
-}
-
-// Processed data ready to be inserted into a `Write + Clone` target during Building
-pub(crate) struct ProcessedLeaf {
-	pub(crate) data: Vec<u8>,
-	pub(crate) entry: RegistryEntry,
-}
-
-// Process Leaf into Prepared Data, externalised for multithreading purposes
-#[inline(never)]
-pub(crate) fn process_leaf<R: Read + Send + Sync>(
-	leaf: &mut Leaf<R>,
-	config: &super::BuilderConfig,
-	_encryptor: Option<&Encryptor>,
-) -> InternalResult<ProcessedLeaf> {
-	let mut entry: RegistryEntry = leaf.into();
-	let mut raw = Vec::new();
-
-	// Compression comes first
-	#[cfg(feature = "compression")]
-	match leaf.compress {
-		CompressMode::Never => {
-			leaf.handle.read_to_end(&mut raw)?;
-		},
-		CompressMode::Always => {
-			Compressor::new(&mut leaf.handle).compress(leaf.compression_algo, &mut raw)?;
-
-			entry.flags.force_set(Flags::COMPRESSED_FLAG, true);
-			entry.flags.force_set(leaf.compression_algo.into(), true);
-		},
-		CompressMode::Detect => {
-			let mut buffer = Vec::new();
-			leaf.handle.read_to_end(&mut buffer)?;
-
-			let mut compressed_data = Vec::new();
-			Compressor::new(buffer.as_slice()).compress(leaf.compression_algo, &mut compressed_data)?;
-
-			if compressed_data.len() <= buffer.len() {
-				entry.flags.force_set(Flags::COMPRESSED_FLAG, true);
-				entry.flags.force_set(leaf.compression_algo.into(), true);
-
-				raw = compressed_data;
-			} else {
-				buffer.as_slice().read_to_end(&mut raw)?;
-			};
-		},
-	}
-
-	// If the compression feature is turned off, simply reads into buffer
-	#[cfg(not(feature = "compression"))]
-	{
-		use crate::global::error::InternalError;
-
-		if entry.flags.contains(Flags::COMPRESSED_FLAG) {
-			return Err(InternalError::MissingFeatureError("compression"));
-		};
-
-		leaf.handle.read_to_end(&mut raw)?;
-	}
-
-	// Encryption comes second
-	#[cfg(feature = "crypto")]
-	if let Some(ex) = _encryptor
-		&& leaf.encrypt
-	{
-		entry.flags.force_set(Flags::ENCRYPTED_FLAG, true);
-		let (_raw, nonce) = ex.encrypt(&raw)?;
-
-		raw = _raw;
-		entry.nonce = Some(nonce);
-	}
-
-	// Sign final data as-is in binary
-	#[cfg(feature = "crypto")]
-	if let Some(keypair) = &config.signing_key
-		&& leaf.sign
-	{
-		entry.flags.force_set(Flags::SIGNED_FLAG, true);
-
-		let entry_bytes = entry.id.as_bytes();
-		raw.extend_from_slice(entry_bytes);
-
-		// Include entry id in the signature
-		entry.signature = Some(ed25519_dalek::Signer::sign(keypair, &raw));
-
-		// truncate leaf data as it's not indicative of serialized data
-		raw.truncate(raw.len() - entry.id.len());
-	};
-
-	Ok(ProcessedLeaf { data: raw, entry })
-}
+use crate::global::error::*;
+use crate::global::{flags::Flags, metadata::{MetaValue, Metadata}, reg_entry::RegistryEntry};
+
+#[cfg(feature = "compression")]
+use crate::global::{
+	codec::Codec,
+	compressor::{CompressionAlgorithm, Compressor},
+};
+
+#[cfg(feature = "crypto")]
+use {
+	crate::crypto::{self, ChunkEncryptor, Encryptor},
+	sha2::{Digest, Sha512},
+};
+
+#[cfg(not(feature = "crypto"))]
+pub(crate) type Encryptor = ();
+
+#[cfg(feature = "compression")]
+use super::spill::TeeReader;
+use twox_hash::XxHash64;
+use super::{
+	dedup::{Fingerprint, Fingerprinter},
+	spill::Spill,
+};
+
+use std::{
+	hash::{Hasher, RandomState},
+	io::{self, Read, Write},
+	sync::Arc,
+};
+
+/// Leaves up to this size, compressed with [`Zstd`](CompressionAlgorithm::Zstd), are compressed against the archive's dictionary.
+/// See [`BuilderConfig::dictionary_size`](crate::builder::BuilderConfig::dictionary_size)
+#[cfg(feature = "compression")]
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+pub const SMALL_LEAF_SIZE: usize = 64 * 1024;
+
+/// Configures how a [`Leaf`] should be compressed.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg(feature = "compression")]
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+pub enum CompressMode {
+	/// The data is never compressed and is embedded as is.
+	#[default]
+	Never,
+	/// The data will always be compressed
+	Always,
+	/// The compressed data is used, only if it is smaller than the original data. See [`BuilderConfig::min_savings`](crate::builder::BuilderConfig::min_savings)
+	Detect,
+	/// Every algorithm in [`BuilderConfig::compression_candidates`](crate::builder::BuilderConfig::compression_candidates) is tried, along with the leaf's [`codec`](Leaf::codec).
+	/// The smallest output is used, as long as it's smaller than the original data, like [`Detect`](CompressMode::Detect)
+	Best,
+}
+
+/// A named ([`ID`](Leaf::id)) wrapper around an [`io::Read`](Read) handle, tagged with extra metadata.
+#[derive(Debug, Default, Clone)]
+pub struct Leaf<R = &'static [u8]> {
+	/// source data
+	pub handle: R,
+
+	/// The `ID` under which the embedded data will be referenced
+	pub id: Arc<str>,
+	/// The flags that will go into the archive write target.
+	pub flags: Flags,
+	/// Key/value attributes stored in the leaf's registry entry
+	pub metadata: Metadata,
+	/// The power of two this leaf's data should be aligned to, overriding [`BuilderConfig::alignment`](crate::builder::BuilderConfig::alignment). Aligned leaves are never grouped into solid blocks
+	pub alignment: Option<u64>,
+	/// The size of the leaf's data, if known up front. Lets [`solid_block_size`](crate::builder::BuilderConfig::solid_block_size) and [`dictionary_size`](crate::builder::BuilderConfig::dictionary_size)
+	/// skip large leaves without reading them, otherwise large solid candidates are processed on the calling thread once their size is found out
+	pub size_hint: Option<u64>,
+
+	/// How a [`Leaf`] should be compressed
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub compress: CompressMode,
+	/// The specific compression algorithm to use
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	#[cfg(feature = "compression")]
+	pub compression_algo: CompressionAlgorithm,
+	/// ID of a [`Codec`] registered in [`BuilderConfig::codecs`](crate::builder::BuilderConfig::codecs), used instead of [`compression_algo`](Leaf::compression_algo)
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	#[cfg(feature = "compression")]
+	pub codec: Option<u16>,
+
+	/// Use encryption when writing into the target.
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub encrypt: bool,
+	/// Whether to include a signature with this [`Leaf`]
+	#[cfg(feature = "crypto")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+	pub sign: bool,
+}
+
+impl<R: Read + Send + Sync> Leaf<R> {
+	/// Creates a new [`Leaf`] wrapping around the given [`Read`] handle, with an ID
+	pub fn new<S: AsRef<str>>(
+		handle: R,
+		id: S,
+	) -> Leaf<R> {
+		let default = Leaf::<&'static [u8]>::default();
+
+		Leaf {
+			handle,
+			id: Arc::from(id.as_ref()),
+
+			// copy from default implementation
+			flags: default.flags,
+			metadata: default.metadata,
+			alignment: default.alignment,
+			size_hint: default.size_hint,
+
+			#[cfg(feature = "compression")]
+			compress: default.compress,
+			#[cfg(feature = "compression")]
+			compression_algo: default.compression_algo,
+			#[cfg(feature = "compression")]
+			codec: default.codec,
+			#[cfg(feature = "crypto")]
+			encrypt: default.encrypt,
+			#[cfg(feature = "crypto")]
+			sign: default.sign,
+		}
+	}
+
+	/// Copy all fields from another [`Leaf`], except for `handle` and `id`.
+	pub fn template<R2>(
+		self,
+		other: &Leaf<R2>,
+	) -> Self {
+		Leaf {
+			handle: self.handle,
+			id: self.id,
+
+			flags: other.flags,
+			metadata: other.metadata.clone(),
+			alignment: other.alignment,
+			size_hint: self.size_hint,
+
+			#[cfg(feature = "compression")]
+			compress: other.compress,
+			#[cfg(feature = "compression")]
+			compression_algo: other.compression_algo,
+			#[cfg(feature = "compression")]
+			codec: other.codec,
+			#[cfg(feature = "crypto")]
+			encrypt: other.encrypt,
+			#[cfg(feature = "crypto")]
+			sign: other.sign,
+		}
+	}
+
+	/// Setter for the [`compress`](Leaf::compress) field
+	#[cfg(feature = "compression")]
+	#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+	pub fn compress(
+		mut self,
+		compress: CompressMode,
+	) -> Self {
+		self.compress = compress;
+		self
+	}
+
+	/// Setter for the [`flags`](crate::builder::Flags) field
+	pub fn flags(
+		mut self,
+		flags: Flags,
+	) -> Self {
+		self.flags = flags;
+		self
+	}
+
+	/// Setter for the [`metadata`](Leaf::metadata) field
+	pub fn metadata(
+		mut self,
+		metadata: Metadata,
+	) -> Self {
+		self.metadata = metadata;
+		self
+	}
+
+	/// Inserts a single attribute into the [`metadata`](Leaf::metadata) field
+	pub fn meta<K: Into<String>, V: Into<MetaValue>>(
+		mut self,
+		key: K,
+		value: V,
+	) -> Self {
+		self.metadata.insert(key, value);
+		self
+	}
+
+	/// Setter for the [`alignment`](Leaf::alignment) field, `1` disables alignment for this leaf
+	pub fn alignment(
+		mut self,
+		alignment: u64,
+	) -> Self {
+		self.alignment = Some(alignment);
+		self
+	}
+
+	/// Setter for the [`size_hint`](Leaf::size_hint) field
+	pub fn size_hint(
+		mut self,
+		size: u64,
+	) -> Self {
+		self.size_hint = Some(size);
+		self
+	}
+
+	/// Setter for the [`encrypt`](Leaf::encrypt) field
+	#[cfg(feature = "crypto")]
+	pub fn encrypt(
+		mut self,
+		encrypt: bool,
+	) -> Self {
+		self.encrypt = encrypt;
+		self
+	}
+
+	/// Setter for the [`sign`](Leaf::sign) field
+	#[cfg(feature = "crypto")]
+	pub fn sign(
+		mut self,
+		sign: bool,
+	) -> Self {
+		self.sign = sign;
+		self
+	}
+
+	/// Setter for the [`compression_algo`](Leaf::compression_algo) field
+	#[cfg(feature = "compression")]
+	pub fn compression_algo(
+		mut self,
+		compression_algo: CompressionAlgorithm,
+	) -> Self {
+		self.compression_algo = compression_algo;
+		self
+	}
+
+	/// Setter for the [`codec`](Leaf::codec) field
+	#[cfg(feature = "compression")]
+	pub fn codec(
+		mut self,
+		codec: u16,
+	) -> Self {
+		self.codec = Some(codec);
+		self
+	}
+
+	/// The ID of the codec stored in this leaf's registry entry, if it may be compressed with one
+	#[cfg(feature = "compression")]
+	fn codec_id(&self) -> Option<u16> {
+		self.codec.filter(|_| !matches!(self.compress, CompressMode::Never))
+	}
+
+	/// Whether this leaf would be compressed against a dictionary, if it turns out small enough
+	#[cfg(feature = "compression")]
+	fn uses_dictionary(
+		&self,
+		candidates: &[CompressionAlgorithm],
+	) -> bool {
+		let zstd = |algo: &CompressionAlgorithm| matches!(algo, CompressionAlgorithm::Zstd(_));
+
+		match self.compress {
+			CompressMode::Never => false,
+			CompressMode::Best => candidates.iter().any(zstd),
+			_ => self.codec_id().is_none() && zstd(&self.compression_algo),
+		}
+	}
+
+	/// Whether the [`size_hint`](Leaf::size_hint) rules out the leaf being small
+	#[cfg(feature = "compression")]
+	pub(crate) fn is_large(&self) -> bool {
+		self.size_hint.is_some_and(|size| size > SMALL_LEAF_SIZE as u64)
+	}
+
+	/// Whether this leaf may be grouped into a solid block, if it turns out small enough.
+	/// Signed and encrypted leaves aren't, nor are [`Best`](CompressMode::Best) leaves since each picks it's own algorithm
+	#[cfg(feature = "compression")]
+	pub(crate) fn is_solid_candidate(
+		&self,
+		sign: bool,
+		encrypt: bool,
+	) -> bool {
+		#[cfg(feature = "crypto")]
+		let secured = (sign && self.sign) || (encrypt && self.encrypt);
+		#[cfg(not(feature = "crypto"))]
+		let secured = sign || encrypt;
+
+		!secured && self.codec_id().is_none() && self.alignment.is_none() && !self.is_large() && !matches!(self.compress, CompressMode::Never | CompressMode::Best)
+	}
+
+	/// The alignment of this leaf's data, it's own or the archive's `default`. [`None`] if it needs no padding
+	pub(crate) fn data_alignment(
+		&self,
+		default: Option<u64>,
+	) -> Option<u64> {
+		self.alignment.or(default).filter(|alignment| *alignment > 1)
+	}
+
+	/// The number of bytes reserved for this leaf's registry entry. Solid candidates reserve space for a block range, which goes unused if they aren't small
+	pub(crate) fn calculate_entry_bytes(
+		&self,
+		sign: bool,
+		encrypt: bool,
+		solid: bool,
+		checksum: bool,
+		alignment: Option<u64>,
+	) -> usize {
+		#[cfg(feature = "crypto")]
+		let sig_len = if sign && self.sign { crate::SIGNATURE_LENGTH } else { 0 };
+		#[cfg(not(feature = "crypto"))]
+		let sig_len = {
+			let _ = (sign, encrypt);
+			0
+		};
+
+		#[cfg(feature = "crypto")]
+		let nonce_len = if encrypt && self.encrypt { crate::NONCE_LENGTH } else { 0 };
+		#[cfg(not(feature = "crypto"))]
+		let nonce_len = 0;
+
+		#[cfg(feature = "compression")]
+		let codec_len = if self.codec_id().is_some() { 2 } else { 0 };
+		#[cfg(not(feature = "compression"))]
+		let codec_len = 0;
+
+		#[cfg(feature = "compression")]
+		let block_len = if solid && self.is_solid_candidate(sign, encrypt) { 16 } else { 0 };
+		#[cfg(not(feature = "compression"))]
+		let block_len = {
+			let _ = solid;
+			0
+		};
+
+		let checksum_len = if checksum { 8 } else { 0 };
+		let alignment_len = if self.data_alignment(alignment).is_some() { 1 } else { 0 };
+
+		self.id.len() + RegistryEntry::CONSTANT + sig_len + nonce_len + codec_len + block_len + checksum_len + alignment_len + self.metadata.size()
+	}
+}
+
+impl<R> From<&mut Leaf<R>> for RegistryEntry {
+	fn from(leaf: &mut Leaf<R>) -> Self {
+		let mut entry = RegistryEntry {
+			id: leaf.id.clone(),
+			flags: leaf.flags,
+			metadata: leaf.metadata.clone(),
+			..RegistryEntry::empty()
+		};
+
+		entry.flags.force_set(Flags::METADATA_FLAG, !entry.metadata.is_empty());
+		entry
+	}
+}
+
+// Processed data ready to be inserted into a `Write + Clone` target during multithreaded Building
+pub(crate) struct ProcessedLeaf {
+	pub(crate) data: Spill,
+	pub(crate) entry: RegistryEntry,
+	pub(crate) fingerprint: Option<Fingerprint>,
+}
+
+/// The end of a leaf's processing pipeline, counts the bytes written into the target and hashes them for the signature
+struct LeafSink<'a> {
+	inner: &'a mut dyn Write,
+	bytes: u64,
+	#[cfg(feature = "crypto")]
+	hasher: Option<Sha512>,
+	checksum: Option<XxHash64>,
+}
+
+impl Write for LeafSink<'_> {
+	fn write(
+		&mut self,
+		buf: &[u8],
+	) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+
+		#[cfg(feature = "crypto")]
+		if let Some(hasher) = self.hasher.as_mut() {
+			hasher.update(&buf[..written]);
+		}
+
+		if let Some(checksum) = self.checksum.as_mut() {
+			checksum.write(&buf[..written]);
+		}
+
+		self.bytes += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// Encrypts data in chunks on it's way into the [`LeafSink`], if the leaf is encrypted
+struct LeafWriter<'a> {
+	sink: LeafSink<'a>,
+	#[cfg(feature = "crypto")]
+	encryptor: Option<ChunkEncryptor<'a>>,
+}
+
+impl Write for LeafWriter<'_> {
+	fn write(
+		&mut self,
+		buf: &[u8],
+	) -> io::Result<usize> {
+		#[cfg(feature = "crypto")]
+		if let Some(encryptor) = self.encryptor.as_mut() {
+			encryptor.update(buf, &mut self.sink)?;
+			return Ok(buf.len());
+		}
+
+		self.sink.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.sink.flush()
+	}
+}
+
+/// Reads up to one byte past [`SMALL_LEAF_SIZE`] from the start of a leaf, enough to tell whether it's small
+#[cfg(feature = "compression")]
+pub(crate) fn read_head<R: Read>(handle: &mut R) -> io::Result<Vec<u8>> {
+	let mut head = Vec::new();
+	handle.take(SMALL_LEAF_SIZE as u64 + 1).read_to_end(&mut head)?;
+	Ok(head)
+}
+
+/// Trains a dictionary of at most `max_size` bytes from the small leaves, reading the heads of leaves that could use it.
+/// Sampling stops once about a hundred times `max_size` bytes were read, the heads read are returned to be processed with the rest of the leaf
+#[cfg(feature = "compression")]
+pub(crate) fn sample_leaves<R: Read + Send + Sync>(
+	leaves: &mut [Leaf<R>],
+	max_size: usize,
+	candidates: &[CompressionAlgorithm],
+) -> InternalResult<(Option<Vec<u8>>, Vec<Option<Vec<u8>>>)> {
+	let budget = max_size.saturating_mul(100);
+	let mut sampled = 0;
+
+	let mut heads = Vec::with_capacity(leaves.len());
+	for leaf in leaves.iter_mut() {
+		if sampled >= budget || !leaf.uses_dictionary(candidates) || leaf.is_large() {
+			heads.push(None);
+			continue;
+		}
+
+		// the heads of large leaves aren't sampled, but are held until they're processed so they count towards the budget too
+		let head = read_head(&mut leaf.handle)?;
+		sampled += head.len();
+
+		heads.push(Some(head));
+	}
+
+	let samples = heads.iter().flatten().filter(|head| head.len() <= SMALL_LEAF_SIZE).map(Vec::as_slice).collect::<Vec<_>>();
+	let dictionary = crate::global::compressor::train_dictionary(&samples, max_size);
+
+	Ok((dictionary, heads))
+}
+
+/// A way to compress a leaf, either a built-in algorithm or a user-defined codec
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy)]
+enum Candidate<'a> {
+	Algorithm(CompressionAlgorithm),
+	Codec(&'a dyn Codec),
+}
+
+/// Compresses a leaf's data with a candidate, Zstd compresses against the dictionary if one is given
+#[cfg(feature = "compression")]
+fn compress<T: Read>(
+	mut data: T,
+	candidate: Candidate,
+	dictionary: Option<&[u8]>,
+	output: &mut dyn Write,
+) -> InternalResult {
+	match (candidate, dictionary) {
+		(Candidate::Codec(codec), _) => codec.compress(&mut data, output).map_err(InternalError::IOError),
+		(Candidate::Algorithm(CompressionAlgorithm::Zstd(level)), Some(dictionary)) => Compressor::new(data).compress_with_dictionary(level, dictionary, output),
+		(Candidate::Algorithm(algo), _) => Compressor::new(data).compress(algo, output),
+	}
+}
+
+/// Compresses a leaf's data with every candidate, returning the smallest output and the candidate that produced it.
+/// The raw data is returned instead, if no candidate shrinks it by at least `min_savings` of it's size
+#[cfg(feature = "compression")]
+fn compress_best<'a, T: Read>(
+	mut data: T,
+	candidates: &[Candidate<'a>],
+	dictionary: Option<&[u8]>,
+	min_savings: Option<f32>,
+) -> InternalResult<(Spill, Option<Candidate<'a>>)> {
+	let mut raw = Spill::default();
+	let mut best: Option<(Spill, Candidate)> = None;
+
+	for (idx, candidate) in candidates.iter().enumerate() {
+		let mut compressed = Spill::default();
+
+		// the first candidate copies the data into a spill as it reads it, the rest read it back from there
+		match idx {
+			0 => compress(TeeReader { inner: &mut data, copy: &mut raw }, *candidate, dictionary, &mut compressed)?,
+			_ => compress(raw.reader()?, *candidate, dictionary, &mut compressed)?,
+		};
+
+		if best.as_ref().is_none_or(|(smallest, _)| compressed.len() < smallest.len()) {
+			best = Some((compressed, *candidate));
+		}
+	}
+
+	if candidates.is_empty() {
+		io::copy(&mut data, &mut raw)?;
+	}
+
+	let limit = raw.len() as f64 * (1.0 - min_savings.unwrap_or(0.0) as f64);
+	match best {
+		Some((compressed, candidate)) if compressed.len() as f64 <= limit => Ok((compressed, Some(candidate))),
+		_ => Ok((raw, None)),
+	}
+}
+
+/// Streams a [`Leaf`]'s data into `output`: compressing it, encrypting it and hashing it for it's signature along the way.
+/// Memory usage is bounded regardless of the leaf's size, [`CompressMode::Detect`] and [`CompressMode::Best`] keep their candidates in [`Spill`]s.
+/// `head` holds the start of the leaf's data if it was already read while training the `dictionary`
+#[inline(never)]
+pub(crate) fn process_leaf<R: Read + Send + Sync>(
+	leaf: &mut Leaf<R>,
+	config: &super::BuilderConfig,
+	_encryptor: Option<&Encryptor>,
+	_dictionary: Option<&[u8]>,
+	head: Option<Vec<u8>>,
+	output: &mut dyn Write,
+) -> InternalResult<RegistryEntry> {
+	let mut entry: RegistryEntry = leaf.into();
+
+	// the data is padded by the caller, the entry only records the alignment
+	entry.alignment = leaf.data_alignment(config.alignment);
+	entry.flags.force_set(Flags::ALIGNED_FLAG, entry.alignment.is_some());
+
+	// leaves compressed with a codec carry it's ID
+	#[cfg(feature = "compression")]
+	let codec = match leaf.codec_id() {
+		Some(id) => {
+			entry.codec = Some(id);
+			entry.flags.force_set(Flags::CODEC_FLAG, true);
+			Some(config.codecs.get(id).ok_or(InternalError::UnknownCodecError(id))?)
+		},
+		None => None,
+	};
+
+	// small leaves are compressed against the dictionary, their heads are read to find out if they are small
+	#[cfg(feature = "compression")]
+	let head = match head {
+		None if _dictionary.is_some() && leaf.uses_dictionary(&config.compression_candidates) => Some(read_head(&mut leaf.handle)?),
+		head => head,
+	};
+
+	// heads no larger than SMALL_LEAF_SIZE hold all of the leaf's data, so the handle isn't read past it's end
+	#[cfg(feature = "compression")]
+	let remaining = match head.as_ref() {
+		Some(head) if head.len() <= SMALL_LEAF_SIZE => 0,
+		_ => u64::MAX,
+	};
+	#[cfg(not(feature = "compression"))]
+	let remaining = u64::MAX;
+
+	#[cfg(feature = "compression")]
+	let dictionary = _dictionary.filter(|_| leaf.uses_dictionary(&config.compression_candidates) && remaining == 0);
+
+	let mut source = io::Cursor::new(head.unwrap_or_default()).chain((&mut leaf.handle).take(remaining));
+
+	// the leaf's own codec or algorithm, used by Always and Detect modes
+	#[cfg(feature = "compression")]
+	let own = match codec {
+		Some(codec) => Candidate::Codec(codec),
+		None => Candidate::Algorithm(leaf.compression_algo),
+	};
+
+	// Detect and Best modes compress into spills first, to find out if compression is worth it. Flags must be known before encryption
+	#[cfg(feature = "compression")]
+	let detected = match leaf.compress {
+		CompressMode::Detect => Some(compress_best(&mut source, &[own], dictionary, config.min_savings)?),
+		CompressMode::Best => {
+			let algorithms = config.compression_candidates.iter().map(|algo| Candidate::Algorithm(*algo));
+			let candidates = algorithms.chain(codec.map(Candidate::Codec)).collect::<Vec<_>>();
+
+			Some(compress_best(&mut source, &candidates, dictionary, config.min_savings)?)
+		},
+		_ => None,
+	};
+
+	#[cfg(feature = "compression")]
+	let chosen = match (&detected, leaf.compress) {
+		(Some((_, candidate)), _) => *candidate,
+		(None, CompressMode::Always) => Some(own),
+		(None, _) => None,
+	};
+
+	#[cfg(feature = "compression")]
+	if let Some(candidate) = chosen {
+		entry.flags.force_set(Flags::COMPRESSED_FLAG, true);
+
+		// codecs are identified by the ID in the entry
+		if let Candidate::Algorithm(algo) = candidate {
+			entry.flags.force_set(algo.into(), true);
+			entry.flags.force_set(Flags::DICTIONARY_COMPRESSED, dictionary.is_some() && matches!(algo, CompressionAlgorithm::Zstd(_)));
+		}
+	}
+
+	// If the compression feature is turned off, data is copied as is
+	#[cfg(not(feature = "compression"))]
+	if entry.flags.contains(Flags::COMPRESSED_FLAG) {
+		return Err(InternalError::MissingFeatureError("compression"));
+	};
+
+	let mut writer = LeafWriter {
+		sink: LeafSink {
+			inner: output,
+			bytes: 0,
+			#[cfg(feature = "crypto")]
+			hasher: None,
+			checksum: None,
+		},
+		#[cfg(feature = "crypto")]
+		encryptor: None,
+	};
+
+	// Encryption comes second
+	#[cfg(feature = "crypto")]
+	if let Some(ex) = _encryptor
+		&& leaf.encrypt
+	{
+		entry.flags.force_set(Flags::ENCRYPTED_FLAG, true);
+		writer.encryptor = Some(ChunkEncryptor::new(ex, entry.associated_data()));
+	}
+
+	// Sign final data as-is in binary
+	#[cfg(feature = "crypto")]
+	if config.signing_key.is_some() && leaf.sign {
+		entry.flags.force_set(Flags::SIGNED_FLAG, true);
+		writer.sink.hasher = Some(Sha512::new());
+	};
+
+	// Checksum final data as-is, catching corruption without the crypto feature
+	if config.checksums {
+		entry.flags.force_set(Flags::CHECKSUM_FLAG, true);
+		writer.sink.checksum = Some(XxHash64::with_seed(0));
+	}
+
+	#[cfg(feature = "compression")]
+	match (detected, chosen) {
+		(Some((spill, _)), _) => io::copy(&mut spill.into_reader()?, &mut writer).map(|_| ())?,
+		(None, Some(candidate)) => compress(&mut source, candidate, dictionary, &mut writer)?,
+		(None, None) => io::copy(&mut source, &mut writer).map(|_| ())?,
+	};
+
+	#[cfg(not(feature = "compression"))]
+	io::copy(&mut source, &mut writer)?;
+
+	#[cfg(feature = "crypto")]
+	if let Some(encryptor) = writer.encryptor.take() {
+		entry.nonce = Some(encryptor.finish(&mut writer.sink)?);
+	}
+
+	entry.offset = writer.sink.bytes;
+	entry.checksum = writer.sink.checksum.as_ref().map(Hasher::finish);
+
+	// Include entry id in the signature
+	#[cfg(feature = "crypto")]
+	if let (Some(keypair), Some(mut hasher)) = (config.signing_key.as_ref(), writer.sink.hasher) {
+		hasher.update(entry.id.as_bytes());
+		let signature = keypair.sign_prehashed(hasher, Some(crypto::SIGNATURE_CONTEXT)).map_err(|err| InternalError::OtherError(err.into()))?;
+		entry.signature = Some(signature);
+	};
+
+	#[cfg(not(feature = "crypto"))]
+	let _ = config;
+
+	Ok(entry)
+}
+
+/// Processes a [`Leaf`] into a [`Spill`], to be written into the target later. Fingerprints the processed data if deduplication `keys` are given
+pub(crate) fn process_into_spill<R: Read + Send + Sync>(
+	leaf: &mut Leaf<R>,
+	config: &super::BuilderConfig,
+	encryptor: Option<&Encryptor>,
+	dictionary: Option<&[u8]>,
+	head: Option<Vec<u8>>,
+	keys: Option<&[RandomState; 2]>,
+) -> InternalResult<ProcessedLeaf> {
+	let mut data = Spill::default();
+
+	let (entry, fingerprint) = match keys {
+		Some(keys) => {
+			let mut output = Fingerprinter::new(&mut data, keys);
+			let entry = process_leaf(leaf, config, encryptor, dictionary, head, &mut output)?;
+			(entry, Some(output.finish()))
+		},
+		None => (process_leaf(leaf, config, encryptor, dictionary, head, &mut data)?, None),
+	};
+
+	Ok(ProcessedLeaf { data, entry, fingerprint })
+}
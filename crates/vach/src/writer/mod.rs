@@ -1,205 +1,690 @@
-use std::io::{Read, Seek, SeekFrom, Write};
-
-mod config;
-mod leaf;
-
-pub use config::BuilderConfig;
-pub use leaf::Leaf;
-
-#[cfg(feature = "compression")]
-pub use {crate::global::compressor::Compressor, leaf::CompressMode};
-
-use crate::global::error::*;
-use crate::global::{header::Header, reg_entry::RegistryEntry};
-
-#[cfg(feature = "crypto")]
-use crate::{crypto::Encryptor, global::flags::Flags};
-
-#[cfg(not(feature = "crypto"))]
-type Encryptor = ();
-
-/// Counts bytes written to the target
-struct WriteCounter<W: Send> {
-	bytes: u64,
-	inner: W,
-}
-
-impl<W: Write + Send> Write for WriteCounter<W> {
-	fn write(
-		&mut self,
-		buf: &[u8],
-	) -> std::io::Result<usize> {
-		let len = self.inner.write(buf)?;
-		self.bytes += len as u64;
-		Ok(len)
-	}
-
-	fn flush(&mut self) -> std::io::Result<()> {
-		self.inner.flush()
-	}
-}
-
-impl<W: Seek + Send> Seek for WriteCounter<W> {
-	fn seek(
-		&mut self,
-		pos: SeekFrom,
-	) -> std::io::Result<u64> {
-		self.inner.seek(pos)
-	}
-}
-
-/// iterates over all [`Leaf`], processes them and writes the output into the target. returns bytes written to `target`
-pub fn dump<W, R>(
-	target: W,
-	leaves: &mut [Leaf<R>],
-	config: Option<BuilderConfig>,
-	mut callback: Option<&mut dyn FnMut(&RegistryEntry, &[u8])>,
-) -> InternalResult<u64>
-where
-	W: Write + Seek + Send,
-	R: Read + Sync + Send,
-{
-	let mut config = config.unwrap_or_default();
-	let mut target = WriteCounter { bytes: 0, inner: target };
-
-	// find duplicates
-	let mut set = std::collections::HashSet::with_capacity(leaves.len());
-	for id in leaves.iter().map(|l| l.id.as_ref()) {
-		if !set.insert(id) {
-			return Err(InternalError::DuplicateLeafID(id.to_string()));
-		}
-	}
-
-	// Determines the offset at which to start writing leafs
-	let mut leaf_offset = {
-		Header::BASE_SIZE + {
-			let sign = config.signing_key.is_some();
-			leaves.iter().map(|leaf| leaf.calculate_entry_bytes(sign)).sum::<usize>()
-		}
-	} as u64;
-
-	#[cfg(feature = "crypto")]
-	if config.signing_key.is_some() {
-		config.flags.force_set(Flags::SIGNED_FLAG, true);
-	};
-
-	// write HEADER
-	let header = crate::global::header::Header {
-		magic: crate::MAGIC,
-		flags: config.flags,
-		version: crate::VERSION,
-		capacity: leaves.len() as u16,
-	};
-
-	target.seek(SeekFrom::Start(0))?;
-	target.write_all(&header.to_bytes())?;
-
-	// Build encryptor
-	#[cfg(feature = "crypto")]
-	let encryptor = {
-		let use_encryption = leaves.iter().any(|leaf| leaf.encrypt);
-		if use_encryption {
-			if let Some(keypair) = config.signing_key.as_ref() {
-				Some(Encryptor::new(&keypair.verifying_key()))
-			} else {
-				return Err(InternalError::NoKeypairError);
-			}
-		} else {
-			None
-		}
-	};
-
-	#[cfg(not(feature = "crypto"))]
-	let encryptor = None;
-
-	// buffer registry data
-	let mut registry = Vec::with_capacity(leaf_offset as usize - Header::BASE_SIZE);
-	target.seek(SeekFrom::Start(leaf_offset))?;
-
-	#[allow(unused_mut)]
-	// Callback for processing IO
-	let mut write = |result: InternalResult<leaf::ProcessedLeaf>| -> InternalResult<()> {
-		let mut processed = result?;
-		let bytes = processed.data.len() as u64;
-
-		// write LEAF
-		target.write_all(&processed.data)?;
-
-		// update registry entry
-		processed.entry.location = leaf_offset;
-		processed.entry.offset = bytes;
-
-		// increment leaf offset
-		leaf_offset += processed.data.len() as u64;
-
-		// write to registry buffer, this one might include the Signature
-		let entry_bytes = processed.entry.to_bytes()?;
-		registry.write_all(&entry_bytes)?;
-
-		// Call the progress callback bound within the [`BuilderConfig`]
-		if let Some(callback) = callback.as_mut() {
-			callback(&processed.entry, &processed.data);
-		}
-
-		Ok(())
-	};
-
-	if config.num_threads > 1 {
-		use std::{sync::mpsc, thread};
-
-		let (tx, rx) = mpsc::sync_channel(leaves.len());
-		thread::scope(|s| -> InternalResult<()> {
-			let count = leaves.len();
-
-			#[rustfmt::skip]
-			// if we have an insane number of threads send leafs in chunks of 8
-			let chunk_size = if config.num_threads > count { 8 } else { count / config.num_threads.max(1) };
-
-			let chunks = leaves.chunks_mut(chunk_size);
-			let encryptor = encryptor.as_ref();
-
-			// Spawn CPU threads
-			for chunk in chunks {
-				let queue = tx.clone();
-				let _config = &config;
-
-				s.spawn(move || {
-					for leaf in chunk {
-						let res = leaf::process_leaf(leaf, _config, encryptor);
-						queue.send(res).unwrap();
-					}
-				});
-			}
-
-			// Process IO, read results from
-			let mut results = 0;
-
-			loop {
-				match rx.try_recv() {
-					Ok(r) => {
-						results += 1;
-						write(r)?
-					},
-					Err(e) => match e {
-						mpsc::TryRecvError::Empty => {
-							if results >= count {
-								break Ok(());
-							}
-						},
-						mpsc::TryRecvError::Disconnected => break Ok(()),
-					},
-				}
-			}
-		})?;
-	} else {
-		// processed all on the main thread baby!
-		leaves.iter_mut().map(|l| leaf::process_leaf(l, &config, encryptor.as_ref())).try_for_each(write)?;
-	};
-
-	// write UPDATED REGISTRY
-	target.seek(SeekFrom::Start(Header::BASE_SIZE as _))?;
-	target.write_all(&registry)?;
-
-	target.flush()?;
-	Ok(target.bytes)
-}
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+mod config;
+pub(crate) mod dedup;
+mod leaf;
+#[cfg(feature = "compression")]
+mod solid;
+pub(crate) mod spill;
+
+pub use config::BuilderConfig;
+pub use leaf::Leaf;
+
+#[cfg(feature = "compression")]
+pub use {
+	crate::global::compressor::Compressor,
+	leaf::{CompressMode, SMALL_LEAF_SIZE},
+};
+
+use crate::global::error::*;
+use crate::global::{header::Header, reg_entry::RegistryEntry};
+use dedup::Deduplicator;
+
+#[cfg(not(feature = "crypto"))]
+use leaf::Encryptor;
+
+use crate::global::{extension::HeaderExtension, flags::Flags};
+
+#[cfg(feature = "crypto")]
+use crate::crypto::{self, Encryptor};
+
+/// Counts bytes written to the target, and tracks it's position
+struct WriteCounter<W: Send> {
+	bytes: u64,
+	position: u64,
+	inner: W,
+}
+
+impl<W: Write + Send> WriteCounter<W> {
+	/// Writes zeroes until the position is a multiple of `alignment`, returning the new position
+	fn pad(
+		&mut self,
+		alignment: Option<u64>,
+	) -> io::Result<u64> {
+		if let Some(alignment) = alignment {
+			let padding = self.position.next_multiple_of(alignment) - self.position;
+			io::copy(&mut io::repeat(0).take(padding), self)?;
+		}
+
+		Ok(self.position)
+	}
+}
+
+impl<W: Write + Send> Write for WriteCounter<W> {
+	fn write(
+		&mut self,
+		buf: &[u8],
+	) -> std::io::Result<usize> {
+		let len = self.inner.write(buf)?;
+		self.bytes += len as u64;
+		self.position += len as u64;
+		Ok(len)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+impl<W: Seek + Send> Seek for WriteCounter<W> {
+	fn seek(
+		&mut self,
+		pos: SeekFrom,
+	) -> std::io::Result<u64> {
+		self.position = self.inner.seek(pos)?;
+		Ok(self.position)
+	}
+}
+
+/// Returned by [`dump`], describes what was written into the target
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DumpStats {
+	/// Bytes written to the target
+	pub bytes_written: u64,
+	/// Bytes of leaf data not written, since identical data was already in the archive. See [`BuilderConfig::deduplicate`]
+	pub bytes_deduplicated: u64,
+}
+
+/// iterates over all [`Leaf`], processes them and writes the output into the target. returns the number of bytes written to `target`, and saved through deduplication
+pub fn dump<W, R>(
+	target: W,
+	leaves: &mut [Leaf<R>],
+	config: Option<BuilderConfig>,
+	mut callback: Option<&mut dyn FnMut(&RegistryEntry)>,
+) -> InternalResult<DumpStats>
+where
+	W: Write + Seek + Send,
+	R: Read + Sync + Send,
+{
+	let mut config = config.unwrap_or_default();
+	let mut target = WriteCounter {
+		bytes: 0,
+		position: 0,
+		inner: target,
+	};
+
+	validate_leaves(leaves, &[], &config)?;
+
+	#[allow(unused_mut)]
+	let mut extensions = Vec::new();
+
+	// derive the encryption key from a passphrase, storing the KDF's salt and parameters in the header
+	#[cfg(feature = "crypto")]
+	let encryption_key = match config.passphrase.as_ref() {
+		Some(passphrase) => {
+			let salt = crypto::gen_salt();
+			let key = config.kdf_params.derive_key(passphrase.as_bytes(), &salt)?;
+
+			extensions.push(HeaderExtension::Passphrase { params: config.kdf_params, salt });
+			Some(key)
+		},
+		None => config.encryption_key,
+	};
+
+	// archives with recipients get a random content key if none was given, stored wrapped for each recipient after the leaves
+	#[cfg(feature = "crypto")]
+	let encryption_key = match config.recipients.is_empty() {
+		true => encryption_key,
+		false => {
+			extensions.push(HeaderExtension::Recipients { offset: 0 });
+			Some(encryption_key.unwrap_or_else(crate::crypto_utils::gen_encryption_key))
+		},
+	};
+
+	// train a dictionary for small leaves, stored in the header. Leaves read while sampling are processed from their heads
+	#[cfg(feature = "compression")]
+	let (dictionary, mut heads) = match config.dictionary_size {
+		Some(max_size) => leaf::sample_leaves(leaves, max_size, &config.compression_candidates)?,
+		None => (None, vec![None; leaves.len()]),
+	};
+
+	#[cfg(not(feature = "compression"))]
+	let (dictionary, mut heads): (Option<Vec<u8>>, Vec<Option<Vec<u8>>>) = (None, vec![None; leaves.len()]);
+
+	#[cfg(feature = "compression")]
+	if let Some(dictionary) = dictionary.as_ref() {
+		extensions.push(HeaderExtension::Dictionary(dictionary.clone()));
+	}
+
+	#[cfg(feature = "crypto")]
+	let (sign, encrypt) = (config.signing_key.is_some(), encryption_key.is_some());
+	#[cfg(not(feature = "crypto"))]
+	let (sign, encrypt) = (false, false);
+
+	#[cfg(feature = "crypto")]
+	if config.signing_key.is_some() {
+		config.flags.force_set(Flags::SIGNED_FLAG, true);
+	};
+
+	// build HEADER, refusing to truncate the number of entries
+	let capacity = u32::try_from(leaves.len()).map_err(|_| InternalError::CapacityOverflowError(leaves.len()))?;
+	#[allow(unused_mut)]
+	let mut header = Header {
+		magic: crate::MAGIC,
+		flags: config.flags,
+		version: crate::VERSION,
+		capacity,
+		extensions,
+		#[cfg(feature = "crypto")]
+		signature: None,
+	};
+
+	// the HEADER is written last, once the location of the recipients section is known.
+	// signed archives carry a signature of the header and registry, right after the header
+	let registry_offset = header.encoded_size() + if sign { crate::SIGNATURE_LENGTH } else { 0 };
+
+	#[cfg(feature = "compression")]
+	let solid = config.solid_block_size.is_some();
+	#[cfg(not(feature = "compression"))]
+	let solid = false;
+
+	// Determines the offset at which to start writing leafs
+	let leaf_offset = { registry_offset + leaves.iter().map(|leaf| leaf.calculate_entry_bytes(sign, encrypt, solid, config.checksums, config.alignment)).sum::<usize>() } as u64;
+
+	// Build encryptor
+	#[cfg(feature = "crypto")]
+	let encryptor = build_encryptor(leaves, encryption_key.as_ref())?;
+
+	#[cfg(not(feature = "crypto"))]
+	let encryptor = None;
+
+	target.seek(SeekFrom::Start(leaf_offset))?;
+	let (registry, bytes_deduplicated) = write_leaves(&mut target, leaves, heads, &config, encryptor.as_ref(), dictionary.as_deref(), callback)?;
+
+	// write the RECIPIENTS section after the leaves, and point to it from the header
+	#[cfg(feature = "crypto")]
+	if let Some(key) = encryption_key.as_ref().filter(|_| !config.recipients.is_empty()) {
+		let wrapped = config.recipients.iter().map(|r| crypto::WrappedKey::wrap(key, r)).collect::<InternalResult<Vec<_>>>()?;
+		let section_offset = target.position;
+		target.write_all(&crypto::WrappedKey::section_bytes(&wrapped))?;
+
+		for extension in header.extensions.iter_mut() {
+			if let HeaderExtension::Recipients { offset } = extension {
+				*offset = section_offset;
+			}
+		}
+	}
+
+	// write UPDATED HEADER and REGISTRY, the registry preceded by the signature over the header and registry
+	let header_bytes = header.to_bytes();
+	target.seek(SeekFrom::Start(0))?;
+	target.write_all(&header_bytes)?;
+
+	#[cfg(feature = "crypto")]
+	if let Some(keypair) = config.signing_key.as_ref() {
+		let manifest = [header_bytes.as_slice(), registry.as_slice()].concat();
+		target.write_all(&ed25519_dalek::Signer::sign(keypair, &manifest).to_bytes())?;
+	}
+
+	target.write_all(&registry)?;
+
+	target.flush()?;
+	Ok(DumpStats {
+		bytes_written: target.bytes,
+		bytes_deduplicated,
+	})
+}
+
+/// Oldest spec version that can be appended to, earlier archives encrypt or sign their entries differently than new leaves would be
+const APPENDABLE_VERSION: u16 = 120;
+
+/// Appends leaves to an archive without rewriting it. The leaves are written at the end of `target`, followed by a new registry holding both the old and new entries.
+/// The header is then updated in place to point at the new registry, leaving the previous one unreferenced.
+/// Signed archives must be appended to using their [`signing_key`](BuilderConfig::signing_key), and encrypted leaves use the archive's [`passphrase`](BuilderConfig::passphrase)
+/// or [`encryption_key`](BuilderConfig::encryption_key), which is checked against an existing encrypted entry. The archive's dictionary and flags are kept, so [`dictionary_size`](BuilderConfig::dictionary_size) and [`flags`](BuilderConfig::flags) are ignored
+pub fn append<W, R>(
+	target: W,
+	leaves: &mut [Leaf<R>],
+	config: Option<BuilderConfig>,
+	callback: Option<&mut dyn FnMut(&RegistryEntry)>,
+) -> InternalResult<DumpStats>
+where
+	W: Read + Write + Seek + Send,
+	R: Read + Sync + Send,
+{
+	let config = config.unwrap_or_default();
+	let mut target = WriteCounter {
+		bytes: 0,
+		position: 0,
+		inner: target,
+	};
+
+	// read the current HEADER and REGISTRY
+	let (mut header, entries) = {
+		let mut reader = io::BufReader::new(&mut target.inner);
+		reader.seek(SeekFrom::Start(0))?;
+		let header = Header::from_handle(&mut reader)?;
+
+		if header.version < APPENDABLE_VERSION {
+			return Err(InternalError::IncompatibleArchiveVersionError(header.version));
+		}
+
+		if let Some(offset) = header.registry_offset() {
+			reader.seek(SeekFrom::Start(offset))?;
+		}
+
+		#[cfg(feature = "crypto")]
+		let registry_start = reader.stream_position()?;
+
+		let entries = (0..header.capacity).map(|_| RegistryEntry::from_handle(&mut reader)).collect::<InternalResult<Vec<_>>>()?;
+
+		// signed archives must be appended to using the key they were signed with, else they'd be re-signed under another
+		#[cfg(feature = "crypto")]
+		if let Some(keypair) = config.signing_key.as_ref().filter(|_| header.has_manifest()) {
+			let signature = header.signature.ok_or(InternalError::ManifestVerificationError)?;
+			let registry_end = reader.stream_position()?;
+
+			let header_size = header.encoded_size();
+			let mut manifest = vec![0u8; header_size + (registry_end - registry_start) as usize];
+			let (header_bytes, registry) = manifest.split_at_mut(header_size);
+
+			reader.seek(SeekFrom::Start(0))?;
+			reader.read_exact(header_bytes)?;
+			reader.seek(SeekFrom::Start(registry_start))?;
+			reader.read_exact(registry)?;
+
+			keypair.verifying_key().verify_strict(&manifest, &signature).map_err(|_| InternalError::ManifestVerificationError)?;
+		}
+
+		(header, entries)
+	};
+
+	validate_leaves(leaves, &entries, &config)?;
+
+	// the manifest covers the whole registry, so signed archives have to be re-signed
+	#[cfg(feature = "crypto")]
+	let sign = config.signing_key.is_some();
+	#[cfg(not(feature = "crypto"))]
+	let sign = false;
+
+	if header.flags.contains(Flags::SIGNED_FLAG) && !sign {
+		return Err(InternalError::NoKeypairError);
+	}
+
+	header.flags.force_set(Flags::SIGNED_FLAG, sign);
+
+	// the encryption key is derived using the archive's KDF parameters, recipients can only be changed using `rewrite_recipients`
+	#[cfg(feature = "crypto")]
+	let encryption_key = {
+		if !config.recipients.is_empty() {
+			return Err(InternalError::OtherError("Recipients can't be added while appending, use rewrite_recipients instead".into()));
+		}
+
+		let kdf = header.extensions.iter().find_map(|extension| match extension {
+			HeaderExtension::Passphrase { params, salt } => Some((params, salt)),
+			_ => None,
+		});
+
+		match (config.passphrase.as_ref(), kdf) {
+			(Some(passphrase), Some((params, salt))) => Some(params.derive_key(passphrase.as_bytes(), salt)?),
+			(Some(_), None) => return Err(InternalError::OtherError("Archive wasn't built with a passphrase".into())),
+			(None, _) => config.encryption_key,
+		}
+	};
+
+	#[cfg(feature = "crypto")]
+	let encryptor = build_encryptor(leaves, encryption_key.as_ref())?;
+	#[cfg(feature = "crypto")]
+	if let Some(encryptor) = encryptor.as_ref() {
+		check_encryption_key(&mut target.inner, &entries, encryptor)?;
+	}
+	#[cfg(not(feature = "crypto"))]
+	let encryptor = None;
+
+	// new leaves are compressed against the archive's existing dictionary
+	#[cfg(feature = "compression")]
+	let dictionary = header.extensions.iter().find_map(|extension| match extension {
+		HeaderExtension::Dictionary(dictionary) => Some(dictionary.clone()),
+		_ => None,
+	});
+	#[cfg(not(feature = "compression"))]
+	let dictionary: Option<Vec<u8>> = None;
+
+	// build the new HEADER, pointing at a registry written after the new leaves
+	let added = u32::try_from(leaves.len()).ok().and_then(|added| header.capacity.checked_add(added));
+	header.capacity = added.ok_or(InternalError::CapacityOverflowError(entries.len() + leaves.len()))?;
+	header.version = crate::VERSION;
+	header.extensions.retain(|extension| !matches!(extension, HeaderExtension::Registry { .. }));
+	header.extensions.push(HeaderExtension::Registry { offset: 0 });
+
+	let header_size = (header.encoded_size() + if sign { crate::SIGNATURE_LENGTH } else { 0 }) as u64;
+	let end = target.seek(SeekFrom::End(0))?;
+
+	// the header may grow into the old registry, but never into data still in use
+	let recipients = header.extensions.iter().filter_map(|extension| match extension {
+		HeaderExtension::Recipients { offset } => Some(*offset),
+		_ => None,
+	});
+
+	if let Some(used) = entries.iter().map(|entry| entry.location).chain(recipients).min()
+		&& header_size > used
+	{
+		return Err(InternalError::OtherError(
+			format!("The updated header takes up {header_size} bytes, overlapping data at {used}. Rebuild the archive using dump instead").into(),
+		));
+	}
+
+	// write the LEAVES, then the REGISTRY with the old entries first
+	target.seek(SeekFrom::Start(end.max(header_size)))?;
+	let (added, bytes_deduplicated) = write_leaves(&mut target, leaves, vec![None; leaves.len()], &config, encryptor.as_ref(), dictionary.as_deref(), callback)?;
+
+	let mut registry = Vec::new();
+	for entry in &entries {
+		registry.extend_from_slice(&entry.to_bytes()?);
+	}
+	registry.extend_from_slice(&added);
+
+	let registry_offset = target.position;
+	target.write_all(&registry)?;
+
+	for extension in header.extensions.iter_mut() {
+		if let HeaderExtension::Registry { offset } = extension {
+			*offset = registry_offset;
+		}
+	}
+
+	// the registry is in place before the header points to it, so the archive stays readable until the HEADER is rewritten
+	target.flush()?;
+
+	let header_bytes = header.to_bytes();
+	target.seek(SeekFrom::Start(0))?;
+	target.write_all(&header_bytes)?;
+
+	#[cfg(feature = "crypto")]
+	if let Some(keypair) = config.signing_key.as_ref() {
+		let manifest = [header_bytes.as_slice(), registry.as_slice()].concat();
+		target.write_all(&ed25519_dalek::Signer::sign(keypair, &manifest).to_bytes())?;
+	}
+
+	target.flush()?;
+	Ok(DumpStats {
+		bytes_written: target.bytes,
+		bytes_deduplicated,
+	})
+}
+
+/// Rejects duplicate IDs, including those of `existing` entries, and alignments that aren't powers of two
+fn validate_leaves<R>(
+	leaves: &[Leaf<R>],
+	existing: &[RegistryEntry],
+	config: &BuilderConfig,
+) -> InternalResult {
+	// find duplicates
+	let mut set = existing.iter().map(|entry| entry.id.as_ref()).collect::<std::collections::HashSet<_>>();
+	for id in leaves.iter().map(|l| l.id.as_ref()) {
+		if !set.insert(id) {
+			return Err(InternalError::DuplicateLeafID(id.to_string()));
+		}
+	}
+
+	// alignments are stored as their base 2 logarithm
+	if let Some(alignment) = config.alignment.into_iter().chain(leaves.iter().filter_map(|leaf| leaf.alignment)).find(|alignment| !alignment.is_power_of_two()) {
+		return Err(InternalError::AlignmentError(format!("Alignments must be powers of two, found {alignment}")));
+	}
+
+	Ok(())
+}
+
+/// Builds the encryptor, if any leaf is to be encrypted. Fails if no key is available
+#[cfg(feature = "crypto")]
+fn build_encryptor<R>(
+	leaves: &[Leaf<R>],
+	key: Option<&crypto::EncryptionKey>,
+) -> InternalResult<Option<Encryptor>> {
+	if !leaves.iter().any(|leaf| leaf.encrypt) {
+		return Ok(None);
+	}
+
+	match key {
+		Some(key) => Ok(Some(Encryptor::new(key))),
+		None => Err(InternalError::NoKeypairError),
+	}
+}
+
+/// Decrypts the first chunk of an existing encrypted entry, so leaves aren't appended under a key the archive's entries weren't encrypted with
+#[cfg(feature = "crypto")]
+fn check_encryption_key<R: Read + Seek>(
+	mut reader: R,
+	entries: &[RegistryEntry],
+	encryptor: &Encryptor,
+) -> InternalResult {
+	let Some((entry, nonce)) = entries.iter().find_map(|entry| entry.nonce.map(|nonce| (entry, nonce))) else {
+		return Ok(());
+	};
+
+	let chunk_size = (crypto::CHUNK_SIZE + crypto::TAG_LENGTH) as u64;
+	let mut chunk = vec![0u8; entry.offset.min(chunk_size) as usize];
+	reader.seek(SeekFrom::Start(entry.location))?;
+	reader.read_exact(&mut chunk)?;
+
+	let last = entry.offset < chunk_size;
+	match encryptor.decrypt_chunk(&chunk, &nonce, 0, last, &entry.associated_data()) {
+		Ok(_) => Ok(()),
+		Err(_) => Err(InternalError::EntryAuthenticationError(entry.id.to_string())),
+	}
+}
+
+/// Processes the leaves and writes their data into the target from it's current position.
+/// Returns the serialized registry entries, and the number of bytes saved through deduplication
+fn write_leaves<W, R>(
+	target: &mut WriteCounter<W>,
+	leaves: &mut [Leaf<R>],
+	mut heads: Vec<Option<Vec<u8>>>,
+	config: &BuilderConfig,
+	encryptor: Option<&Encryptor>,
+	dictionary: Option<&[u8]>,
+	mut callback: Option<&mut dyn FnMut(&RegistryEntry)>,
+) -> InternalResult<(Vec<u8>, u64)>
+where
+	W: Write + Seek + Send,
+	R: Read + Sync + Send,
+{
+	// buffer registry data
+	let mut registry = Vec::new();
+
+	// Registers processed leaves sharing the data at `location`, several for solid blocks. Deduplicated leaves point at data written earlier
+	let mut register = |entries: &mut [RegistryEntry], location: u64| -> InternalResult<()> {
+		for entry in entries.iter_mut() {
+			entry.location = location;
+
+			// write to registry buffer, this one might include the Signature
+			let entry_bytes = entry.to_bytes()?;
+			registry.write_all(&entry_bytes)?;
+
+			// Call the progress callback bound within the [`BuilderConfig`]
+			if let Some(callback) = callback.as_mut() {
+				callback(entry);
+			}
+		}
+
+		Ok(())
+	};
+
+	// identical leaves are written once, processed leaves are fingerprinted to find them
+	let mut dedup = config.deduplicate.then(Deduplicator::default);
+	let keys = dedup.as_ref().map(|dedup| dedup.keys.clone());
+
+	// Writes a processed leaf into the target, unless identical data was already written
+	let mut commit = |processed: leaf::ProcessedLeaf, target: &mut WriteCounter<W>, register: &mut dyn FnMut(&mut [RegistryEntry], u64) -> InternalResult<()>| -> InternalResult<()> {
+		let (mut entries, mut data) = ([processed.entry], processed.data);
+
+		let original = match (dedup.as_mut(), processed.fingerprint) {
+			(Some(dedup), Some(fingerprint)) => dedup.find(fingerprint, &entries[0], &mut data)?,
+			_ => None,
+		};
+
+		let location = match original {
+			Some(location) => location,
+			None => {
+				let location = target.pad(entries[0].alignment)?;
+				io::copy(&mut data.reader()?, target)?;
+				location
+			},
+		};
+
+		register(&mut entries, location)?;
+
+		if let (Some(dedup), Some(fingerprint), None) = (dedup.as_mut(), processed.fingerprint, original) {
+			dedup.insert(fingerprint, &entries[0], &mut data)?;
+		}
+
+		Ok(())
+	};
+
+	// Processes a leaf on this thread. Deduplicated leaves are processed into spills first, and dropped if identical data was already written
+	let mut write_leaf = |leaf: &mut Leaf<R>, head: Option<Vec<u8>>, target: &mut WriteCounter<W>, register: &mut dyn FnMut(&mut [RegistryEntry], u64) -> InternalResult<()>| -> InternalResult<()> {
+		match keys.as_ref() {
+			Some(keys) => commit(leaf::process_into_spill(leaf, config, encryptor, dictionary, head, Some(keys))?, target, register),
+			None => {
+				let location = target.pad(leaf.data_alignment(config.alignment))?;
+				let entry = leaf::process_leaf(leaf, config, encryptor, dictionary, head, target)?;
+				register(&mut [entry], location)
+			},
+		}
+	};
+
+	// small leaves are grouped into solid blocks, written ahead of the rest. Candidates only found to be large once their head is read are
+	// processed right away, so their heads and handles aren't held on to
+	let mut written = vec![false; leaves.len()];
+
+	#[cfg(feature = "compression")]
+	if let Some(block_size) = config.solid_block_size {
+		let mut blocks = solid::SolidBlocks::new(block_size, config.alignment, config.min_savings);
+		let alignment = config.alignment.filter(|alignment| *alignment > 1);
+
+		#[cfg(feature = "crypto")]
+		let (sign, encrypt) = (config.signing_key.is_some(), encryptor.is_some());
+		#[cfg(not(feature = "crypto"))]
+		let (sign, encrypt) = (false, false);
+
+		for ((leaf, head), written) in leaves.iter_mut().zip(heads.iter_mut()).zip(written.iter_mut()) {
+			if !leaf.is_solid_candidate(sign, encrypt) {
+				continue;
+			}
+
+			let data = match head.take() {
+				Some(head) => head,
+				None => leaf::read_head(&mut leaf.handle)?,
+			};
+
+			*written = true;
+			if data.len() > SMALL_LEAF_SIZE {
+				write_leaf(leaf, Some(data), target, &mut register)?;
+			} else if let Some(block) = blocks.push(leaf, &data) {
+				let location = target.pad(alignment)?;
+				register(&mut block.write(target, config.checksums, dictionary)?, location)?;
+			}
+		}
+
+		for block in blocks.finish() {
+			let location = target.pad(alignment)?;
+			register(&mut block.write(target, config.checksums, dictionary)?, location)?;
+		}
+	}
+
+	if config.num_threads > 1 {
+		use std::{sync::mpsc, thread};
+
+		thread::scope(|s| -> InternalResult<()> {
+			// workers block once the queue is full, so at most a few processed leaves wait on the target at any time
+			let (tx, rx) = mpsc::sync_channel(config.num_threads);
+			let count = leaves.len();
+
+			#[rustfmt::skip]
+			// if we have an insane number of threads send leafs in chunks of 8
+			let chunk_size = if config.num_threads > count { 8 } else { count / config.num_threads.max(1) };
+
+			let chunks = leaves.chunks_mut(chunk_size).zip(heads.chunks_mut(chunk_size)).zip(written.chunks(chunk_size));
+			let keys = keys.as_ref();
+
+			// Spawn CPU threads, each processing leaves into spills
+			for ((chunk, heads), written) in chunks {
+				let queue = tx.clone();
+				let _config = config;
+
+				s.spawn(move || {
+					for ((leaf, head), _) in chunk.iter_mut().zip(heads).zip(written).filter(|(_, written)| !**written) {
+						let res = leaf::process_into_spill(leaf, _config, encryptor, dictionary, head.take(), keys);
+
+						// the receiver hangs up on errors
+						if queue.send(res).is_err() {
+							break;
+						}
+					}
+				});
+			}
+
+			drop(tx);
+
+			// Process IO, copying spills into the target as they arrive
+			for processed in rx {
+				commit(processed?, target, &mut register)?;
+			}
+
+			Ok(())
+		})?;
+	} else {
+		for ((leaf, head), _) in leaves.iter_mut().zip(heads.iter_mut()).zip(&written).filter(|(_, written)| !**written) {
+			write_leaf(leaf, head.take(), target, &mut register)?;
+		}
+	}
+
+	Ok((registry, dedup.map_or(0, |dedup| dedup.saved)))
+}
+
+/// Rewrites the recipients section of an archive built with [`BuilderConfig::recipients`], in place, leaving the leaves, registry and manifest untouched.
+/// `key` is the private key of one of the archive's current recipients, used to unwrap the encryption key before wrapping it for the new `recipients`.
+/// Once leaves are [`append`]ed after the section, a larger one is moved to the end of unsigned archives, and rejected for signed ones
+#[cfg(feature = "crypto")]
+#[cfg_attr(docsrs, doc(cfg(feature = "crypto")))]
+pub fn rewrite_recipients<T: Read + Write + Seek>(
+	mut target: T,
+	key: &crypto::RecipientSecret,
+	recipients: &[crypto::RecipientKey],
+) -> InternalResult {
+	target.seek(SeekFrom::Start(0))?;
+	let mut header = Header::from_handle(&mut target)?;
+
+	let offset = header.extensions.iter().find_map(|extension| match extension {
+		HeaderExtension::Recipients { offset } => Some(*offset),
+		_ => None,
+	});
+
+	let Some(offset) = offset else {
+		return Err(InternalError::OtherError("Archive has no recipients section".into()));
+	};
+
+	// unwrap the encryption key using our own entry
+	target.seek(SeekFrom::Start(offset))?;
+	let encryption_key = crypto::WrappedKey::open_section(&mut target, key)?;
+	let section_end = target.stream_position()?;
+
+	let wrapped = recipients.iter().map(|r| crypto::WrappedKey::wrap(&encryption_key, r)).collect::<InternalResult<Vec<_>>>()?;
+	let section = crypto::WrappedKey::section_bytes(&wrapped);
+	let end = target.seek(SeekFrom::End(0))?;
+
+	// the section is self-delimiting, so a shorter one can overwrite a longer one. A longer one only fits if nothing was appended after it
+	if offset + section.len() as u64 <= section_end || section_end == end {
+		target.seek(SeekFrom::Start(offset))?;
+		target.write_all(&section)?;
+	} else if header.has_manifest() {
+		return Err(InternalError::OtherError(
+			"The recipients section can't grow past appended data, and moving it would invalidate the archive's signature".into(),
+		));
+	} else {
+		// otherwise it's moved to the end, and the header pointed at it
+		target.write_all(&section)?;
+
+		for extension in header.extensions.iter_mut() {
+			if let HeaderExtension::Recipients { offset } = extension {
+				*offset = end;
+			}
+		}
+
+		target.seek(SeekFrom::Start(0))?;
+		target.write_all(&header.to_bytes())?;
+	}
+
+	target.flush()?;
+	Ok(())
+}
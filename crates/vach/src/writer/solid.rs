@@ -0,0 +1,135 @@
+use std::io::{Read, Write};
+
+use twox_hash::XxHash64;
+
+use super::leaf::{CompressMode, Leaf};
+use crate::global::{
+	compressor::{CompressionAlgorithm, Compressor},
+	error::*,
+	flags::Flags,
+	reg_entry::{BlockRange, RegistryEntry},
+};
+
+/// Small leaves waiting to be compressed together into a solid block
+pub(crate) struct SolidBlock {
+	algo: CompressionAlgorithm,
+	min_savings: Option<f32>,
+	data: Vec<u8>,
+	entries: Vec<RegistryEntry>,
+}
+
+impl SolidBlock {
+	/// Compresses the block into `output`, storing it as is if compression doesn't make it smaller. Returns the block's entries.
+	/// Zstd blocks are compressed against the `dictionary` if one is given, like small leaves
+	pub(crate) fn write(
+		self,
+		output: &mut dyn Write,
+		checksum: bool,
+		dictionary: Option<&[u8]>,
+	) -> InternalResult<Vec<RegistryEntry>> {
+		let mut compressed = Vec::new();
+		let mut flags = Flags::COMPRESSED_FLAG | u32::from(self.algo);
+
+		match (self.algo, dictionary) {
+			(CompressionAlgorithm::Zstd(level), Some(dictionary)) => {
+				Compressor::new(self.data.as_slice()).compress_with_dictionary(level, dictionary, &mut compressed)?;
+				flags |= Flags::DICTIONARY_COMPRESSED;
+			},
+			(algo, _) => Compressor::new(self.data.as_slice()).compress(algo, &mut compressed)?,
+		}
+
+		// blocks of `Detect` leaves must also save `min_savings` of their size, as the leaves would on their own
+		let limit = self.data.len() as f64 * (1.0 - self.min_savings.unwrap_or(0.0) as f64);
+		let (data, flags) = match compressed.len() < self.data.len() && compressed.len() as f64 <= limit {
+			true => (compressed, flags),
+			false => (self.data, 0),
+		};
+
+		output.write_all(&data)?;
+
+		// entries share their block's checksum
+		let checksum = checksum.then(|| XxHash64::oneshot(0, &data));
+
+		let mut entries = self.entries;
+		for entry in entries.iter_mut() {
+			entry.flags.force_set(flags, true);
+			entry.flags.force_set(Flags::CHECKSUM_FLAG, checksum.is_some());
+			entry.offset = data.len() as u64;
+			entry.checksum = checksum;
+		}
+
+		Ok(entries)
+	}
+}
+
+/// Solid blocks being filled, one per compression algorithm and mode
+pub(crate) struct SolidBlocks {
+	block_size: usize,
+	alignment: Option<u64>,
+	min_savings: Option<f32>,
+	open: Vec<SolidBlock>,
+}
+
+impl SolidBlocks {
+	pub(crate) fn new(
+		block_size: usize,
+		alignment: Option<u64>,
+		min_savings: Option<f32>,
+	) -> SolidBlocks {
+		SolidBlocks {
+			block_size,
+			alignment: alignment.filter(|alignment| *alignment > 1),
+			min_savings,
+			open: Vec::new(),
+		}
+	}
+
+	/// Adds a leaf's data to the open block for it's algorithm and [`CompressMode`], returning the block once it's full.
+	/// Leaves whose algorithms differ only by level share blocks, compressed at the level of the block's first leaf
+	pub(crate) fn push<R: Read + Send + Sync>(
+		&mut self,
+		leaf: &mut Leaf<R>,
+		data: &[u8],
+	) -> Option<SolidBlock> {
+		let kind = u32::from(leaf.compression_algo);
+		let min_savings = self.min_savings.filter(|_| matches!(leaf.compress, CompressMode::Detect));
+
+		let idx = match self.open.iter().position(|block| u32::from(block.algo) == kind && block.min_savings == min_savings) {
+			Some(idx) => idx,
+			None => {
+				self.open.push(SolidBlock {
+					algo: leaf.compression_algo,
+					min_savings,
+					data: Vec::with_capacity(self.block_size),
+					entries: Vec::new(),
+				});
+
+				self.open.len() - 1
+			},
+		};
+
+		let block = &mut self.open[idx];
+
+		let mut entry: RegistryEntry = leaf.into();
+		entry.flags.force_set(Flags::SOLID_FLAG, true);
+		entry.flags.force_set(Flags::ALIGNED_FLAG, self.alignment.is_some());
+		entry.alignment = self.alignment;
+		entry.block = Some(BlockRange {
+			start: block.data.len() as u64,
+			length: data.len() as u64,
+		});
+
+		block.data.extend_from_slice(data);
+		block.entries.push(entry);
+
+		match block.data.len() >= self.block_size {
+			true => Some(self.open.swap_remove(idx)),
+			false => None,
+		}
+	}
+
+	/// The blocks left open once every leaf was pushed
+	pub(crate) fn finish(self) -> Vec<SolidBlock> {
+		self.open
+	}
+}
@@ -0,0 +1,109 @@
+use std::{
+	fs::File,
+	io::{self, BufReader, Read, Seek, SeekFrom, Write},
+};
+
+/// Size after which a [`Spill`] moves it's contents out of memory and into a temporary file
+pub(crate) const SPILL_THRESHOLD: usize = 4 * 1024 * 1024;
+
+/// A write buffer that is kept in memory while small, then spills over into an anonymous temporary file.
+/// Holds processed leaves until they can be written into the target, without holding them in memory in full
+#[derive(Debug, Default)]
+pub(crate) struct Spill {
+	buffer: Vec<u8>,
+	file: Option<File>,
+	len: u64,
+}
+
+impl Spill {
+	/// The number of bytes written into the [`Spill`]
+	#[inline(always)]
+	pub(crate) fn len(&self) -> u64 {
+		self.len
+	}
+
+	/// A reader over the [`Spill`]'s contents from the start, leaving them intact to be read again
+	#[inline(always)]
+	pub(crate) fn reader(&mut self) -> io::Result<Box<dyn Read + '_>> {
+		self.reader_at(0)
+	}
+
+	/// A reader over the [`Spill`]'s contents from `offset`, leaving them intact to be read again
+	pub(crate) fn reader_at(
+		&mut self,
+		offset: u64,
+	) -> io::Result<Box<dyn Read + '_>> {
+		match self.file.as_mut() {
+			Some(file) => {
+				file.seek(SeekFrom::Start(offset))?;
+				Ok(Box::new(BufReader::new(file)))
+			},
+			None => Ok(Box::new(self.buffer.get(offset as usize..).unwrap_or_default())),
+		}
+	}
+
+	/// Consumes the [`Spill`], returning a reader over it's contents from the start
+	#[cfg(feature = "compression")]
+	pub(crate) fn into_reader(self) -> io::Result<Box<dyn Read + Send>> {
+		match self.file {
+			Some(mut file) => {
+				file.seek(SeekFrom::Start(0))?;
+				Ok(Box::new(BufReader::new(file)))
+			},
+			None => Ok(Box::new(io::Cursor::new(self.buffer))),
+		}
+	}
+}
+
+impl Write for Spill {
+	fn write(
+		&mut self,
+		buf: &[u8],
+	) -> io::Result<usize> {
+		if self.file.is_none() && self.buffer.len() + buf.len() > SPILL_THRESHOLD {
+			let mut file = tempfile::tempfile()?;
+			file.write_all(&self.buffer)?;
+
+			self.buffer = Vec::new();
+			self.file = Some(file);
+		}
+
+		// the file may have been read from since the last write
+		let written = match self.file.as_mut() {
+			Some(file) => {
+				file.seek(SeekFrom::End(0))?;
+				file.write(buf)?
+			},
+			None => self.buffer.write(buf)?,
+		};
+
+		self.len += written as u64;
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match self.file.as_mut() {
+			Some(file) => file.flush(),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Copies every byte read from `inner` into `copy`, used to keep the raw data around while compressing it
+#[cfg(feature = "compression")]
+pub(crate) struct TeeReader<'a, R> {
+	pub(crate) inner: R,
+	pub(crate) copy: &'a mut Spill,
+}
+
+#[cfg(feature = "compression")]
+impl<R: Read> Read for TeeReader<'_, R> {
+	fn read(
+		&mut self,
+		buf: &mut [u8],
+	) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.copy.write_all(&buf[..read])?;
+		Ok(read)
+	}
+}
@@ -1,7 +1,10 @@
 #![no_main]
 
 use libfuzzer_sys::{arbitrary, fuzz_target};
-use vach::{crypto_utils::gen_keypair, prelude::*};
+use vach::{
+	crypto_utils::{gen_encryption_key, gen_keypair},
+	prelude::*,
+};
 
 #[derive(arbitrary::Arbitrary, Debug, Clone)]
 struct ArbitraryVachInput {
@@ -39,6 +42,7 @@ fuzz_target!(|data: (usize, std::collections::BTreeMap<String, ArbitraryVachInpu
 
 	let generate_keypair = data.values().any(|input| input.encrypt || input.sign);
 	let keypair = generate_keypair.then(gen_keypair);
+	let encryption_key = data.values().any(|input| input.encrypt).then(gen_encryption_key);
 
 	let mut leaves = data.iter().map(|(key, input)| arbitrary_vach_input_to_leaf(key, input)).collect::<Vec<_>>();
 	let mut target = std::io::Cursor::new(Vec::new());
@@ -50,16 +54,21 @@ fuzz_target!(|data: (usize, std::collections::BTreeMap<String, ArbitraryVachInpu
 			num_threads: builder_threads % 8,
 			flags: Flags::default(),
 			signing_key: keypair.clone(),
+			encryption_key,
+			..Default::default()
 		}),
 		None,
 	)
 	.unwrap();
 
-	let mut archive = match keypair {
-		Some(kp) => Archive::with_key(target, &kp.verifying_key()).unwrap(),
-		None => Archive::new(target).unwrap(),
+	let config = ArchiveConfig {
+		verifying_key: keypair.map(|kp| kp.verifying_key()),
+		decryption_key: encryption_key,
+		..Default::default()
 	};
 
+	let mut archive = Archive::with_config(target, config).unwrap();
+
 	let ids = archive.entries().keys().cloned().collect::<Vec<_>>();
 	assert_eq!(ids.len(), leaves.len());
 